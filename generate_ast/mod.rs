@@ -14,11 +14,14 @@ pub fn generate_ast(output_dir: &str) -> io::Result<()> {
     output_dir,
     "Expr",
     &["error", "token", "object", "rc"],
+    "",
     &[
       "Assign    : Token name, Rc<Expr> value",
       "Binary    : Rc<Expr> left, Token operator, Rc<Expr> right",
-      "Call      : Rc<Expr> callee, Token paren, Vec<Rc<Expr>> arguments",
+      "Call      : Rc<Expr> callee, Token paren, Vec<Rc<Expr>> arguments, bool optional",
+      "Get       : Rc<Expr> object, Token name, bool optional",
       "Grouping  : Rc<Expr> expression",
+      "List      : Token bracket, Vec<Rc<Expr>> elements",
       "Literal   : Option<Object> value",
       "Logical   : Rc<Expr> left, Token operator, Rc<Expr> right",
       "Unary     : Token operator, Rc<Expr> right",
@@ -29,17 +32,24 @@ pub fn generate_ast(output_dir: &str) -> io::Result<()> {
   define_ast(
     output_dir,
     "Stmt",
-    &["error", "token", "expr", "rc"],
+    &["error", "token", "expr", "pattern", "saturday_enum", "types", "rc"],
+    ": ExprRewriter",
     &[
+      "Assert     : Token keyword, Rc<Expr> condition, Option<Rc<Expr>> message, String source",
       "Block      : Rc<Vec<Rc<Stmt>>> statements",
       "Break      : Token token",
+      "Enum       : Token name, Rc<Vec<VariantDecl>> variants",
       "Expression : Rc<Expr> expression",
-      "Function   : Token name, Rc<Vec<Token>> params, Rc<Vec<Rc<Stmt>>> body",
+      "ForIn      : Token name, Rc<Expr> iterable, Rc<Stmt> body",
+      "Function   : Token name, Rc<Vec<Token>> params, Rc<Vec<Rc<Stmt>>> body, bool generator, Rc<Vec<Option<TypeAnnotation>>> param_types, Option<TypeAnnotation> return_type",
       "If         : Rc<Expr> condition, Rc<Stmt> then_branch, Option<Rc<Stmt>> else_branch",
+      "Match      : Token keyword, Rc<Expr> subject, Rc<Vec<MatchArm>> arms",
       "Print      : Rc<Expr> expression",
+      "Record     : Token name, Rc<Vec<Token>> fields",
       "Return     : Token keyword, Option<Rc<Expr>> value",
-      "Def        : Token name, Option<Rc<Expr>> initializer",
+      "Def        : Token name, Option<Rc<Expr>> initializer, Option<TypeAnnotation> annotation",
       "While      : Rc<Expr> condition, Rc<Stmt> body",
+      "Yield      : Token keyword, Option<Rc<Expr>> value",
     ],
   )?;
   Ok(())
@@ -49,6 +59,7 @@ fn define_ast(
   output_dir: &str,
   base_name: &str,
   imports: &[&str],
+  rewriter_bounds: &str,
   types: &[&str],
 ) -> io::Result<()> {
   let path = format!("{output_dir}/{}.rs", base_name.to_lowercase());
@@ -154,6 +165,8 @@ fn define_ast(
     )?;
   }
   writeln!(file, "}}\n")?;
+
+  define_rewriter(&mut file, base_name, rewriter_bounds, &tree_types)?;
   /*
    for t in &tree_types {
      writeln!(file, "impl {} {{", t.class_name)?;
@@ -174,3 +187,165 @@ fn define_ast(
   */
   Ok(())
 }
+
+/// # 改写器
+/// ```
+/// 生成{Base}Rewriter：rewrite_x返回替换后的节点，默认调用walk_x。
+/// walk_x改写所有子节点，只有子节点变化时才创建新节点，否则返回原来的wrapper，
+/// 这样resolver以节点为键记录的信息仍然有效；创建新节点时调用replaced_x通知实现者
+/// ```
+fn define_rewriter(
+  file: &mut File,
+  base_name: &str,
+  bounds: &str,
+  tree_types: &[TreeType],
+) -> io::Result<()> {
+  let lower = base_name.to_lowercase();
+  writeln!(file, "pub trait {base_name}Rewriter{bounds} {{")?;
+  writeln!(
+    file,
+    "  fn rewrite_{lower}(&self, {lower}: Rc<{base_name}>) -> Result<Rc<{base_name}>, SaturdayResult> {{"
+  )?;
+  writeln!(file, "    match &*{lower} {{")?;
+  for t in tree_types {
+    writeln!(
+      file,
+      "      {base_name}::{0}(v) => self.rewrite_{1}_{lower}({lower}.clone(), v),",
+      t.base_class_name,
+      t.base_class_name.to_lowercase()
+    )?;
+  }
+  writeln!(file, "    }}")?;
+  writeln!(file, "  }}\n")?;
+  writeln!(
+    file,
+    "  fn replaced_{lower}(&self, _old: &Rc<{base_name}>, _new: &Rc<{base_name}>) {{}}\n"
+  )?;
+
+  for t in tree_types {
+    let name = t.base_class_name.to_lowercase();
+    writeln!(
+      file,
+      "  fn rewrite_{name}_{lower}(&self, wrapper: Rc<{base_name}>, {lower}: &{0}) -> Result<Rc<{base_name}>, SaturdayResult> {{",
+      t.class_name
+    )?;
+    writeln!(file, "    self.walk_{name}_{lower}(wrapper, {lower})")?;
+    writeln!(file, "  }}\n")?;
+
+    // 没有子节点的节点原样返回
+    if t
+      .fields
+      .iter()
+      .all(|f| rewritten(f.split_once(": ").unwrap().1).is_none())
+    {
+      writeln!(
+        file,
+        "  fn walk_{name}_{lower}(&self, wrapper: Rc<{base_name}>, _: &{0}) -> Result<Rc<{base_name}>, SaturdayResult> {{",
+        t.class_name
+      )?;
+      writeln!(file, "    Ok(wrapper)")?;
+      writeln!(file, "  }}\n")?;
+      continue;
+    }
+
+    writeln!(
+      file,
+      "  fn walk_{name}_{lower}(&self, wrapper: Rc<{base_name}>, {lower}: &{0}) -> Result<Rc<{base_name}>, SaturdayResult> {{",
+      t.class_name
+    )?;
+    writeln!(file, "    let mut changed = false;")?;
+    let mut names = Vec::new();
+    for f in &t.fields {
+      let (field, ty) = f.split_once(": ").unwrap();
+      names.push(field.to_string());
+      let old = format!("{lower}.{field}");
+      match rewritten(ty) {
+        Some((Shape::Single, inner)) => {
+          writeln!(
+            file,
+            "    let {field} = self.rewrite_{inner}({old}.clone())?;"
+          )?;
+          writeln!(file, "    changed |= !Rc::ptr_eq(&{field}, &{old});")?;
+        }
+        Some((Shape::Optional, inner)) => {
+          writeln!(
+            file,
+            "    let {field} = match &{old} {{ Some(v) => Some(self.rewrite_{inner}(v.clone())?), None => None }};"
+          )?;
+          writeln!(
+            file,
+            "    changed |= matches!((&{field}, &{old}), (Some(a), Some(b)) if !Rc::ptr_eq(a, b));"
+          )?;
+        }
+        Some((shape, inner)) => {
+          writeln!(
+            file,
+            "    let {field}: Vec<Rc<{0}>> = {old}.iter().map(|v| self.rewrite_{inner}(v.clone())).collect::<Result<_, _>>()?;",
+            capitalize(inner)
+          )?;
+          writeln!(
+            file,
+            "    changed |= {field}.iter().zip({old}.iter()).any(|(a, b)| !Rc::ptr_eq(a, b));"
+          )?;
+          if shape == Shape::SharedList {
+            writeln!(file, "    let {field} = Rc::new({field});")?;
+          }
+        }
+        None if ty == "bool" => writeln!(file, "    let {field} = {old};")?,
+        None => writeln!(file, "    let {field} = {old}.clone();")?,
+      }
+    }
+    writeln!(file, "    if !changed {{")?;
+    writeln!(file, "      return Ok(wrapper);")?;
+    writeln!(file, "    }}")?;
+    writeln!(
+      file,
+      "    let new = Rc::new({base_name}::{0}(Rc::new({1} {{ {2} }})));",
+      t.base_class_name,
+      t.class_name,
+      names.join(", ")
+    )?;
+    writeln!(file, "    self.replaced_{lower}(&wrapper, &new);")?;
+    writeln!(file, "    Ok(new)")?;
+    writeln!(file, "  }}\n")?;
+  }
+  writeln!(file, "}}\n")?;
+  Ok(())
+}
+
+#[derive(PartialEq)]
+enum Shape {
+  /// Rc<Expr>
+  Single,
+  /// Option<Rc<Expr>>
+  Optional,
+  /// Vec<Rc<Expr>>
+  List,
+  /// Rc<Vec<Rc<Stmt>>>
+  SharedList,
+}
+
+/// 需要递归改写的字段类型，返回形状和节点类型的小写名
+fn rewritten(ty: &str) -> Option<(Shape, &'static str)> {
+  for inner in ["expr", "stmt"] {
+    let node = format!("Rc<{}>", capitalize(inner));
+    if ty == node {
+      return Some((Shape::Single, inner));
+    } else if ty == format!("Option<{node}>") {
+      return Some((Shape::Optional, inner));
+    } else if ty == format!("Vec<{node}>") {
+      return Some((Shape::List, inner));
+    } else if ty == format!("Rc<Vec<{node}>>") {
+      return Some((Shape::SharedList, inner));
+    }
+  }
+  None
+}
+
+fn capitalize(name: &str) -> String {
+  let mut chars = name.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
@@ -22,7 +22,7 @@ impl AstPrinter {
 
 impl ExprVisitor<String> for AstPrinter {
   fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<String, SaturdayError> {
-    self.parenthesize(&expr.operator.lexeme, &[&expr.left, &expr.right])
+    self.parenthesize(&expr.operator.lexeme(), &[&expr.left, &expr.right])
   }
 
   fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<String, SaturdayError> {
@@ -38,7 +38,7 @@ impl ExprVisitor<String> for AstPrinter {
   }
 
   fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<String, SaturdayError> {
-    self.parenthesize(&expr.operator.lexeme, &[&expr.right])
+    self.parenthesize(&expr.operator.lexeme(), &[&expr.right])
   }
 
   fn visit_variable_expr(&self, expr: &VariableExpr) -> Result<String, SaturdayError> {
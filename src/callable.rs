@@ -1,4 +1,9 @@
+use crate::fiber::FiberOp;
 use crate::object::Object;
+use crate::saturday_function::SaturdayFunction;
+use crate::saturday_record::RecordType;
+use crate::token::Token;
+use crate::vm::Closure;
 use crate::Interpreter;
 use crate::SaturdayResult;
 use std::fmt::{Debug, Display, Formatter};
@@ -32,22 +37,50 @@ impl PartialEq for Callable {
 }
 
 pub trait SaturdayCallable {
+  /// paren是调用处的token，内置函数用它报告参数错误
   fn call(
     &self,
     interpreter: &Interpreter,
+    paren: &Token,
     arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult>;
   fn arity(&self) -> usize;
   fn to_string(&self) -> String;
+
+  /// 可变参数的函数，arity是最少需要的参数个数
+  fn variadic(&self) -> bool {
+    false
+  }
+
+  /// 在协程中作为语句调用时可以挂起协程的内置函数
+  fn fiber_op(&self) -> Option<FiberOp> {
+    None
+  }
+
+  /// 用户定义的函数，spawn需要用它的函数体创建协程
+  fn as_function(&self) -> Option<&SaturdayFunction> {
+    None
+  }
+
+  /// 编译成字节码的函数，由VM压入新的调用帧执行
+  fn as_closure(&self) -> Option<&Closure> {
+    None
+  }
+
+  /// record的构造函数，record模式用它取得要匹配的类型
+  fn as_record(&self) -> Option<&Rc<RecordType>> {
+    None
+  }
 }
 
 impl SaturdayCallable for Callable {
   fn call(
     &self,
     interpreter: &Interpreter,
+    paren: &Token,
     arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult> {
-    self.func.call(interpreter, arguments)
+    self.func.call(interpreter, paren, arguments)
   }
 
   fn arity(&self) -> usize {
@@ -57,4 +90,24 @@ impl SaturdayCallable for Callable {
   fn to_string(&self) -> String {
     self.func.to_string()
   }
+
+  fn variadic(&self) -> bool {
+    self.func.variadic()
+  }
+
+  fn fiber_op(&self) -> Option<FiberOp> {
+    self.func.fiber_op()
+  }
+
+  fn as_function(&self) -> Option<&SaturdayFunction> {
+    self.func.as_function()
+  }
+
+  fn as_closure(&self) -> Option<&Closure> {
+    self.func.as_closure()
+  }
+
+  fn as_record(&self) -> Option<&Rc<RecordType>> {
+    self.func.as_record()
+  }
 }
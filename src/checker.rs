@@ -0,0 +1,610 @@
+use crate::error::SaturdayResult;
+use crate::expr::{
+  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, ListExpr,
+  LiteralExpr, LogicalExpr, UnaryExpr, VariableExpr,
+};
+use crate::object::Object;
+use crate::stmt::{
+  AssertStmt, BlockStmt, BreakStmt, DefStmt, EnumStmt, ExpressionStmt, ForInStmt, FunctionStmt,
+  IfStmt, MatchStmt, PrintStmt, RecordStmt, ReturnStmt, Stmt, StmtVisitor, WhileStmt, YieldStmt,
+};
+use crate::token::Token;
+use crate::token_type::TokenType;
+use crate::types::{Signature, Type, TypeAnnotation};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::rc::Rc;
+
+struct Binding {
+  ty: Type,
+  /// 有标注的变量赋值时要检查类型，没有标注的变量按初始值推断
+  annotated: bool,
+  /// 声明处名字token的地址，两遍检查之间用它识别同一个变量
+  decl: usize,
+}
+
+/// # 静态类型检查
+/// ```
+/// 在Resolver之后、执行之前运行。有标注的地方不匹配是错误；
+/// 没有标注的变量按初始值推断类型，只要在任何地方被赋了别的类型就按Any处理，
+/// 推断出的必然失败的运算和调用报告为警告，strict模式下为错误
+/// ```
+pub struct Checker {
+  scopes: RefCell<Vec<HashMap<String, Binding>>>,
+  had_error: RefCell<bool>,
+  /// 当前函数的名字和声明的返回类型
+  current_function: RefCell<Option<(String, Type)>>,
+  strict: bool,
+  /// 第一遍只收集被赋过不同类型的变量，不报告任何问题
+  collecting: RefCell<bool>,
+  widened: RefCell<HashSet<usize>>,
+  /// 在声明之前就被赋值的名字（比如函数里给后面定义的全局变量赋值）
+  widened_globals: RefCell<HashSet<String>>,
+}
+
+impl Checker {
+  pub fn new() -> Self {
+    Self {
+      scopes: RefCell::new(vec![HashMap::new()]),
+      had_error: RefCell::new(false),
+      current_function: RefCell::new(None),
+      strict: false,
+      collecting: RefCell::new(false),
+      widened: RefCell::new(HashSet::new()),
+      widened_globals: RefCell::new(HashSet::new()),
+    }
+  }
+
+  pub fn set_strict(&mut self, strict: bool) {
+    self.strict = strict;
+  }
+
+  pub fn check(&self, statements: &Rc<Vec<Rc<Stmt>>>) -> Result<(), SaturdayResult> {
+    self.collecting.replace(true);
+    self.check_all(statements)?;
+    self.collecting.replace(false);
+    self.scopes.replace(vec![HashMap::new()]);
+    self.check_all(statements)
+  }
+
+  fn check_all(&self, statements: &Rc<Vec<Rc<Stmt>>>) -> Result<(), SaturdayResult> {
+    for statement in statements.deref() {
+      self.check_stmt(statement.clone())?;
+    }
+
+    Ok(())
+  }
+
+  pub fn success(&self) -> bool {
+    !*self.had_error.borrow()
+  }
+
+  fn check_stmt(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
+    stmt.accept(stmt.clone(), self)
+  }
+
+  fn check_expr(&self, expr: Rc<Expr>) -> Result<Type, SaturdayResult> {
+    expr.accept(expr.clone(), self)
+  }
+
+  fn begin_scope(&self) {
+    self.scopes.borrow_mut().push(HashMap::new());
+  }
+
+  fn end_scope(&self) {
+    self.scopes.borrow_mut().pop();
+  }
+
+  fn declare(&self, name: &Token, ty: Type, annotated: bool) {
+    let decl = name as *const Token as usize;
+    let mut scopes = self.scopes.borrow_mut();
+    let widened = !annotated
+      && (self.widened.borrow().contains(&decl)
+        || (scopes.len() == 1 && self.widened_globals.borrow().contains(&name.as_string())));
+    let ty = if widened { Type::Any } else { ty };
+
+    if let Some(scope) = scopes.last_mut() {
+      scope.insert(
+        name.as_string(),
+        Binding {
+          ty,
+          annotated,
+          decl,
+        },
+      );
+    }
+  }
+
+  fn look_up(&self, name: &Token) -> Type {
+    let scopes = self.scopes.borrow();
+    scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.get(&name.as_string()))
+      .map(|binding| binding.ty.clone())
+      .unwrap_or(Type::Any)
+  }
+
+  fn annotated(annotation: &Option<TypeAnnotation>) -> Type {
+    annotation
+      .as_ref()
+      .map(|a| a.ty.clone())
+      .unwrap_or(Type::Any)
+  }
+
+  fn signature(function: &FunctionStmt) -> Rc<Signature> {
+    let ret = if function.generator {
+      Type::Any
+    } else {
+      Self::annotated(&function.return_type)
+    };
+
+    Rc::new(Signature {
+      params: function.param_types.iter().map(Self::annotated).collect(),
+      ret,
+    })
+  }
+
+  fn error(&self, token: &Token, message: &str) {
+    if *self.collecting.borrow() {
+      return;
+    }
+
+    self.had_error.replace(true);
+    SaturdayResult::parse_error(token, message);
+  }
+
+  /// 推断出的问题，strict模式下按错误处理
+  fn warn(&self, token: &Token, message: &str) {
+    if self.strict {
+      self.error(token, message);
+    } else if !*self.collecting.borrow() {
+      SaturdayResult::warning(token, message);
+    }
+  }
+}
+
+impl StmtVisitor<()> for Checker {
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.condition.clone())?;
+    if let Some(message) = stmt.message.clone() {
+      self.check_expr(message)?;
+    }
+
+    Ok(())
+  }
+
+  fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<(), SaturdayResult> {
+    self.begin_scope();
+    self.check_all(&stmt.statements)?;
+    self.end_scope();
+    Ok(())
+  }
+
+  fn visit_break_stmt(&self, _: Rc<Stmt>, _: &BreakStmt) -> Result<(), SaturdayResult> {
+    Ok(())
+  }
+
+  fn visit_enum_stmt(&self, _: Rc<Stmt>, stmt: &EnumStmt) -> Result<(), SaturdayResult> {
+    self.declare(&stmt.name, Type::Any, false);
+    Ok(())
+  }
+
+  fn visit_record_stmt(&self, _: Rc<Stmt>, stmt: &RecordStmt) -> Result<(), SaturdayResult> {
+    self.declare(&stmt.name, Type::Any, false);
+    Ok(())
+  }
+
+  fn visit_expression_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &ExpressionStmt,
+  ) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.expression.clone())?;
+    Ok(())
+  }
+
+  fn visit_forin_stmt(&self, _: Rc<Stmt>, stmt: &ForInStmt) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.iterable.clone())?;
+    self.begin_scope();
+    self.declare(&stmt.name, Type::Any, false);
+    self.check_stmt(stmt.body.clone())?;
+    self.end_scope();
+    Ok(())
+  }
+
+  fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
+    let signature = Self::signature(stmt);
+    self.declare(&stmt.name, Type::Fun(Some(signature.clone())), false);
+
+    let ret = if stmt.generator {
+      None
+    } else {
+      Some((stmt.name.as_string(), signature.ret.clone()))
+    };
+    let enclosing_function = self.current_function.replace(ret);
+    self.begin_scope();
+    for (param, annotation) in stmt.params.iter().zip(stmt.param_types.iter()) {
+      self.declare(param, Self::annotated(annotation), annotation.is_some());
+    }
+    self.check_all(&stmt.body)?;
+    self.end_scope();
+    self.current_function.replace(enclosing_function);
+
+    Ok(())
+  }
+
+  fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.condition.clone())?;
+    self.check_stmt(stmt.then_branch.clone())?;
+    if let Some(else_branch) = stmt.else_branch.clone() {
+      self.check_stmt(else_branch)?;
+    }
+
+    Ok(())
+  }
+
+  fn visit_match_stmt(&self, _: Rc<Stmt>, stmt: &MatchStmt) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.subject.clone())?;
+    for arm in stmt.arms.iter() {
+      self.begin_scope();
+      for name in arm.pattern.bindings() {
+        self.declare(name, Type::Any, false);
+      }
+      if let Some(guard) = &arm.guard {
+        self.check_expr(guard.clone())?;
+      }
+      self.check_stmt(arm.body.clone())?;
+      self.end_scope();
+    }
+
+    Ok(())
+  }
+
+  fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.expression.clone())?;
+    Ok(())
+  }
+
+  fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), SaturdayResult> {
+    let actual = match stmt.value.clone() {
+      Some(value) => self.check_expr(value)?,
+      None => Type::Nil,
+    };
+
+    if let Some((name, expected)) = &*self.current_function.borrow() {
+      if !expected.accepts(&actual) {
+        self.error(
+          &stmt.keyword,
+          &format!("Function '{name}' returns {expected}, got {actual}."),
+        );
+      }
+    }
+
+    Ok(())
+  }
+
+  fn visit_def_stmt(&self, _: Rc<Stmt>, stmt: &DefStmt) -> Result<(), SaturdayResult> {
+    let actual = match stmt.initializer.clone() {
+      Some(initializer) => Some(self.check_expr(initializer)?),
+      None => None,
+    };
+
+    match &stmt.annotation {
+      Some(annotation) => {
+        if let Some(actual) = actual.filter(|actual| !annotation.ty.accepts(actual)) {
+          self.error(
+            &stmt.name,
+            &format!(
+              "Can't initialize '{}' declared as {} with {actual}.",
+              stmt.name.as_string(),
+              annotation.ty
+            ),
+          );
+        }
+        self.declare(&stmt.name, annotation.ty.clone(), true);
+      }
+      None => self.declare(&stmt.name, actual.unwrap_or(Type::Nil), false),
+    }
+
+    Ok(())
+  }
+
+  fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), SaturdayResult> {
+    self.check_expr(stmt.condition.clone())?;
+    self.check_stmt(stmt.body.clone())?;
+    Ok(())
+  }
+
+  fn visit_yield_stmt(&self, _: Rc<Stmt>, stmt: &YieldStmt) -> Result<(), SaturdayResult> {
+    if let Some(value) = stmt.value.clone() {
+      self.check_expr(value)?;
+    }
+
+    Ok(())
+  }
+}
+
+impl ExprVisitor<Type> for Checker {
+  fn visit_assign_expr(&self, _: Rc<Expr>, expr: &AssignExpr) -> Result<Type, SaturdayResult> {
+    let actual = self.check_expr(expr.value.clone())?;
+    let name = expr.name.as_string();
+
+    let mut scopes = self.scopes.borrow_mut();
+    if let Some(binding) = scopes
+      .iter_mut()
+      .rev()
+      .find_map(|scope| scope.get_mut(&name))
+    {
+      if binding.annotated {
+        if !binding.ty.accepts(&actual) {
+          let message = format!(
+            "Can't assign {actual} to '{name}' declared as {}.",
+            binding.ty
+          );
+          drop(scopes);
+          self.error(&expr.name, &message);
+        }
+      } else if binding.ty != actual {
+        binding.ty = Type::Any;
+        self.widened.borrow_mut().insert(binding.decl);
+      }
+    } else {
+      self.widened_globals.borrow_mut().insert(name);
+    }
+
+    Ok(actual)
+  }
+
+  fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<Type, SaturdayResult> {
+    let left = self.check_expr(expr.left.clone())?;
+    let right = self.check_expr(expr.right.clone())?;
+    let known = left != Type::Any && right != Type::Any;
+    let both_num = left == Type::Num && right == Type::Num;
+
+    // 与Interpreter::visit_binary_expr中会得到ArithmeticError的组合保持一致
+    let (legal, result) = match expr.operator.token_type() {
+      TokenType::Minus | TokenType::Slash | TokenType::Star => (both_num, Type::Num),
+      TokenType::Plus => match (&left, &right) {
+        (Type::Num, Type::Num) => (true, Type::Num),
+        (Type::Str, Type::Str | Type::Num) | (Type::Num, Type::Str) => (true, Type::Str),
+        _ => (false, Type::Any),
+      },
+      TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+        (both_num, Type::Bool)
+      }
+      TokenType::Equal | TokenType::BangEqual => (true, Type::Bool),
+      TokenType::Ampersand
+      | TokenType::Pipe
+      | TokenType::Caret
+      | TokenType::LessLess
+      | TokenType::GreaterGreater => (both_num, Type::Num),
+      _ => (true, Type::Any),
+    };
+
+    if !known {
+      return Ok(if legal { result } else { Type::Any });
+    }
+
+    if !legal {
+      self.warn(
+        &expr.operator,
+        &format!(
+          "Operator '{}' can't be applied to {left} and {right}.",
+          expr.operator.as_string()
+        ),
+      );
+      return Ok(Type::Any);
+    }
+
+    Ok(result)
+  }
+
+  fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Type, SaturdayResult> {
+    let callee = self.check_expr(expr.callee.clone())?;
+    let mut arguments = Vec::new();
+    for argument in expr.arguments.iter() {
+      arguments.push(self.check_expr(argument.clone())?);
+    }
+
+    let signature = match callee {
+      Type::Fun(Some(signature)) => signature,
+      Type::Nil if expr.optional => return Ok(Type::Nil),
+      Type::Num | Type::Str | Type::Bool | Type::Nil => {
+        self.warn(
+          &expr.paren,
+          &format!("Can only call functions, got {callee}."),
+        );
+        return Ok(Type::Any);
+      }
+      _ => return Ok(Type::Any),
+    };
+
+    if signature.params.len() != arguments.len() {
+      self.warn(
+        &expr.paren,
+        &format!(
+          "Expected {} arguments but got {}.",
+          signature.params.len(),
+          arguments.len()
+        ),
+      );
+    } else {
+      let name = match &*expr.callee {
+        Expr::Variable(v) => v.name.as_string(),
+        _ => String::from("function"),
+      };
+      for (i, (expected, actual)) in signature.params.iter().zip(arguments.iter()).enumerate() {
+        if !expected.accepts(actual) {
+          self.error(
+            &expr.paren,
+            &format!(
+              "Argument {} of '{name}' expects {expected}, got {actual}.",
+              i + 1
+            ),
+          );
+        }
+      }
+    }
+
+    Ok(signature.ret.clone())
+  }
+
+  fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<Type, SaturdayResult> {
+    self.check_expr(expr.object.clone())?;
+    Ok(Type::Any)
+  }
+
+  fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<Type, SaturdayResult> {
+    self.check_expr(expr.expression.clone())
+  }
+
+  fn visit_list_expr(&self, _: Rc<Expr>, expr: &ListExpr) -> Result<Type, SaturdayResult> {
+    for element in &expr.elements {
+      self.check_expr(element.clone())?;
+    }
+    Ok(Type::Any)
+  }
+
+  fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<Type, SaturdayResult> {
+    Ok(match &expr.value {
+      Some(Object::Num(_)) => Type::Num,
+      Some(Object::Str(_)) => Type::Str,
+      Some(Object::Bool(_)) => Type::Bool,
+      Some(Object::Nil) | None => Type::Nil,
+      _ => Type::Any,
+    })
+  }
+
+  fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<Type, SaturdayResult> {
+    let left = self.check_expr(expr.left.clone())?;
+    let right = self.check_expr(expr.right.clone())?;
+
+    Ok(if expr.operator.is(TokenType::QuestionQuestion) {
+      match left {
+        Type::Nil => right,
+        Type::Any => Type::Any,
+        left => left,
+      }
+    } else if left == right {
+      left
+    } else {
+      Type::Any
+    })
+  }
+
+  fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<Type, SaturdayResult> {
+    let right = self.check_expr(expr.right.clone())?;
+    Ok(match expr.operator.token_type() {
+      TokenType::Minus if right == Type::Num => Type::Num,
+      TokenType::Bang => Type::Bool,
+      TokenType::Tilde => {
+        if right != Type::Num && right != Type::Any {
+          self.warn(
+            &expr.operator,
+            &format!("Operator '~' can't be applied to {right}."),
+          );
+        }
+        Type::Num
+      }
+      _ => Type::Any,
+    })
+  }
+
+  fn visit_variable_expr(&self, _: Rc<Expr>, expr: &VariableExpr) -> Result<Type, SaturdayResult> {
+    Ok(self.look_up(&expr.name))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Parser;
+  use crate::scanner::Scanner;
+
+  fn check_with(source: &str, strict: bool) -> bool {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let mut checker = Checker::new();
+    checker.set_strict(strict);
+    assert!(checker.check(&statements).is_ok());
+    checker.success()
+  }
+
+  fn check(source: &str) -> bool {
+    check_with(source, false)
+  }
+
+  fn check_strict(source: &str) -> bool {
+    check_with(source, true)
+  }
+
+  #[test]
+  fn unannotated_code_is_dynamic() {
+    assert!(check(
+      "def x = 1; x = \"one\";
+       fun add(a, b) { return a + b; }
+       add(\"a\", 2);"
+    ));
+  }
+
+  #[test]
+  fn annotated_code_that_agrees() {
+    assert!(check(
+      "fun add(a: num, b: num) -> num { return a + b; }
+       def total: num = add(1, 2);
+       def label: str = \"total \" + total;
+       def anything: any = nil;"
+    ));
+  }
+
+  #[test]
+  fn argument_mismatch() {
+    assert!(!check(
+      "fun add(a: num, b: num) -> num { return a + b; }
+       add(\"1\", 2);"
+    ));
+  }
+
+  #[test]
+  fn initializer_and_assignment_mismatch() {
+    assert!(!check("def x: str = 1;"));
+    assert!(!check("def x: num = 1; x = true;"));
+  }
+
+  #[test]
+  fn return_mismatch() {
+    assert!(!check("fun f() -> num { return \"s\"; }"));
+    assert!(!check("fun f() -> str { return; }"));
+  }
+
+  #[test]
+  fn inferred_errors_are_warnings_unless_strict() {
+    let source = "def s = \"a\"; print s - 1;";
+    assert!(check(source));
+    assert!(!check_strict(source));
+  }
+
+  #[test]
+  fn calls_to_non_callables_and_wrong_arity() {
+    assert!(!check_strict("def n = 1; n();"));
+    assert!(!check_strict("fun f(a) {} f(1, 2);"));
+    assert!(check_strict("def n = nil; n?.();"));
+  }
+
+  #[test]
+  fn reassigned_variables_are_dynamic() {
+    assert!(check_strict(
+      "def x = \"a\";
+       while x != 1 { print x - 1; x = 1; }"
+    ));
+    assert!(check_strict(
+      "fun set() { y = 1; }
+       def y = \"a\";
+       set();
+       print y - 1;"
+    ));
+  }
+}
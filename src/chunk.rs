@@ -0,0 +1,102 @@
+use crate::object::Object;
+use crate::stmt::{AssertStmt, EnumStmt, MatchStmt, RecordStmt};
+use crate::token::Token;
+use std::rc::Rc;
+
+/// # 字节码指令
+/// ```
+/// 操作数都是u16：常量、token、全局变量等表中的下标，局部变量的栈槽，
+/// 或者跳转的相对偏移。需要报错的位置通过token下标取回行号
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+  Constant(u16),
+  Nil,
+  True,
+  False,
+  Pop,
+
+  GetLocal(u16),
+  SetLocal(u16),
+  /// 全局变量名字的token，和解释器共用同一个全局环境，未定义时用token报错
+  GetGlobal(u16),
+  DefineGlobal(u16),
+  SetGlobal(u16),
+  GetUpvalue(u16),
+  SetUpvalue(u16),
+  /// 属性访问，参数是名字的token
+  Get(u16),
+
+  /// 二元和一元运算，参数是运算符的token
+  Binary(u16),
+  Unary(u16),
+
+  Jump(u16),
+  JumpIfFalse(u16),
+  /// ??：左边不是nil时跳过右边
+  JumpIfNotNil(u16),
+  /// ?.：左边为nil时跳到整条可选链的末尾，结果为nil
+  JumpIfNil(u16),
+  Loop(u16),
+
+  /// 参数个数和调用处的token
+  Call(u8, u16),
+  /// return语句中的调用，被调用的是闭包时复用当前的调用帧，否则和Call相同
+  TailCall(u8, u16),
+  /// 创建闭包，参数是functions表的下标
+  Closure(u16),
+  CloseUpvalue,
+  Return,
+
+  Print,
+  /// 语句开始处计一步执行预算，和解释器每执行一条语句计一步对应
+  Fuel,
+  /// 定义枚举，参数是enums表的下标
+  Enum(u16),
+  /// 定义record，参数是records表的下标
+  Record(u16),
+  /// 用栈顶的若干个值创建列表
+  List(u16),
+  /// 匹配arms表中的模式，栈顶是模式中引用的枚举和record类型，匹配前先弹出。
+  /// 成功时按顺序压入绑定的值，失败时跳过偏移量
+  MatchArm(u16, u16),
+  /// assert失败，参数是asserts表的下标；栈上依次是（两个操作数、）附加消息
+  AssertFail(u16),
+}
+
+/// 闭包捕获的变量：外层函数的局部变量槽，或者外层函数自己的upvalue
+#[derive(Clone, Copy)]
+pub struct UpvalueDesc {
+  pub is_local: bool,
+  pub index: u16,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+  pub code: Vec<Op>,
+  pub constants: Vec<Object>,
+  pub tokens: Vec<Token>,
+  pub functions: Vec<Rc<Function>>,
+  pub enums: Vec<Rc<EnumStmt>>,
+  pub records: Vec<Rc<RecordStmt>>,
+  pub arms: Vec<(Rc<MatchStmt>, usize)>,
+  pub asserts: Vec<(Rc<AssertStmt>, bool)>,
+  /// 每条指令对应的源码行
+  pub lines: Vec<usize>,
+}
+
+impl Chunk {
+  pub fn write(&mut self, op: Op, line: usize) -> usize {
+    self.code.push(op);
+    self.lines.push(line);
+    self.code.len() - 1
+  }
+}
+
+/// 编译后的函数，运行时和捕获的upvalue一起组成闭包
+pub struct Function {
+  pub name: String,
+  pub arity: usize,
+  pub chunk: Chunk,
+  pub upvalues: Vec<UpvalueDesc>,
+}
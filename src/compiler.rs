@@ -0,0 +1,740 @@
+use crate::chunk::{Chunk, Function, Op, UpvalueDesc};
+use crate::error::SaturdayResult;
+use crate::expr::{
+  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, ListExpr,
+  LiteralExpr, LogicalExpr, UnaryExpr, VariableExpr,
+};
+use crate::object::Object;
+use crate::stmt::{
+  AssertStmt, BlockStmt, BreakStmt, DefStmt, EnumStmt, ExpressionStmt, ForInStmt, FunctionStmt,
+  IfStmt, MatchStmt, PrintStmt, RecordStmt, ReturnStmt, Stmt, StmtVisitor, WhileStmt, YieldStmt,
+};
+use crate::token::Token;
+use crate::token_type::TokenType;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// # 字节码编译器
+/// ```
+/// 在resolver和checker之后运行，把语法树编译成VM执行的字节码。
+/// 局部变量放在栈槽中，被闭包捕获的变量通过upvalue访问，全局变量按名字存取解释器的全局环境。
+/// 生成器和for-in还不能编译，遇到时报编译错误，不会悄悄换成解释器执行
+/// ```
+pub struct Compiler {
+  strip_asserts: bool,
+  /// 正在编译的函数，最后一个是最内层的
+  states: RefCell<Vec<FunctionState>>,
+  line: Cell<usize>,
+}
+
+struct Local {
+  name: String,
+  depth: usize,
+  captured: bool,
+}
+
+struct Loop {
+  depth: usize,
+  /// 需要回填到循环出口的break跳转
+  breaks: Vec<usize>,
+}
+
+struct FunctionState {
+  name: String,
+  arity: usize,
+  chunk: Chunk,
+  locals: Vec<Local>,
+  upvalues: Vec<UpvalueDesc>,
+  scope_depth: usize,
+  loops: Vec<Loop>,
+}
+
+impl FunctionState {
+  fn new(name: &str, arity: usize, scope_depth: usize) -> Self {
+    Self {
+      name: name.to_string(),
+      arity,
+      chunk: Chunk::default(),
+      // 0号槽是被调用的函数本身
+      locals: vec![Local {
+        name: String::new(),
+        depth: 0,
+        captured: false,
+      }],
+      upvalues: Vec::new(),
+      scope_depth,
+      loops: Vec::new(),
+    }
+  }
+}
+
+impl Compiler {
+  pub fn new(strip_asserts: bool) -> Self {
+    Self {
+      strip_asserts,
+      states: RefCell::new(Vec::new()),
+      line: Cell::new(0),
+    }
+  }
+
+  /// 编译整个脚本，结果是一个没有参数的函数
+  pub fn compile(&self, statements: &[Rc<Stmt>]) -> Result<Rc<Function>, SaturdayResult> {
+    self
+      .states
+      .borrow_mut()
+      .push(FunctionState::new("script", 0, 0));
+    for statement in statements {
+      self.statement(statement.clone())?;
+    }
+
+    Ok(self.end_function())
+  }
+
+  /// VM还不支持的语法，what说明是哪一种
+  fn unsupported(&self, token: &Token, what: &str) -> Result<(), SaturdayResult> {
+    Err(SaturdayResult::parse_error(
+      token,
+      &format!("{what} are not supported by the VM yet."),
+    ))
+  }
+
+  fn statement(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
+    self.emit(Op::Fuel);
+    stmt.accept(stmt.clone(), self)
+  }
+
+  fn expression(&self, expr: Rc<Expr>) -> Result<(), SaturdayResult> {
+    expr.accept(expr.clone(), self)
+  }
+
+  fn with_state<T>(&self, f: impl FnOnce(&mut FunctionState) -> T) -> T {
+    f(self.states.borrow_mut().last_mut().unwrap())
+  }
+
+  fn emit(&self, op: Op) -> usize {
+    let line = self.line.get();
+    self.with_state(|state| state.chunk.write(op, line))
+  }
+
+  fn end_function(&self) -> Rc<Function> {
+    self.emit(Op::Nil);
+    self.emit(Op::Return);
+    let state = self.states.borrow_mut().pop().unwrap();
+    Rc::new(Function {
+      name: state.name,
+      arity: state.arity,
+      chunk: state.chunk,
+      upvalues: state.upvalues,
+    })
+  }
+
+  fn index(&self, len: usize, what: &str) -> Result<u16, SaturdayResult> {
+    u16::try_from(len).map_err(|_| {
+      SaturdayResult::error(self.line.get(), &format!("Too many {what} in one chunk."))
+    })
+  }
+
+  fn make_constant(&self, value: Object) -> Result<u16, SaturdayResult> {
+    let len = self.with_state(|state| {
+      state.chunk.constants.push(value);
+      state.chunk.constants.len() - 1
+    });
+    self.index(len, "constants")
+  }
+
+  /// 记录报错时需要的token，同时更新当前行号
+  fn make_token(&self, token: &Token) -> Result<u16, SaturdayResult> {
+    self.line.set(token.line);
+    let len = self.with_state(|state| {
+      state.chunk.tokens.push(token.dup());
+      state.chunk.tokens.len() - 1
+    });
+    self.index(len, "tokens")
+  }
+
+  fn emit_jump(&self, op: fn(u16) -> Op) -> usize {
+    self.emit(op(0))
+  }
+
+  /// 把at处的跳转指令的目标设为当前位置
+  fn patch_jump(&self, at: usize) -> Result<(), SaturdayResult> {
+    let distance = self.with_state(|state| state.chunk.code.len() - at - 1);
+    let Ok(offset) = u16::try_from(distance) else {
+      return Err(SaturdayResult::error(
+        self.line.get(),
+        "Too much code to jump over.",
+      ));
+    };
+
+    self.with_state(|state| {
+      state.chunk.code[at] = match state.chunk.code[at] {
+        Op::Jump(_) => Op::Jump(offset),
+        Op::JumpIfFalse(_) => Op::JumpIfFalse(offset),
+        Op::JumpIfNotNil(_) => Op::JumpIfNotNil(offset),
+        Op::JumpIfNil(_) => Op::JumpIfNil(offset),
+        Op::MatchArm(arm, _) => Op::MatchArm(arm, offset),
+        op => unreachable!("{op:?} is not a jump"),
+      }
+    });
+    Ok(())
+  }
+
+  fn emit_loop(&self, start: usize) -> Result<(), SaturdayResult> {
+    let distance = self.with_state(|state| state.chunk.code.len() + 1 - start);
+    let Ok(offset) = u16::try_from(distance) else {
+      return Err(SaturdayResult::error(
+        self.line.get(),
+        "Loop body too large.",
+      ));
+    };
+
+    self.emit(Op::Loop(offset));
+    Ok(())
+  }
+
+  fn begin_scope(&self) {
+    self.with_state(|state| state.scope_depth += 1);
+  }
+
+  fn end_scope(&self) {
+    self.with_state(|state| state.scope_depth -= 1);
+    let depth = self.with_state(|state| state.scope_depth);
+    self.pop_locals(depth);
+    self.with_state(|state| state.locals.retain(|local| local.depth <= depth));
+  }
+
+  /// 弹出比depth更深的局部变量，但不从编译器中移除，break和match的guard失败时使用
+  fn pop_locals(&self, depth: usize) {
+    let ops: Vec<Op> = self.with_state(|state| {
+      state
+        .locals
+        .iter()
+        .rev()
+        .take_while(|local| local.depth > depth)
+        .map(|local| {
+          if local.captured {
+            Op::CloseUpvalue
+          } else {
+            Op::Pop
+          }
+        })
+        .collect()
+    });
+    for op in ops {
+      self.emit(op);
+    }
+  }
+
+  /// 在当前作用域中添加局部变量，它的值是栈顶的值
+  fn add_local(&self, name: &str) -> Result<(), SaturdayResult> {
+    let len = self.with_state(|state| {
+      state.locals.push(Local {
+        name: name.to_string(),
+        depth: state.scope_depth,
+        captured: false,
+      });
+      state.locals.len()
+    });
+    self.index(len, "local variables").map(|_| ())
+  }
+
+  /// 栈顶的值成为名为name的变量：顶层是全局变量，其他情况是局部变量
+  fn define_variable(&self, name: &Token) -> Result<(), SaturdayResult> {
+    if self.with_state(|state| state.scope_depth) == 0 {
+      let token = self.make_token(name)?;
+      self.emit(Op::DefineGlobal(token));
+      Ok(())
+    } else {
+      self.add_local(&name.as_string())
+    }
+  }
+
+  fn resolve_local(&self, level: usize, name: &str) -> Option<u16> {
+    let states = self.states.borrow();
+    let position = states[level]
+      .locals
+      .iter()
+      .rposition(|local| !local.name.is_empty() && local.name == name)?;
+    Some(position as u16)
+  }
+
+  fn resolve_upvalue(&self, level: usize, name: &str) -> Option<u16> {
+    if level == 0 {
+      return None;
+    }
+
+    if let Some(local) = self.resolve_local(level - 1, name) {
+      self.states.borrow_mut()[level - 1].locals[local as usize].captured = true;
+      return Some(self.add_upvalue(level, true, local));
+    }
+
+    let upvalue = self.resolve_upvalue(level - 1, name)?;
+    Some(self.add_upvalue(level, false, upvalue))
+  }
+
+  fn add_upvalue(&self, level: usize, is_local: bool, index: u16) -> u16 {
+    let upvalues = &mut self.states.borrow_mut()[level].upvalues;
+    if let Some(position) = upvalues
+      .iter()
+      .position(|u| u.is_local == is_local && u.index == index)
+    {
+      return position as u16;
+    }
+
+    upvalues.push(UpvalueDesc { is_local, index });
+    (upvalues.len() - 1) as u16
+  }
+
+  fn named_variable(&self, name: &Token, assign: bool) -> Result<(), SaturdayResult> {
+    let token = self.make_token(name)?;
+    let level = self.states.borrow().len() - 1;
+    let lexeme = name.as_string();
+
+    let op = if let Some(slot) = self.resolve_local(level, &lexeme) {
+      if assign {
+        Op::SetLocal(slot)
+      } else {
+        Op::GetLocal(slot)
+      }
+    } else if let Some(index) = self.resolve_upvalue(level, &lexeme) {
+      if assign {
+        Op::SetUpvalue(index)
+      } else {
+        Op::GetUpvalue(index)
+      }
+    } else if assign {
+      Op::SetGlobal(token)
+    } else {
+      Op::GetGlobal(token)
+    };
+
+    self.emit(op);
+    Ok(())
+  }
+
+  /// # 可选链
+  /// ```
+  /// 和解释器一样，a?.b.c()中a为nil时跳到整条链的末尾，栈顶留下的nil就是整条链的结果。
+  /// op是链最外层的调用使用的指令，Op::Call或Op::TailCall
+  /// ```
+  fn optional_chain(&self, expr: &Rc<Expr>, op: fn(u8, u16) -> Op) -> Result<(), SaturdayResult> {
+    let mut exits = Vec::new();
+    self.link(expr, op, &mut exits)?;
+    for exit in exits {
+      self.patch_jump(exit)?;
+    }
+    Ok(())
+  }
+
+  /// 编译链中的一环，?.处的JumpIfNil记录在exits中，由optional_chain回填
+  fn link(
+    &self,
+    expr: &Rc<Expr>,
+    op: fn(u8, u16) -> Op,
+    exits: &mut Vec<usize>,
+  ) -> Result<(), SaturdayResult> {
+    match &**expr {
+      Expr::Get(get) => {
+        self.link(&get.object, Op::Call, exits)?;
+        let name = self.make_token(&get.name)?;
+        if get.optional {
+          exits.push(self.emit_jump(Op::JumpIfNil));
+        }
+        self.emit(Op::Get(name));
+      }
+      Expr::Call(call) => {
+        self.link(&call.callee, Op::Call, exits)?;
+        if call.optional {
+          exits.push(self.emit_jump(Op::JumpIfNil));
+        }
+        for argument in call.arguments.iter() {
+          self.expression(argument.clone())?;
+        }
+
+        let paren = self.make_token(&call.paren)?;
+        self.emit(op(call.arguments.len() as u8, paren));
+      }
+      _ => self.expression(expr.clone())?,
+    }
+    Ok(())
+  }
+
+  fn function(&self, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
+    self.states.borrow_mut().push(FunctionState::new(
+      &stmt.name.as_string(),
+      stmt.params.len(),
+      1,
+    ));
+    for param in stmt.params.iter() {
+      self.add_local(&param.as_string())?;
+    }
+    for statement in stmt.body.iter() {
+      self.statement(statement.clone())?;
+    }
+
+    let function = self.end_function();
+    let index = self.with_state(|state| {
+      state.chunk.functions.push(function);
+      state.chunk.functions.len() - 1
+    });
+    let index = self.index(index, "functions")?;
+    self.emit(Op::Closure(index));
+    Ok(())
+  }
+}
+
+impl StmtVisitor<()> for Compiler {
+  fn visit_assert_stmt(&self, wrapper: Rc<Stmt>, stmt: &AssertStmt) -> Result<(), SaturdayResult> {
+    if self.strip_asserts {
+      return Ok(());
+    }
+    let Stmt::Assert(shared) = &*wrapper else {
+      unreachable!()
+    };
+
+    self.line.set(stmt.keyword.line);
+    let binary = match &*stmt.condition {
+      Expr::Binary(binary) => Some(binary),
+      _ => None,
+    };
+
+    // 二元条件的两个操作数先保存在隐藏的局部变量中，失败时一起报告
+    self.begin_scope();
+    if let Some(binary) = binary {
+      self.expression(binary.left.clone())?;
+      self.add_local("")?;
+      self.expression(binary.right.clone())?;
+      self.add_local("")?;
+      let slot = self.with_state(|state| state.locals.len() - 2) as u16;
+      self.emit(Op::GetLocal(slot));
+      self.emit(Op::GetLocal(slot + 1));
+      let operator = self.make_token(&binary.operator)?;
+      self.emit(Op::Binary(operator));
+    } else {
+      self.expression(stmt.condition.clone())?;
+    }
+
+    let fail = self.emit_jump(Op::JumpIfFalse);
+    self.emit(Op::Pop);
+    let end = self.emit_jump(Op::Jump);
+
+    self.patch_jump(fail)?;
+    self.emit(Op::Pop);
+    if let Some(message) = stmt.message.clone() {
+      self.expression(message)?;
+    }
+    let index = self.with_state(|state| {
+      state.chunk.asserts.push((shared.clone(), binary.is_some()));
+      state.chunk.asserts.len() - 1
+    });
+    let index = self.index(index, "asserts")?;
+    self.emit(Op::AssertFail(index));
+
+    self.patch_jump(end)?;
+    self.end_scope();
+    Ok(())
+  }
+
+  fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<(), SaturdayResult> {
+    self.begin_scope();
+    for statement in stmt.statements.iter() {
+      self.statement(statement.clone())?;
+    }
+    self.end_scope();
+    Ok(())
+  }
+
+  fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<(), SaturdayResult> {
+    self.line.set(stmt.token.line);
+    let Some(depth) = self.with_state(|state| state.loops.last().map(|l| l.depth)) else {
+      return Err(SaturdayResult::runtime_error(
+        &stmt.token,
+        "break statement outside of a while/for loop",
+      ));
+    };
+
+    self.pop_locals(depth);
+    let jump = self.emit_jump(Op::Jump);
+    self.with_state(|state| state.loops.last_mut().unwrap().breaks.push(jump));
+    Ok(())
+  }
+
+  fn visit_enum_stmt(&self, wrapper: Rc<Stmt>, stmt: &EnumStmt) -> Result<(), SaturdayResult> {
+    self.line.set(stmt.name.line);
+    let index = self.with_state(|state| {
+      let Stmt::Enum(e) = &*wrapper else {
+        unreachable!()
+      };
+      state.chunk.enums.push(e.clone());
+      state.chunk.enums.len() - 1
+    });
+    let index = self.index(index, "enums")?;
+    self.emit(Op::Enum(index));
+    self.define_variable(&stmt.name)
+  }
+
+  fn visit_record_stmt(&self, wrapper: Rc<Stmt>, stmt: &RecordStmt) -> Result<(), SaturdayResult> {
+    self.line.set(stmt.name.line);
+    let index = self.with_state(|state| {
+      let Stmt::Record(r) = &*wrapper else {
+        unreachable!()
+      };
+      state.chunk.records.push(r.clone());
+      state.chunk.records.len() - 1
+    });
+    let index = self.index(index, "records")?;
+    self.emit(Op::Record(index));
+    self.define_variable(&stmt.name)
+  }
+
+  fn visit_expression_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &ExpressionStmt,
+  ) -> Result<(), SaturdayResult> {
+    self.expression(stmt.expression.clone())?;
+    self.emit(Op::Pop);
+    Ok(())
+  }
+
+  fn visit_forin_stmt(&self, _: Rc<Stmt>, stmt: &ForInStmt) -> Result<(), SaturdayResult> {
+    self.unsupported(&stmt.name, "For-in loops")
+  }
+
+  fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
+    self.line.set(stmt.name.line);
+    if stmt.generator {
+      return self.unsupported(&stmt.name, "Generator functions");
+    }
+
+    // 局部函数先声明再编译函数体，这样函数体中可以递归地引用自己，
+    // 之后压入的闭包正好占据这个局部变量的槽
+    if self.with_state(|state| state.scope_depth) > 0 {
+      self.add_local(&stmt.name.as_string())?;
+      return self.function(stmt);
+    }
+
+    self.function(stmt)?;
+    self.define_variable(&stmt.name)
+  }
+
+  fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<(), SaturdayResult> {
+    self.expression(stmt.condition.clone())?;
+    let else_jump = self.emit_jump(Op::JumpIfFalse);
+    self.emit(Op::Pop);
+    self.statement(stmt.then_branch.clone())?;
+    let end = self.emit_jump(Op::Jump);
+
+    self.patch_jump(else_jump)?;
+    self.emit(Op::Pop);
+    if let Some(else_branch) = stmt.else_branch.clone() {
+      self.statement(else_branch)?;
+    }
+    self.patch_jump(end)
+  }
+
+  fn visit_match_stmt(&self, wrapper: Rc<Stmt>, stmt: &MatchStmt) -> Result<(), SaturdayResult> {
+    let Stmt::Match(shared) = &*wrapper else {
+      unreachable!()
+    };
+
+    // 被匹配的值保存在隐藏的局部变量中，每个分支的绑定依次压在它上面
+    self.begin_scope();
+    self.expression(stmt.subject.clone())?;
+    self.add_local("")?;
+
+    let mut ends = Vec::new();
+    for (i, arm) in stmt.arms.iter().enumerate() {
+      self.line.set(arm.pattern.token().line);
+      let index = self.with_state(|state| {
+        state.chunk.arms.push((shared.clone(), i));
+        state.chunk.arms.len() - 1
+      });
+      let index = self.index(index, "match arms")?;
+      for type_name in arm.pattern.types() {
+        self.expression(type_name.clone())?;
+      }
+      let next = self.emit(Op::MatchArm(index, 0));
+
+      self.begin_scope();
+      let depth = self.with_state(|state| state.scope_depth) - 1;
+      for name in arm.pattern.bindings() {
+        self.add_local(&name.as_string())?;
+      }
+
+      let fail = match &arm.guard {
+        Some(guard) => {
+          self.expression(guard.clone())?;
+          let fail = self.emit_jump(Op::JumpIfFalse);
+          self.emit(Op::Pop);
+          Some(fail)
+        }
+        None => None,
+      };
+
+      self.statement(arm.body.clone())?;
+      self.pop_locals(depth);
+      ends.push(self.emit_jump(Op::Jump));
+
+      if let Some(fail) = fail {
+        self.patch_jump(fail)?;
+        self.emit(Op::Pop);
+        self.pop_locals(depth);
+      }
+      self.with_state(|state| {
+        state.scope_depth -= 1;
+        state.locals.retain(|local| local.depth <= depth);
+      });
+      self.patch_jump(next)?;
+    }
+
+    for end in ends {
+      self.patch_jump(end)?;
+    }
+    self.end_scope();
+    Ok(())
+  }
+
+  fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), SaturdayResult> {
+    self.expression(stmt.expression.clone())?;
+    self.emit(Op::Print);
+    Ok(())
+  }
+
+  fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), SaturdayResult> {
+    self.line.set(stmt.keyword.line);
+    match stmt.value.clone() {
+      // 和解释器一样，返回调用的结果时不增加调用深度
+      Some(value) => match &*value {
+        Expr::Call(_) => self.optional_chain(&value, Op::TailCall)?,
+        _ => self.expression(value.clone())?,
+      },
+      None => {
+        self.emit(Op::Nil);
+      }
+    }
+    self.emit(Op::Return);
+    Ok(())
+  }
+
+  fn visit_def_stmt(&self, _: Rc<Stmt>, stmt: &DefStmt) -> Result<(), SaturdayResult> {
+    self.line.set(stmt.name.line);
+    match stmt.initializer.clone() {
+      Some(initializer) => self.expression(initializer)?,
+      None => {
+        self.emit(Op::Nil);
+      }
+    }
+    self.define_variable(&stmt.name)
+  }
+
+  fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), SaturdayResult> {
+    let start = self.with_state(|state| {
+      state.loops.push(Loop {
+        depth: state.scope_depth,
+        breaks: Vec::new(),
+      });
+      state.chunk.code.len()
+    });
+
+    self.expression(stmt.condition.clone())?;
+    let exit = self.emit_jump(Op::JumpIfFalse);
+    self.emit(Op::Pop);
+    self.statement(stmt.body.clone())?;
+    self.emit_loop(start)?;
+
+    self.patch_jump(exit)?;
+    self.emit(Op::Pop);
+    let breaks = self.with_state(|state| state.loops.pop().unwrap().breaks);
+    for jump in breaks {
+      self.patch_jump(jump)?;
+    }
+    Ok(())
+  }
+
+  fn visit_yield_stmt(&self, _: Rc<Stmt>, stmt: &YieldStmt) -> Result<(), SaturdayResult> {
+    self.unsupported(&stmt.keyword, "Yield statements")
+  }
+}
+
+impl ExprVisitor<()> for Compiler {
+  fn visit_assign_expr(&self, _: Rc<Expr>, expr: &AssignExpr) -> Result<(), SaturdayResult> {
+    self.expression(expr.value.clone())?;
+    self.named_variable(&expr.name, true)
+  }
+
+  fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<(), SaturdayResult> {
+    self.expression(expr.left.clone())?;
+    self.expression(expr.right.clone())?;
+    let operator = self.make_token(&expr.operator)?;
+    self.emit(Op::Binary(operator));
+    Ok(())
+  }
+
+  fn visit_call_expr(&self, wrapper: Rc<Expr>, _: &CallExpr) -> Result<(), SaturdayResult> {
+    self.optional_chain(&wrapper, Op::Call)
+  }
+
+  fn visit_get_expr(&self, wrapper: Rc<Expr>, _: &GetExpr) -> Result<(), SaturdayResult> {
+    self.optional_chain(&wrapper, Op::Call)
+  }
+
+  fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<(), SaturdayResult> {
+    self.expression(expr.expression.clone())
+  }
+
+  fn visit_list_expr(&self, _: Rc<Expr>, expr: &ListExpr) -> Result<(), SaturdayResult> {
+    for element in &expr.elements {
+      self.expression(element.clone())?;
+    }
+    self.line.set(expr.bracket.line);
+    let count = u16::try_from(expr.elements.len())
+      .map_err(|_| SaturdayResult::error(expr.bracket.line, "Too many elements in one list."))?;
+    self.emit(Op::List(count));
+    Ok(())
+  }
+
+  fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<(), SaturdayResult> {
+    match expr.value.clone().unwrap() {
+      Object::Nil => self.emit(Op::Nil),
+      Object::Bool(true) => self.emit(Op::True),
+      Object::Bool(false) => self.emit(Op::False),
+      value => {
+        let index = self.make_constant(value)?;
+        self.emit(Op::Constant(index))
+      }
+    };
+    Ok(())
+  }
+
+  fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<(), SaturdayResult> {
+    self.expression(expr.left.clone())?;
+    self.line.set(expr.operator.line);
+
+    let end = if expr.operator.is(TokenType::QuestionQuestion) {
+      self.emit_jump(Op::JumpIfNotNil)
+    } else if expr.operator.is(TokenType::Or) {
+      let else_jump = self.emit_jump(Op::JumpIfFalse);
+      let end = self.emit_jump(Op::Jump);
+      self.patch_jump(else_jump)?;
+      end
+    } else {
+      self.emit_jump(Op::JumpIfFalse)
+    };
+
+    self.emit(Op::Pop);
+    self.expression(expr.right.clone())?;
+    self.patch_jump(end)
+  }
+
+  fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<(), SaturdayResult> {
+    self.expression(expr.right.clone())?;
+    let operator = self.make_token(&expr.operator)?;
+    self.emit(Op::Unary(operator));
+    Ok(())
+  }
+
+  fn visit_variable_expr(&self, _: Rc<Expr>, expr: &VariableExpr) -> Result<(), SaturdayResult> {
+    self.named_variable(&expr.name, false)
+  }
+}
@@ -1,52 +1,79 @@
+use crate::interner::Symbol;
 use crate::object::Object;
 use crate::token::Token;
 use crate::SaturdayResult;
 use std::cell::RefCell;
-use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+/// # 环境
+/// ```
+/// 局部环境中的变量按定义顺序放在values中，resolver为每个局部变量分配了同样顺序的槽，
+/// 所以局部变量通过(距离, 槽)访问，不需要按名字查找。
+/// 只有全局环境记录名字到槽的映射，用于访问resolver没有解析到的全局变量
+/// ```
 #[derive(Debug)]
 pub struct Environment {
-  values: HashMap<String, Object>,
+  values: Vec<Object>,
+  names: Option<HashMap<Symbol, usize>>,
   enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
   pub fn new() -> Self {
     Self {
-      values: HashMap::new(),
+      values: Vec::new(),
+      names: Some(HashMap::new()),
       enclosing: None,
     }
   }
 
   pub fn new_with_enclosing(enclosing: Rc<RefCell<Self>>) -> Self {
     Self {
-      values: HashMap::new(),
+      values: Vec::new(),
+      names: None,
       enclosing: Some(enclosing),
     }
   }
 
-  pub fn define(&mut self, name: &str, value: Object) {
-    self.values.insert(name.to_string(), value);
+  /// 定义变量，局部环境不记录名字，值放在下一个槽中
+  pub fn define(&mut self, name: Symbol, value: Object) {
+    if let Some(names) = &mut self.names {
+      if let Some(slot) = names.get(&name) {
+        self.values[*slot] = value;
+        return;
+      }
+
+      names.insert(name, self.values.len());
+    }
+
+    self.values.push(value);
   }
 
-  pub fn get_at(&self, distance: usize, name: &str) -> Result<Object, SaturdayResult> {
+  fn ancestor<T>(&self, distance: usize, f: impl FnOnce(&Environment) -> T) -> T {
     if distance == 0 {
-      Ok(self.values.get(name).unwrap().clone())
+      f(self)
     } else {
       self
         .enclosing
         .as_ref()
         .unwrap()
         .borrow()
-        .get_at(distance - 1, name)
+        .ancestor(distance - 1, f)
     }
   }
 
+  pub fn get_at(&self, distance: usize, slot: usize) -> Object {
+    self.ancestor(distance, |e| e.values[slot].clone())
+  }
+
   pub fn get(&self, name: &Token) -> Result<Object, SaturdayResult> {
-    if let Some(object) = self.values.get(&name.as_string()) {
-      Ok(object.clone())
+    if let Some(slot) = self
+      .names
+      .as_ref()
+      .and_then(|names| names.get(&name.symbol))
+    {
+      Ok(self.values[*slot].clone())
     } else if let Some(enclosing) = &self.enclosing {
       enclosing.borrow().get(name)
     } else {
@@ -57,28 +84,39 @@ impl Environment {
     }
   }
 
-  pub fn assign_at(
-    &mut self,
-    distance: usize,
-    name: &Token,
-    value: Object,
-  ) -> Result<(), SaturdayResult> {
+  pub fn assign_at(&mut self, distance: usize, slot: usize, value: Object) {
     if distance == 0 {
-      self.values.insert(name.as_string(), value);
-      Ok(())
+      self.values[slot] = value;
     } else {
       self
         .enclosing
         .as_ref()
         .unwrap()
         .borrow_mut()
-        .assign_at(distance - 1, name, value)
+        .assign_at(distance - 1, slot, value)
     }
   }
 
+  pub fn values(&self) -> &[Object] {
+    &self.values
+  }
+
+  pub fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+    self.enclosing.as_ref()
+  }
+
+  /// 清空变量和外层环境，用于打断环，返回的内容由调用方释放
+  pub fn clear(&mut self) -> (Vec<Object>, Option<Rc<RefCell<Environment>>>) {
+    (std::mem::take(&mut self.values), self.enclosing.take())
+  }
+
   pub fn assign(&mut self, name: &Token, value: Object) -> Result<(), SaturdayResult> {
-    if let Entry::Occupied(mut object) = self.values.entry(name.as_string()) {
-      object.insert(value);
+    if let Some(slot) = self
+      .names
+      .as_ref()
+      .and_then(|names| names.get(&name.symbol))
+    {
+      self.values[*slot] = value;
       Ok(())
     } else if let Some(enclosing) = &self.enclosing {
       enclosing.borrow_mut().assign(name, value)
@@ -94,28 +132,29 @@ impl Environment {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::interner::intern;
   use crate::token_type::TokenType;
 
   #[test]
   fn can_define_a_variable() {
     let mut e = Environment::new();
-    e.define("One", Object::Bool(true));
-    assert!(e.values.contains_key("One"));
-    assert_eq!(*e.values.get("One").unwrap(), Object::Bool(true));
+    e.define(intern("One"), Object::Bool(true));
+    assert!(e.names.as_ref().unwrap().contains_key(&intern("One")));
+    assert_eq!(e.values, vec![Object::Bool(true)]);
   }
 
   #[test]
   fn can_redefine_a_variable() {
     let mut e = Environment::new();
-    e.define("Two", Object::Bool(true));
-    e.define("Two", Object::Num(12.0));
-    assert_eq!(*e.values.get("Two").unwrap(), Object::Num(12.0));
+    e.define(intern("Two"), Object::Bool(true));
+    e.define(intern("Two"), Object::Num(12.0));
+    assert_eq!(e.values, vec![Object::Num(12.0)]);
   }
 
   #[test]
   fn can_look_up_a_variable() {
     let mut e = Environment::new();
-    e.define("Three", Object::Str("foo".to_string()));
+    e.define(intern("Three"), Object::Str("foo".to_string()));
     assert_eq!(
       e.get(&Token::new(
         TokenType::Identifier,
@@ -147,7 +186,7 @@ mod tests {
   fn can_reassign_existing_variable() {
     let mut e = Environment::new();
     let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
-    e.define("Four", Object::Num(73.1));
+    e.define(intern("Four"), Object::Num(73.1));
     assert!(e.assign(&four_tok, Object::Num(89.5)).is_ok());
     assert_eq!(e.get(&four_tok).ok(), Some(Object::Num(89.5)));
   }
@@ -163,7 +202,7 @@ mod tests {
   fn can_read_from_enclosed_environment() {
     let e = Rc::new(RefCell::new(Environment::new()));
     let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
-    e.borrow_mut().define("Four", Object::Num(73.1));
+    e.borrow_mut().define(intern("Four"), Object::Num(73.1));
     let f = Environment::new_with_enclosing(Rc::clone(&e));
     assert_eq!(f.get(&four_tok).ok(), Some(Object::Num(73.1)));
   }
@@ -171,10 +210,26 @@ mod tests {
   #[test]
   fn can_assign_to_enclosed_environment() {
     let e = Rc::new(RefCell::new(Environment::new()));
-    e.borrow_mut().define("Four", Object::Num(73.1));
+    e.borrow_mut().define(intern("Four"), Object::Num(73.1));
     let mut f = Environment::new_with_enclosing(Rc::clone(&e));
     let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
     assert!(f.assign(&four_tok, Object::Num(91.2)).is_ok());
     assert_eq!(f.get(&four_tok).ok(), Some(Object::Num(91.2)));
   }
+
+  #[test]
+  fn locals_are_accessed_by_depth_and_slot() {
+    let e = Rc::new(RefCell::new(Environment::new()));
+    let mut f = Environment::new_with_enclosing(Rc::clone(&e));
+    f.define(intern("a"), Object::Num(1.0));
+    f.define(intern("b"), Object::Num(2.0));
+    let g = Rc::new(RefCell::new(f));
+    let mut h = Environment::new_with_enclosing(Rc::clone(&g));
+    h.define(intern("c"), Object::Num(3.0));
+
+    assert_eq!(h.get_at(1, 1), Object::Num(2.0));
+    h.assign_at(1, 0, Object::Nil);
+    assert_eq!(g.borrow().get_at(0, 0), Object::Nil);
+    assert_eq!(h.get_at(0, 0), Object::Num(3.0));
+  }
 }
@@ -1,13 +1,54 @@
+use crate::callable::Callable;
 use crate::object::Object;
 use crate::token::Token;
 use crate::token_type::TokenType;
 
+/// trace中连续相同的帧最多输出的个数
+const REPEATED_FRAMES: usize = 3;
+
+/// 调用栈中的一帧，function为None表示顶层脚本
+#[derive(PartialEq)]
+pub struct StackFrame {
+  pub function: Option<String>,
+  pub line: usize,
+}
+
+/// 运行时错误的调用栈，最内层在前
+#[derive(Default)]
+pub struct Trace {
+  pub frames: Vec<StackFrame>,
+}
+
 pub enum SaturdayResult {
-  ParseError { token: Token, message: String },
-  RuntimeError { token: Token, message: String },
-  Error { line: usize, message: String },
-  SystemError { message: String },
-  ReturnValue { value: Object },
+  ParseError {
+    token: Token,
+    message: String,
+  },
+  /// trace是展开时收集的调用栈，装箱是为了让Result的错误值保持较小
+  RuntimeError {
+    token: Token,
+    message: String,
+    trace: Box<Trace>,
+  },
+  Error {
+    line: usize,
+    message: String,
+  },
+  SystemError {
+    message: String,
+  },
+  ReturnValue {
+    value: Object,
+  },
+  TailCall {
+    callee: Callable,
+    paren: Token,
+    arguments: Vec<Object>,
+  },
+  /// 执行步数的预算用完，执行中止
+  OutOfFuel,
+  /// 执行被Ctrl-C或超时中断
+  Interrupted,
   Break,
   Fail,
 }
@@ -30,13 +71,28 @@ impl SaturdayResult {
     err
   }
 
+  /// 运行时错误要等调用栈展开后才知道完整的trace，由执行入口负责调用report
   pub fn runtime_error(token: &Token, message: &str) -> Self {
-    let err = Self::RuntimeError {
+    Self::RuntimeError {
       token: token.dup(),
       message: message.to_string(),
-    };
-    err.report("");
-    err
+      trace: Box::default(),
+    }
+  }
+
+  /// 错误离开function时记录这一帧，line是function最近一次发起调用的行。
+  /// 最内层的帧停在出错的位置，外层的帧停在它发起调用的位置
+  pub fn unwind(mut self, function: Option<String>, line: usize) -> Self {
+    if let Self::RuntimeError { token, trace, .. } = &mut self {
+      let line = if trace.frames.is_empty() {
+        token.line
+      } else {
+        line
+      };
+      trace.frames.push(StackFrame { function, line });
+    }
+
+    self
   }
 
   pub fn parse_error(token: &Token, message: &str) -> Self {
@@ -56,7 +112,17 @@ impl SaturdayResult {
     err
   }
 
-  fn report(&self, loc: &str) {
+  /// 警告只输出信息，不影响执行
+  pub fn warning(token: &Token, message: &str) {
+    eprintln!(
+      "[line {}] Warning at '{}': {}",
+      token.line,
+      token.as_string(),
+      message
+    );
+  }
+
+  pub fn report(&self, loc: &str) {
     match self {
       Self::ParseError { token, message } => {
         eprintln!(
@@ -66,11 +132,32 @@ impl SaturdayResult {
           message
         );
       }
-      Self::RuntimeError { token, message } => {
+      Self::RuntimeError {
+        token,
+        message,
+        trace,
+      } => {
         if token.is(TokenType::Eof) {
           eprintln!("[line {}] Error at end: {}", token.line, message);
-        } else {
+        } else if trace.frames.is_empty() {
           eprintln!("{}\n[line {}]", message, token.line);
+        } else {
+          eprintln!("{}", message);
+        }
+        // 深递归时连续相同的帧只输出前几个
+        for frames in trace.frames.chunk_by(|a, b| a == b) {
+          for frame in frames.iter().take(REPEATED_FRAMES) {
+            match &frame.function {
+              Some(function) => eprintln!("[line {}] in {}()", frame.line, function),
+              None => eprintln!("[line {}] in script", frame.line),
+            }
+          }
+          if frames.len() > REPEATED_FRAMES {
+            eprintln!(
+              "[previous frame repeated {} more times]",
+              frames.len() - REPEATED_FRAMES
+            );
+          }
         }
       }
       Self::SystemError { message } => {
@@ -79,7 +166,13 @@ impl SaturdayResult {
       Self::Error { line, message } => {
         eprintln!("[line {}] Error{}: {}", line, loc, message);
       }
-      Self::Break | Self::ReturnValue { .. } => {}
+      Self::OutOfFuel => {
+        eprintln!("Execution stopped: out of fuel.");
+      }
+      Self::Interrupted => {
+        eprintln!("Execution interrupted.");
+      }
+      Self::Break | Self::ReturnValue { .. } | Self::TailCall { .. } => {}
       Self::Fail => {
         panic!("should not get here")
       }
@@ -0,0 +1,407 @@
+use crate::error::SaturdayResult;
+use crate::generator::{Generator, Step};
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::token::Token;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// 协程相关的内置函数，协程函数体中直接调用它们时由生成器帧栈挂起当前协程
+#[derive(Clone, Copy, PartialEq)]
+pub enum FiberOp {
+  YieldNow,
+  Send,
+  Recv,
+}
+
+/// 无界缓冲的通道，send永远不会阻塞
+pub struct Channel {
+  id: usize,
+  queue: RefCell<VecDeque<Object>>,
+}
+
+impl Channel {
+  pub fn send(&self, value: Object) {
+    self.queue.borrow_mut().push_back(value);
+  }
+
+  pub fn try_recv(&self) -> Option<Object> {
+    self.queue.borrow_mut().pop_front()
+  }
+
+  fn is_empty(&self) -> bool {
+    self.queue.borrow().is_empty()
+  }
+}
+
+impl Debug for Channel {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{self}")
+  }
+}
+
+impl Display for Channel {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<chan {}>", self.id)
+  }
+}
+
+impl PartialEq for Channel {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+enum FiberState {
+  Ready,
+  Blocked { channel: Rc<Channel>, token: Token },
+}
+
+struct Fiber {
+  id: usize,
+  name: String,
+  body: Generator,
+  state: RefCell<FiberState>,
+}
+
+/// 不能挂起的recv：在主程序或协程调用的函数中阻塞等待，fiber为None表示主程序
+struct Waiter {
+  fiber: Option<Rc<Fiber>>,
+  channel: Rc<Channel>,
+  token: Token,
+}
+
+/// # 协程调度器
+/// ```
+/// 所有协程在同一个线程、同一个Interpreter中协作式运行，
+/// 每一轮按spawn顺序恢复可运行的协程，直到它再次让出或阻塞
+/// ```
+pub struct Scheduler {
+  fibers: RefCell<Vec<Rc<Fiber>>>,
+  running: RefCell<Vec<Rc<Fiber>>>,
+  waiters: RefCell<Vec<Waiter>>,
+  next_fiber_id: Cell<usize>,
+  next_channel_id: Cell<usize>,
+}
+
+impl Scheduler {
+  pub fn new() -> Self {
+    Self {
+      fibers: RefCell::new(Vec::new()),
+      running: RefCell::new(Vec::new()),
+      waiters: RefCell::new(Vec::new()),
+      next_fiber_id: Cell::new(1),
+      next_channel_id: Cell::new(1),
+    }
+  }
+
+  pub fn spawn(&self, name: String, body: Generator) -> usize {
+    let id = self.next_fiber_id.replace(self.next_fiber_id.get() + 1);
+    self.fibers.borrow_mut().push(Rc::new(Fiber {
+      id,
+      name,
+      body,
+      state: RefCell::new(FiberState::Ready),
+    }));
+    id
+  }
+
+  pub fn channel(&self) -> Rc<Channel> {
+    let id = self.next_channel_id.replace(self.next_channel_id.get() + 1);
+    Rc::new(Channel {
+      id,
+      queue: RefCell::new(VecDeque::new()),
+    })
+  }
+
+  /// 运行一轮，返回是否有协程取得进展
+  pub fn run_round(&self, interpreter: &Interpreter) -> Result<bool, SaturdayResult> {
+    let fibers: Vec<Rc<Fiber>> = self.fibers.borrow().clone();
+    let mut progress = false;
+
+    for fiber in fibers {
+      // 正在执行的协程（比如在它内部嵌套调用了recv）不能重入
+      if fiber.body.is_running() {
+        continue;
+      }
+
+      let runnable = match &*fiber.state.borrow() {
+        FiberState::Ready => true,
+        FiberState::Blocked { channel, .. } => !channel.is_empty(),
+      };
+      if !runnable {
+        continue;
+      }
+
+      progress = true;
+      self.running.borrow_mut().push(Rc::clone(&fiber));
+      let step = fiber.body.step(interpreter, true);
+      self.running.borrow_mut().pop();
+      match step? {
+        Step::Yielded(_) => {
+          fiber.state.replace(FiberState::Ready);
+        }
+        Step::Blocked(channel, token) => {
+          fiber.state.replace(FiberState::Blocked { channel, token });
+        }
+        Step::Done => {
+          self.fibers.borrow_mut().retain(|f| !Rc::ptr_eq(f, &fiber));
+        }
+      }
+    }
+
+    Ok(progress)
+  }
+
+  /// 运行所有协程直到结束，全部阻塞时报告死锁
+  pub fn run_until_idle(&self, interpreter: &Interpreter) -> Result<(), SaturdayResult> {
+    while self.run_round(interpreter)? {}
+
+    if self.fibers.borrow().is_empty() {
+      Ok(())
+    } else {
+      Err(self.deadlock())
+    }
+  }
+
+  /// # 阻塞地接收
+  /// ```
+  /// 在主程序或者协程调用的函数中接收时不能挂起，只能在这里驱动其他协程运行，
+  /// 直到通道中有值。调用者所在的协程在此期间不会被调度
+  /// ```
+  pub fn recv(
+    &self,
+    interpreter: &Interpreter,
+    paren: &Token,
+    channel: &Rc<Channel>,
+  ) -> Result<Object, SaturdayResult> {
+    self.waiters.borrow_mut().push(Waiter {
+      fiber: self.running.borrow().last().cloned(),
+      channel: Rc::clone(channel),
+      token: paren.dup(),
+    });
+    let result = loop {
+      if let Some(value) = channel.try_recv() {
+        break Ok(value);
+      }
+
+      match self.run_round(interpreter) {
+        Ok(true) => {}
+        Ok(false) => break Err(self.deadlock()),
+        Err(e) => break Err(e),
+      }
+    };
+    self.waiters.borrow_mut().pop();
+
+    result
+  }
+
+  /// 报告死锁：列出所有阻塞的协程，包括在嵌套的recv中等待的主程序和协程
+  fn deadlock(&self) -> SaturdayResult {
+    let mut message = String::from("Deadlock: all fibers are blocked.");
+    let mut location: Option<Token> = None;
+    for waiter in self.waiters.borrow().iter().rev() {
+      let who = match &waiter.fiber {
+        Some(fiber) => format!("fiber #{} ({})", fiber.id, fiber.name),
+        None => String::from("main script"),
+      };
+      message.push_str(&format!(
+        "\n  {} waiting on recv({}) at line {}",
+        who, waiter.channel, waiter.token.line
+      ));
+      location.get_or_insert_with(|| waiter.token.dup());
+    }
+    for fiber in self.fibers.borrow().iter() {
+      if let FiberState::Blocked { channel, token } = &*fiber.state.borrow() {
+        message.push_str(&format!(
+          "\n  fiber #{} ({}) waiting on recv({}) at line {}",
+          fiber.id, fiber.name, channel, token.line
+        ));
+        location.get_or_insert_with(|| token.dup());
+      }
+    }
+
+    // 死锁的协程全都清理掉，避免REPL中下一行继续报告
+    self.fibers.borrow_mut().retain(|f| f.body.is_running());
+    // 没有协程能取得进展时，除了正在执行的协程之外都阻塞在recv上；
+    // 正在执行的协程只能是在嵌套的recv中，已经记录在waiters里
+    let location = location.expect("a deadlocked fiber is blocked on recv");
+    SaturdayResult::runtime_error(&location, &message)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::scanner::Scanner;
+  use crate::token_type::TokenType;
+
+  fn run(source: &str) -> (Interpreter, Result<(), SaturdayResult>) {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    let result = interpreter.interpreter(&statements);
+    (interpreter, result)
+  }
+
+  fn global(interpreter: &Interpreter, name: &str) -> Object {
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+    interpreter.globals.borrow().get(&token).ok().unwrap()
+  }
+
+  #[test]
+  fn fibers_interleave_on_yield_now() {
+    let (terp, result) = run(
+      "def log = \"\";
+       fun a() { log = log + \"a1\"; yield_now(); log = log + \"a2\"; }
+       fun b() { log = log + \"b1\"; yield_now(); log = log + \"b2\"; }
+       spawn(a);
+       spawn(b);",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "log"), Object::Str("a1b1a2b2".to_string()));
+  }
+
+  #[test]
+  fn recv_blocks_until_value_is_sent() {
+    let (terp, result) = run(
+      "def ch = chan();
+       def got;
+       fun consumer() { def v = recv(ch); got = v * 2; }
+       fun producer() { yield_now(); send(ch, 21); }
+       spawn(consumer);
+       spawn(producer);",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "got"), Object::Num(42.0));
+  }
+
+  #[test]
+  fn main_recv_drives_fibers() {
+    let (terp, result) = run(
+      "def ch = chan();
+       fun producer() { send(ch, 1); yield_now(); send(ch, 2); }
+       spawn(producer);
+       def total = recv(ch) + recv(ch);",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "total"), Object::Num(3.0));
+  }
+
+  #[test]
+  fn suspends_inside_expressions() {
+    let (terp, result) = run(
+      "def jobs = chan();
+       def log = \"\";
+       fun worker() { log = \"got \" + (recv(jobs) * 2 ?? 0) + \" after \" + log; }
+       spawn(worker);
+       yield_now();
+       log = log + \"main \";
+       send(jobs, 21);",
+    );
+    assert!(result.is_ok());
+    assert_eq!(
+      global(&terp, "log"),
+      Object::Str("got 42 after main ".to_string())
+    );
+  }
+
+  #[test]
+  fn optional_chains_skip_suspension() {
+    let (terp, result) = run(
+      "def jobs = chan();
+       def skipped;
+       def n;
+       fun worker() {
+         def s = nil;
+         skipped = s?.repeat(recv(jobs)).len() ?? \"skipped\";
+         n = \"ab\"?.repeat(recv(jobs)).len();
+       }
+       spawn(worker);
+       send(jobs, 3);",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "skipped"), Object::Str("skipped".to_string()));
+    assert_eq!(global(&terp, "n"), Object::Num(6.0));
+  }
+
+  #[test]
+  fn reports_deadlock() {
+    let (_, result) = run(
+      "def ch = chan();
+       fun waiter() { recv(ch); }
+       spawn(waiter);",
+    );
+    assert!(matches!(result, Err(SaturdayResult::RuntimeError { .. })));
+
+    // 在函数中嵌套等待的协程也要列出来
+    let (_, result) = run(
+      "def a = chan();
+       def b = chan();
+       fun take() { def v = recv(a); return v; }
+       fun nested() { take(); }
+       fun direct() { recv(b); }
+       spawn(direct);
+       spawn(nested);",
+    );
+    let Err(SaturdayResult::RuntimeError { token, message, .. }) = result else {
+      panic!("expect deadlock");
+    };
+    assert_eq!(token.line, 3);
+    assert_eq!(
+      message,
+      "Deadlock: all fibers are blocked.
+  fiber #2 (nested) waiting on recv(<chan 1>) at line 3
+  fiber #1 (direct) waiting on recv(<chan 2>) at line 5"
+    );
+  }
+
+  #[test]
+  fn fiber_sends_respect_memory_limit() {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(
+      "def c = chan();
+       def payload = \"x\".repeat(1000);
+       fun flood() { while true { send(c, payload); } }
+       spawn(flood);"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+
+    interpreter.set_memory_limit(Some(crate::memory::allocated() + (4 << 20)));
+    match interpreter.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError { message, .. }) => assert_eq!(message, "Out of memory."),
+      _ => panic!("expect out of memory"),
+    }
+  }
+
+  #[test]
+  fn reports_bad_arguments_at_the_call() {
+    let cases = [
+      (
+        "spawn(1);",
+        "spawn() expects a function without parameters, got 1.",
+      ),
+      ("send(1, 2);", "send() expects a channel, got 1."),
+      ("recv(nil);", "recv() expects a channel, got nil."),
+    ];
+    for (source, expected) in cases {
+      let (_, result) = run(&format!("def x = 1;\n{source}"));
+      let Err(SaturdayResult::RuntimeError { token, message, .. }) = result else {
+        panic!("expect runtime error");
+      };
+      assert_eq!(message, expected);
+      assert_eq!(token.line, 2);
+    }
+  }
+}
@@ -0,0 +1,234 @@
+use crate::error::SaturdayResult;
+use crate::object::Object;
+use crate::token::Token;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+  Left,
+  Right,
+  Center,
+}
+
+/// 占位符 {:<align><width>.<precision>} 中的格式说明
+struct Spec {
+  align: Option<Align>,
+  width: usize,
+  precision: Option<usize>,
+}
+
+impl Spec {
+  fn parse(spec: &str) -> Option<Spec> {
+    let mut chars = spec.chars().peekable();
+    let align = match chars.peek() {
+      Some('<') => Some(Align::Left),
+      Some('>') => Some(Align::Right),
+      Some('^') => Some(Align::Center),
+      _ => None,
+    };
+    if align.is_some() {
+      chars.next();
+    }
+
+    let mut width = String::new();
+    while let Some(ch) = chars.next_if(|ch| ch.is_ascii_digit()) {
+      width.push(ch);
+    }
+
+    let precision = if chars.next_if_eq(&'.').is_some() {
+      let mut digits = String::new();
+      while let Some(ch) = chars.next_if(|ch| ch.is_ascii_digit()) {
+        digits.push(ch);
+      }
+      Some(digits.parse().ok()?)
+    } else {
+      None
+    };
+
+    if chars.next().is_some() {
+      return None;
+    }
+
+    Some(Spec {
+      align,
+      width: width.parse().unwrap_or(0),
+      precision,
+    })
+  }
+
+  /// # 按说明渲染一个值
+  /// ```
+  /// 没有精度时和Object的Display完全一致；数字的精度是小数位数，字符串的精度是最多保留的字符数。
+  /// 和Rust一样，数字默认右对齐，其他值默认左对齐
+  /// ```
+  fn render(&self, value: &Object) -> String {
+    let text = match (value, self.precision) {
+      (Object::Num(n), Some(precision)) => format!("{n:.precision$}"),
+      (_, Some(precision)) => value.to_string().chars().take(precision).collect(),
+      (_, None) => value.to_string(),
+    };
+
+    let len = text.chars().count();
+    if len >= self.width {
+      return text;
+    }
+
+    let default = if matches!(value, Object::Num(_)) {
+      Align::Right
+    } else {
+      Align::Left
+    };
+    let padding = self.width - len;
+    let (before, after) = match self.align.unwrap_or(default) {
+      Align::Left => (0, padding),
+      Align::Right => (padding, 0),
+      Align::Center => (padding / 2, padding - padding / 2),
+    };
+    format!("{}{text}{}", " ".repeat(before), " ".repeat(after))
+  }
+}
+
+/// # 格式化
+/// ```
+/// {} 依次取下一个参数，{{ 和 }} 输出花括号本身，
+/// 占位符数量必须和参数数量一致。错误都报告在调用处的token上
+/// ```
+pub fn format(
+  template: &str,
+  arguments: &[Object],
+  paren: &Token,
+) -> Result<String, SaturdayResult> {
+  let mut result = String::new();
+  let mut placeholders = 0;
+  let mut chars = template.chars();
+
+  while let Some(ch) = chars.next() {
+    match ch {
+      '{' => {
+        let mut placeholder = String::new();
+        loop {
+          match chars.next() {
+            Some('{') if placeholder.is_empty() => {
+              result.push('{');
+              break;
+            }
+            Some('}') => {
+              let spec = match placeholder.strip_prefix(':') {
+                Some(spec) => Spec::parse(spec),
+                None if placeholder.is_empty() => Spec::parse(""),
+                None => None,
+              };
+              let Some(spec) = spec else {
+                return Err(SaturdayResult::runtime_error(
+                  paren,
+                  &format!("Invalid format placeholder '{{{placeholder}}}'."),
+                ));
+              };
+              // 参数不够时先数完所有占位符，再一起报告
+              if let Some(value) = arguments.get(placeholders) {
+                result.push_str(&spec.render(value));
+              }
+              placeholders += 1;
+              break;
+            }
+            Some(ch) => placeholder.push(ch),
+            None => {
+              return Err(SaturdayResult::runtime_error(
+                paren,
+                "Unclosed '{' in format string.",
+              ))
+            }
+          }
+        }
+      }
+      '}' => {
+        if chars.next() != Some('}') {
+          return Err(SaturdayResult::runtime_error(
+            paren,
+            "Unmatched '}' in format string, use '}}' for a literal brace.",
+          ));
+        }
+        result.push('}');
+      }
+      ch => result.push(ch),
+    }
+  }
+
+  if placeholders != arguments.len() {
+    return Err(SaturdayResult::runtime_error(
+      paren,
+      &format!(
+        "Format string has {} placeholders but got {} arguments.",
+        placeholders,
+        arguments.len()
+      ),
+    ));
+  }
+
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::token_type::TokenType;
+
+  fn fmt(template: &str, arguments: Vec<Object>) -> Option<String> {
+    let paren = Token::new(TokenType::RightParen, ")".to_string(), None, 1);
+    format(template, &arguments, &paren).ok()
+  }
+
+  #[test]
+  fn plain_placeholders_match_display() {
+    assert_eq!(
+      fmt(
+        "{} + {} = {}",
+        vec![Object::Num(1.0), Object::Num(1.5), Object::Num(2.5)]
+      ),
+      Some("1 + 1.5 = 2.5".to_string())
+    );
+    assert_eq!(
+      fmt("{{{}}}", vec![Object::Str("x".to_string())]),
+      Some("{x}".to_string())
+    );
+  }
+
+  #[test]
+  fn width_precision_and_alignment() {
+    assert_eq!(
+      fmt("{:.2}", vec![Object::Num(1.23456)]),
+      Some("1.23".to_string())
+    );
+    assert_eq!(
+      fmt("[{:8.1}]", vec![Object::Num(2.0)]),
+      Some("[     2.0]".to_string())
+    );
+    assert_eq!(
+      fmt("[{:6}]", vec![Object::Str("ab".to_string())]),
+      Some("[ab    ]".to_string())
+    );
+    assert_eq!(
+      fmt("[{:>6}|{:^6}]", vec![Object::Bool(true), Object::Nil]),
+      Some("[  true| nil  ]".to_string())
+    );
+  }
+
+  #[test]
+  fn mismatched_arguments_are_errors() {
+    assert!(fmt("{}", vec![]).is_none());
+    assert!(fmt("", vec![Object::Nil]).is_none());
+    assert!(fmt("{:x}", vec![Object::Nil]).is_none());
+    assert!(fmt("}", vec![]).is_none());
+
+    let paren = Token::new(TokenType::RightParen, ")".to_string(), None, 3);
+    match format("{} {}", &[Object::Num(1.0)], &paren) {
+      Err(SaturdayResult::RuntimeError { token, message, .. }) => {
+        assert_eq!(
+          message,
+          "Format string has 2 placeholders but got 1 arguments."
+        );
+        assert_eq!(token.line, 3);
+      }
+      _ => panic!("expect runtime error"),
+    }
+  }
+}
@@ -0,0 +1,321 @@
+use crate::callable::SaturdayCallable;
+use crate::environment::Environment;
+use crate::object::Object;
+use crate::vm::Upvalue;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// 函数数量增长到上次回收后的这个倍数时自动回收
+const GROWTH_FACTOR: usize = 2;
+const MIN_THRESHOLD: usize = 256;
+
+/// # 环循环回收器
+/// ```
+/// 闭包持有定义它的环境，而函数又保存在这个环境中，形成Rc无法释放的环。
+/// VM的闭包同样会通过捕获的upvalue持有自己（比如递归的局部函数）。
+/// 每个环都经过至少一个用户函数，所以只需要登记函数，环境从函数的closure出发找到，
+/// upvalue从VM闭包的upvalues出发找到。
+///
+/// 回收时先用强引用计数减去图内部的引用，剩下还有引用的节点被图外部（解释器、Rust栈、
+/// 生成器等）持有，是根；从根出发标记，标记不到的环境清空值和enclosing，打断环。
+/// 没有遍历的引用一律当作外部引用，所以只会少回收，不会错误回收
+/// ```
+pub struct Collector {
+  functions: RefCell<Vec<Weak<dyn SaturdayCallable>>>,
+  threshold: Cell<usize>,
+  stats: Cell<GcStats>,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct GcStats {
+  /// 回收的次数
+  pub collections: usize,
+  /// 累计释放的环境和函数
+  pub environments: usize,
+  pub functions: usize,
+  /// 当前登记的存活函数
+  pub tracked: usize,
+}
+
+/// 回收图中的节点，用Rc的地址作为标识
+enum Node {
+  Environment(Rc<RefCell<Environment>>),
+  Function(Rc<dyn SaturdayCallable>),
+  /// VM闭包捕获的upvalue列表，调用帧也会持有它
+  Upvalues(Rc<Vec<Rc<RefCell<Upvalue>>>>),
+  Upvalue(Rc<RefCell<Upvalue>>),
+}
+
+impl Node {
+  fn id(&self) -> usize {
+    match self {
+      Node::Environment(e) => Rc::as_ptr(e) as *const () as usize,
+      Node::Function(f) => Rc::as_ptr(f) as *const () as usize,
+      Node::Upvalues(u) => Rc::as_ptr(u) as *const () as usize,
+      Node::Upvalue(u) => Rc::as_ptr(u) as *const () as usize,
+    }
+  }
+
+  fn strong_count(&self) -> usize {
+    match self {
+      Node::Environment(e) => Rc::strong_count(e),
+      Node::Function(f) => Rc::strong_count(f),
+      Node::Upvalues(u) => Rc::strong_count(u),
+      Node::Upvalue(u) => Rc::strong_count(u),
+    }
+  }
+
+  /// 节点直接持有的其他节点；环境正被借用时返回None，按根处理
+  fn edges(&self) -> Option<Vec<usize>> {
+    match self {
+      Node::Environment(e) => {
+        let e = e.try_borrow().ok()?;
+        let mut edges: Vec<usize> = e
+          .values()
+          .iter()
+          .filter_map(|value| match value {
+            Object::Func(callable) => Some(Rc::as_ptr(&callable.func) as *const () as usize),
+            _ => None,
+          })
+          .collect();
+        if let Some(enclosing) = e.enclosing() {
+          edges.push(Rc::as_ptr(enclosing) as *const () as usize);
+        }
+        Some(edges)
+      }
+      Node::Function(f) => {
+        if let Some(closure) = f.as_closure() {
+          return Some(vec![Rc::as_ptr(&closure.upvalues) as *const () as usize]);
+        }
+        let closure = f.as_function()?.closure();
+        Some(vec![Rc::as_ptr(closure) as *const () as usize])
+      }
+      Node::Upvalues(upvalues) => Some(
+        upvalues
+          .iter()
+          .map(|u| Rc::as_ptr(u) as *const () as usize)
+          .collect(),
+      ),
+      // 还在栈上的upvalue没有边，栈上的值由VM持有
+      Node::Upvalue(u) => match &*u.try_borrow().ok()? {
+        Upvalue::Closed(Object::Func(callable)) => {
+          Some(vec![Rc::as_ptr(&callable.func) as *const () as usize])
+        }
+        _ => Some(Vec::new()),
+      },
+    }
+  }
+}
+
+impl Collector {
+  pub fn new() -> Self {
+    Self {
+      functions: RefCell::new(Vec::new()),
+      threshold: Cell::new(MIN_THRESHOLD),
+      stats: Cell::new(GcStats::default()),
+    }
+  }
+
+  /// 登记新创建的用户函数，数量超过阈值时先回收一次
+  pub fn track(&self, function: &Rc<dyn SaturdayCallable>) {
+    if self.functions.borrow().len() >= self.threshold.get() {
+      self.collect();
+      let live = self.functions.borrow().len();
+      self
+        .threshold
+        .set((live * GROWTH_FACTOR).max(MIN_THRESHOLD));
+    }
+
+    self.functions.borrow_mut().push(Rc::downgrade(function));
+  }
+
+  pub fn stats(&self) -> GcStats {
+    let mut stats = self.stats.get();
+    stats.tracked = self
+      .functions
+      .borrow()
+      .iter()
+      .filter(|f| f.strong_count() > 0)
+      .count();
+    stats
+  }
+
+  /// 回收不可达的环，返回释放的环境和函数的个数
+  pub fn collect(&self) -> usize {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    fn add(node: Node, nodes: &mut Vec<Node>, index: &mut HashMap<usize, usize>) {
+      index.entry(node.id()).or_insert_with(|| {
+        nodes.push(node);
+        nodes.len() - 1
+      });
+    }
+
+    self.functions.borrow_mut().retain(|f| f.strong_count() > 0);
+    let functions: Vec<Rc<dyn SaturdayCallable>> = self
+      .functions
+      .borrow()
+      .iter()
+      .filter_map(Weak::upgrade)
+      .collect();
+    for function in functions {
+      let mut environment = function.as_function().map(|f| Rc::clone(f.closure()));
+      if let Some(closure) = function.as_closure() {
+        for upvalue in closure.upvalues.iter() {
+          add(Node::Upvalue(Rc::clone(upvalue)), &mut nodes, &mut index);
+        }
+        add(
+          Node::Upvalues(Rc::clone(&closure.upvalues)),
+          &mut nodes,
+          &mut index,
+        );
+      }
+      add(Node::Function(function), &mut nodes, &mut index);
+
+      while let Some(e) = environment {
+        environment = e.try_borrow().ok().and_then(|e| e.enclosing().cloned());
+        add(Node::Environment(e), &mut nodes, &mut index);
+      }
+    }
+
+    // 内部引用之外还有引用的节点是根，nodes自己持有的一个引用不算
+    let edges: Vec<Option<Vec<usize>>> = nodes.iter().map(Node::edges).collect();
+    let mut external: Vec<usize> = nodes.iter().map(|n| n.strong_count() - 1).collect();
+    for target in edges.iter().flatten().flatten() {
+      if let Some(&i) = index.get(target) {
+        external[i] -= 1;
+      }
+    }
+
+    let mut reachable = vec![false; nodes.len()];
+    let mut pending: Vec<usize> = (0..nodes.len())
+      .filter(|&i| external[i] > 0 || edges[i].is_none())
+      .collect();
+    while let Some(i) = pending.pop() {
+      if std::mem::replace(&mut reachable[i], true) {
+        continue;
+      }
+
+      for target in edges[i].iter().flatten() {
+        if let Some(&j) = index.get(target) {
+          pending.push(j);
+        }
+      }
+    }
+
+    let mut stats = self.stats.get();
+    stats.collections += 1;
+    // 值在借用结束后才释放，释放时可能会级联释放其他对象
+    let mut garbage = Vec::new();
+    let mut upvalues = Vec::new();
+    for (node, reachable) in nodes.iter().zip(reachable) {
+      match node {
+        _ if reachable => {}
+        Node::Environment(e) => {
+          stats.environments += 1;
+          garbage.push(e.borrow_mut().clear());
+        }
+        Node::Function(_) => stats.functions += 1,
+        Node::Upvalue(u) => {
+          let value = std::mem::replace(&mut *u.borrow_mut(), Upvalue::Closed(Object::Nil));
+          upvalues.push(value);
+        }
+        Node::Upvalues(_) => {}
+      }
+    }
+
+    let before = self.stats.replace(stats);
+    drop(garbage);
+    drop(upvalues);
+    drop(nodes);
+    self.functions.borrow_mut().retain(|f| f.strong_count() > 0);
+    stats.environments + stats.functions - before.environments - before.functions
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::compiler::Compiler;
+  use crate::interpreter::Interpreter;
+  use crate::object::Object;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::scanner::Scanner;
+  use crate::token::Token;
+  use crate::token_type::TokenType;
+  use std::rc::Rc;
+
+  fn run(source: &str) -> Interpreter {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    assert!(interpreter.interpreter(&statements).is_ok());
+    interpreter
+  }
+
+  fn global(interpreter: &Interpreter, name: &str) -> Object {
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+    interpreter.globals.borrow().get(&token).ok().unwrap()
+  }
+
+  #[test]
+  fn collects_local_function_cycles() {
+    let terp = run(
+      "fun make() { def x = 1; fun inner() { return x; } return 0; }
+       def i = 0;
+       while i < 10 { make(); i = i + 1; }
+       def freed = gc();",
+    );
+    // 每次调用留下一个环境和一个inner
+    assert_eq!(global(&terp, "freed"), Object::Num(20.0));
+    let stats = terp.gc.stats();
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.tracked, 1);
+  }
+
+  #[test]
+  fn collects_vm_closure_cycles() {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(
+      "fun make() { fun inner(n) { if n == 0 { return 0; } return inner(n - 1); } return 0; }
+       def i = 0;
+       while i < 10 { make(); i = i + 1; }
+       def freed = gc();"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+    let compiler = Compiler::new(false);
+    let script = compiler.compile(&statements).ok().unwrap();
+    assert!(crate::vm::run(&interpreter, script).is_ok());
+
+    // 每次调用留下一个通过upvalue引用自己的inner
+    assert_eq!(global(&interpreter, "freed"), Object::Num(10.0));
+    assert_eq!(interpreter.gc.stats().tracked, 1);
+  }
+
+  #[test]
+  fn keeps_reachable_closures() {
+    let terp = run(
+      "fun counter() { def n = 0; fun inc() { n = n + 1; return n; } return inc; }
+       def c = counter();
+       c();
+       def freed = gc();
+       def two = c();
+       def stats = gc_stats();",
+    );
+    assert_eq!(global(&terp, "freed"), Object::Num(0.0));
+    assert_eq!(global(&terp, "two"), Object::Num(2.0));
+    assert_eq!(
+      global(&terp, "stats"),
+      Object::Str(
+        "collections: 1, freed environments: 0, freed functions: 0, tracked functions: 2"
+          .to_string()
+      )
+    );
+  }
+}
@@ -0,0 +1,725 @@
+use crate::environment::Environment;
+use crate::error::SaturdayResult;
+use crate::expr::{BinaryExpr, CallExpr, Expr, GetExpr, ListExpr, LogicalExpr, UnaryExpr};
+use crate::fiber::{Channel, FiberOp};
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::stmt::{AssertStmt, ForInStmt, IfStmt, MatchStmt, Stmt, WhileStmt};
+use crate::token::Token;
+use crate::token_type::TokenType;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// 生成器的执行帧
+/// ```
+/// 生成器函数体不走递归的execute_block，而是用显式的帧栈执行，
+/// 这样遇到yield时可以直接返回，下次next()时从栈顶继续
+/// ```
+enum Frame {
+  Block {
+    statements: Rc<Vec<Rc<Stmt>>>,
+    index: usize,
+    environment: Rc<RefCell<Environment>>,
+  },
+  While {
+    stmt: Rc<WhileStmt>,
+    environment: Rc<RefCell<Environment>>,
+  },
+  ForIn {
+    stmt: Rc<ForInStmt>,
+    iterator: Rc<Generator>,
+    environment: Rc<RefCell<Environment>>,
+  },
+}
+
+/// # 求值任务
+/// ```
+/// 语句中的表达式拆成任务栈逐个执行，中间结果放在值栈上。
+/// 协程中含有调用的表达式按节点拆开，recv()和yield_now()出现在任何位置都可以挂起，
+/// 恢复时从任务栈顶继续；其他表达式整体求值。
+/// 栈底的任务用求出的值完成整条语句
+/// ```
+enum Task {
+  Eval(Rc<Expr>),
+  Value(Object),
+  Assign(Rc<Expr>, Token),
+  Binary(Rc<BinaryExpr>),
+  Logical(Rc<LogicalExpr>),
+  Unary(Rc<UnaryExpr>),
+  List(Rc<ListExpr>),
+  /// 可选链中的一环，链的末尾是ChainEnd
+  Link(Rc<Expr>),
+  ChainEnd,
+  Get(Rc<GetExpr>),
+  Arguments(Rc<CallExpr>),
+  Call(Rc<CallExpr>),
+  Discard,
+  Define(Token),
+  Print,
+  Return,
+  Yield,
+  Branch(Rc<IfStmt>),
+  Loop(Rc<WhileStmt>),
+  Iterate(Rc<ForInStmt>),
+  Select(Rc<MatchStmt>),
+  Assert(Rc<AssertStmt>),
+}
+
+/// 一条语句的求值状态，协程挂起时整个保存下来
+struct Evaluation {
+  tasks: Vec<Task>,
+  values: Vec<Object>,
+  environment: Rc<RefCell<Environment>>,
+  /// 在recv上阻塞时等待的通道和调用位置
+  waiting: Option<(Rc<Channel>, Token)>,
+}
+
+/// 单次恢复执行的结果
+pub enum Step {
+  Yielded(Object),
+  /// 协程在recv上阻塞，Token为recv调用的位置
+  Blocked(Rc<Channel>, Token),
+  Done,
+}
+
+pub struct Generator {
+  name: Token,
+  frames: RefCell<Vec<Frame>>,
+  pending: RefCell<Option<Evaluation>>,
+}
+
+impl Generator {
+  pub fn new(
+    name: &Token,
+    body: &Rc<Vec<Rc<Stmt>>>,
+    environment: Rc<RefCell<Environment>>,
+  ) -> Self {
+    Self {
+      name: name.dup(),
+      frames: RefCell::new(vec![Frame::Block {
+        statements: Rc::clone(body),
+        index: 0,
+        environment,
+      }]),
+      pending: RefCell::new(None),
+    }
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.frames.try_borrow_mut().is_err()
+  }
+
+  /// # 恢复执行直到下一个yield
+  /// ```
+  /// 返回Some(value)表示产出一个值，None表示生成器已结束
+  /// ```
+  pub fn resume(&self, interpreter: &Interpreter) -> Result<Option<Object>, SaturdayResult> {
+    match self.step(interpreter, false)? {
+      Step::Yielded(value) => Ok(Some(value)),
+      Step::Done | Step::Blocked(..) => Ok(None),
+    }
+  }
+
+  /// # 恢复执行一步
+  /// ```
+  /// fiber为true时按协程执行：yield只是让出，
+  /// 函数体中直接写出的yield_now()/send()/recv()调用会挂起当前协程
+  /// ```
+  pub fn step(&self, interpreter: &Interpreter, fiber: bool) -> Result<Step, SaturdayResult> {
+    let mut frames = match self.frames.try_borrow_mut() {
+      Ok(frames) => frames,
+      Err(_) => {
+        return Err(SaturdayResult::runtime_error(
+          &self.name,
+          "Generator is already running.",
+        ))
+      }
+    };
+
+    let mut pending = self.pending.borrow_mut();
+    let result = interpreter.nested(&self.name, || {
+      interpreter.in_frame(&self.name, || {
+        Generator::run(&mut frames, &mut pending, interpreter, fiber)
+      })
+    });
+    if !matches!(result, Ok(Step::Yielded(_)) | Ok(Step::Blocked(..))) {
+      frames.clear();
+      *pending = None;
+    }
+
+    result
+  }
+
+  fn run(
+    frames: &mut Vec<Frame>,
+    pending: &mut Option<Evaluation>,
+    interpreter: &Interpreter,
+    fiber: bool,
+  ) -> Result<Step, SaturdayResult> {
+    if let Some(mut evaluation) = pending.take() {
+      if let Some((channel, token)) = evaluation.waiting.take() {
+        match channel.try_recv() {
+          Some(value) => evaluation.values.push(value),
+          None => {
+            let step = Step::Blocked(Rc::clone(&channel), token.dup());
+            evaluation.waiting = Some((channel, token));
+            *pending = Some(evaluation);
+            return Ok(step);
+          }
+        }
+      }
+
+      if let Some(step) = Generator::evaluate(evaluation, frames, pending, interpreter, fiber)? {
+        return Ok(step);
+      }
+    }
+
+    while let Some(frame) = frames.last_mut() {
+      let (tasks, environment) = match frame {
+        Frame::Block {
+          statements,
+          index,
+          environment,
+        } => {
+          let Some(stmt) = statements.get(*index).cloned() else {
+            frames.pop();
+            continue;
+          };
+          *index += 1;
+          let environment = Rc::clone(environment);
+
+          match &*stmt {
+            Stmt::Break(_) => {
+              while let Some(frame) = frames.pop() {
+                if matches!(frame, Frame::While { .. } | Frame::ForIn { .. }) {
+                  break;
+                }
+              }
+              continue;
+            }
+            Stmt::Block(stmt) => {
+              frames.push(Frame::Block {
+                statements: Rc::clone(&stmt.statements),
+                index: 0,
+                environment: Rc::new(RefCell::new(Environment::new_with_enclosing(environment))),
+              });
+              continue;
+            }
+            Stmt::While(stmt) => {
+              frames.push(Frame::While {
+                stmt: Rc::clone(stmt),
+                environment,
+              });
+              continue;
+            }
+            _ => {}
+          }
+
+          match Generator::statement_tasks(&stmt, interpreter, fiber) {
+            Some(tasks) => {
+              interpreter.consume_fuel()?;
+              (tasks, environment)
+            }
+            None => {
+              interpreter.execute_in(stmt, environment)?;
+              continue;
+            }
+          }
+        }
+        Frame::While { stmt, environment } => {
+          // 循环体中的yield等语句由这里直接处理，不经过execute，每轮循环在这里计一步并检查中断
+          interpreter.consume_fuel()?;
+          interpreter.poll_interrupt()?;
+          let tasks = vec![
+            Task::Loop(Rc::clone(stmt)),
+            Task::Eval(Rc::clone(&stmt.condition)),
+          ];
+          (tasks, Rc::clone(environment))
+        }
+        Frame::ForIn {
+          stmt,
+          iterator,
+          environment,
+        } => {
+          let stmt = Rc::clone(stmt);
+          let environment = Rc::clone(environment);
+          match iterator.resume(interpreter)? {
+            Some(value) => {
+              let mut e = Environment::new_with_enclosing(environment);
+              e.define(stmt.name.symbol, value);
+              frames.push(Frame::Block {
+                statements: Rc::new(vec![Rc::clone(&stmt.body)]),
+                index: 0,
+                environment: Rc::new(RefCell::new(e)),
+              });
+            }
+            None => {
+              frames.pop();
+            }
+          }
+          continue;
+        }
+      };
+
+      let evaluation = Evaluation {
+        tasks,
+        values: Vec::new(),
+        environment,
+        waiting: None,
+      };
+      if let Some(step) = Generator::evaluate(evaluation, frames, pending, interpreter, fiber)? {
+        return Ok(step);
+      }
+    }
+
+    Ok(Step::Done)
+  }
+
+  /// # 语句的求值任务
+  /// ```
+  /// yield、return和控制流语句总是由帧栈完成；
+  /// 表达式、def、print和assert只在协程中、并且含有调用时才拆开，否则返回None交给execute
+  /// ```
+  fn statement_tasks(stmt: &Stmt, interpreter: &Interpreter, fiber: bool) -> Option<Vec<Task>> {
+    let (finish, expr) = match stmt {
+      Stmt::Yield(stmt) => (Task::Yield, stmt.value.clone()),
+      Stmt::Return(stmt) => (Task::Return, stmt.value.clone()),
+      Stmt::If(stmt) => (
+        Task::Branch(Rc::clone(stmt)),
+        Some(Rc::clone(&stmt.condition)),
+      ),
+      Stmt::Match(stmt) => (
+        Task::Select(Rc::clone(stmt)),
+        Some(Rc::clone(&stmt.subject)),
+      ),
+      Stmt::ForIn(stmt) => (
+        Task::Iterate(Rc::clone(stmt)),
+        Some(Rc::clone(&stmt.iterable)),
+      ),
+      _ if !fiber => return None,
+      Stmt::Expression(stmt) if contains_call(&stmt.expression) => {
+        (Task::Discard, Some(Rc::clone(&stmt.expression)))
+      }
+      Stmt::Def(stmt) if stmt.initializer.as_deref().is_some_and(contains_call) => {
+        (Task::Define(stmt.name.dup()), stmt.initializer.clone())
+      }
+      Stmt::Print(stmt) if contains_call(&stmt.expression) => {
+        (Task::Print, Some(Rc::clone(&stmt.expression)))
+      }
+      Stmt::Assert(stmt) if !interpreter.strip_asserts() && contains_call(&stmt.condition) => {
+        // 二元条件分别对两边求值，失败时才能报告两边的值
+        let mut tasks = vec![Task::Assert(Rc::clone(stmt))];
+        match &*stmt.condition {
+          Expr::Binary(binary) => {
+            tasks.push(Task::Eval(Rc::clone(&binary.right)));
+            tasks.push(Task::Eval(Rc::clone(&binary.left)));
+          }
+          _ => tasks.push(Task::Eval(Rc::clone(&stmt.condition))),
+        }
+        return Some(tasks);
+      }
+      _ => return None,
+    };
+
+    Some(vec![
+      finish,
+      match expr {
+        Some(expr) => Task::Eval(expr),
+        None => Task::Value(Object::Nil),
+      },
+    ])
+  }
+
+  /// # 执行求值任务
+  /// ```
+  /// 返回Some(step)时生成器让出或结束；在recv或yield_now处挂起时把求值状态存入pending
+  /// ```
+  fn evaluate(
+    mut evaluation: Evaluation,
+    frames: &mut Vec<Frame>,
+    pending: &mut Option<Evaluation>,
+    interpreter: &Interpreter,
+    fiber: bool,
+  ) -> Result<Option<Step>, SaturdayResult> {
+    let environment = Rc::clone(&evaluation.environment);
+    let tasks = &mut evaluation.tasks;
+    let values = &mut evaluation.values;
+    while let Some(task) = tasks.pop() {
+      match task {
+        Task::Eval(expr) if !fiber || !contains_call(&expr) => {
+          values.push(interpreter.evaluate_in(expr, Rc::clone(&environment))?);
+        }
+        Task::Eval(expr) => match &*expr {
+          Expr::Assign(assign) => {
+            tasks.push(Task::Assign(Rc::clone(&expr), assign.name.dup()));
+            tasks.push(Task::Eval(Rc::clone(&assign.value)));
+          }
+          Expr::Binary(binary) => {
+            tasks.push(Task::Binary(Rc::clone(binary)));
+            tasks.push(Task::Eval(Rc::clone(&binary.right)));
+            tasks.push(Task::Eval(Rc::clone(&binary.left)));
+          }
+          Expr::Call(_) | Expr::Get(_) => {
+            tasks.push(Task::ChainEnd);
+            tasks.push(Task::Link(Rc::clone(&expr)));
+          }
+          Expr::Grouping(grouping) => tasks.push(Task::Eval(Rc::clone(&grouping.expression))),
+          Expr::Logical(logical) => {
+            tasks.push(Task::Logical(Rc::clone(logical)));
+            tasks.push(Task::Eval(Rc::clone(&logical.left)));
+          }
+          Expr::Unary(unary) => {
+            tasks.push(Task::Unary(Rc::clone(unary)));
+            tasks.push(Task::Eval(Rc::clone(&unary.right)));
+          }
+          Expr::List(list) => {
+            tasks.push(Task::List(Rc::clone(list)));
+            for element in list.elements.iter().rev() {
+              tasks.push(Task::Eval(Rc::clone(element)));
+            }
+          }
+          Expr::Literal(_) | Expr::Variable(_) => {
+            values.push(interpreter.evaluate_in(Rc::clone(&expr), Rc::clone(&environment))?);
+          }
+        },
+        Task::Value(value) => values.push(value),
+        Task::Assign(wrapper, name) => {
+          let value = values.last().cloned().unwrap();
+          interpreter.assign_in(wrapper, &name, value, Rc::clone(&environment))?;
+        }
+        Task::Binary(binary) => {
+          let right = values.pop().unwrap();
+          let left = values.pop().unwrap();
+          values.push(interpreter.binary(&binary.operator, left, right)?);
+        }
+        Task::Logical(logical) => {
+          let left = values.last().unwrap();
+          let short_circuit = match logical.operator.token_type() {
+            TokenType::QuestionQuestion => *left != Object::Nil,
+            TokenType::Or => interpreter.is_truthy(left),
+            _ => !interpreter.is_truthy(left),
+          };
+          if !short_circuit {
+            values.pop();
+            tasks.push(Task::Eval(Rc::clone(&logical.right)));
+          }
+        }
+        Task::Unary(unary) => {
+          let right = values.pop().unwrap();
+          values.push(interpreter.unary(&unary.operator, right)?);
+        }
+        Task::List(list) => {
+          let elements = values.split_off(values.len() - list.elements.len());
+          values.push(Object::List(Rc::new(elements)));
+        }
+        Task::Link(expr) => match &*expr {
+          Expr::Call(call) => {
+            tasks.push(Task::Arguments(Rc::clone(call)));
+            tasks.push(Task::Link(Rc::clone(&call.callee)));
+          }
+          Expr::Get(get) if contains_call(&get.object) => {
+            tasks.push(Task::Get(Rc::clone(get)));
+            tasks.push(Task::Link(Rc::clone(&get.object)));
+          }
+          Expr::Get(_) => {
+            let link = interpreter.with_environment(Rc::clone(&environment), || {
+              interpreter.optional_chain(&expr)
+            })?;
+            match link {
+              Some(value) => values.push(value),
+              None => short_circuit(tasks, values),
+            }
+          }
+          _ => tasks.push(Task::Eval(expr)),
+        },
+        Task::ChainEnd => {}
+        Task::Get(get) => {
+          let object = values.pop().unwrap();
+          if get.optional && object == Object::Nil {
+            short_circuit(tasks, values);
+          } else {
+            values.push(interpreter.get_property(object, &get.name)?);
+          }
+        }
+        Task::Arguments(call) => {
+          // ?.调用的callee为nil时不再对参数求值，整条链的结果就是nil
+          if call.optional && values.last() == Some(&Object::Nil) {
+            values.pop();
+            short_circuit(tasks, values);
+          } else {
+            tasks.push(Task::Call(Rc::clone(&call)));
+            for argument in call.arguments.iter().rev() {
+              tasks.push(Task::Eval(Rc::clone(argument)));
+            }
+          }
+        }
+        Task::Call(call) => {
+          let mut arguments = values.split_off(values.len() - call.arguments.len());
+          let callee = values.pop().unwrap();
+          let function = interpreter.check_call(&call.paren, callee, arguments.len())?;
+          let Some(op) = function.func.fiber_op() else {
+            let value = interpreter.with_environment(Rc::clone(&environment), || {
+              interpreter.call_at(&call.paren, || {
+                function.func.call(interpreter, &call.paren, arguments)
+              })
+            })?;
+            values.push(value);
+            continue;
+          };
+          interpreter.charge_call(&call.paren)?;
+
+          let channel = match (op, arguments.first()) {
+            (FiberOp::YieldNow, _) => {
+              values.push(Object::Nil);
+              *pending = Some(evaluation);
+              return Ok(Some(Step::Yielded(Object::Nil)));
+            }
+            (_, Some(Object::Channel(channel))) => Rc::clone(channel),
+            (op, other) => {
+              let name = if op == FiberOp::Send { "send" } else { "recv" };
+              return Err(SaturdayResult::runtime_error(
+                &call.paren,
+                &format!(
+                  "{name}() expects a channel, got {}.",
+                  other.unwrap_or(&Object::Nil)
+                ),
+              ));
+            }
+          };
+
+          // send不会阻塞，结果是nil
+          if op == FiberOp::Send {
+            channel.send(arguments.pop().unwrap());
+            values.push(Object::Nil);
+            continue;
+          }
+
+          match channel.try_recv() {
+            Some(value) => values.push(value),
+            None => {
+              let step = Step::Blocked(Rc::clone(&channel), call.paren.dup());
+              evaluation.waiting = Some((channel, call.paren.dup()));
+              *pending = Some(evaluation);
+              return Ok(Some(step));
+            }
+          }
+        }
+        Task::Discard => {
+          values.pop();
+        }
+        Task::Define(name) => {
+          let value = values.pop().unwrap();
+          environment.borrow_mut().define(name.symbol, value);
+        }
+        Task::Print => println!("{}", values.pop().unwrap()),
+        // 返回值被丢弃，但表达式已经求值，保留了副作用
+        Task::Return => return Ok(Some(Step::Done)),
+        Task::Yield => return Ok(Some(Step::Yielded(values.pop().unwrap()))),
+        Task::Branch(stmt) => {
+          let branch = if interpreter.is_truthy(&values.pop().unwrap()) {
+            Some(Rc::clone(&stmt.then_branch))
+          } else {
+            stmt.else_branch.clone()
+          };
+
+          if let Some(branch) = branch {
+            frames.push(Frame::Block {
+              statements: Rc::new(vec![branch]),
+              index: 0,
+              environment: Rc::clone(&environment),
+            });
+          }
+        }
+        Task::Loop(stmt) => {
+          if interpreter.is_truthy(&values.pop().unwrap()) {
+            frames.push(Frame::Block {
+              statements: Rc::new(vec![Rc::clone(&stmt.body)]),
+              index: 0,
+              environment: Rc::clone(&environment),
+            });
+          } else {
+            frames.pop();
+          }
+        }
+        Task::Iterate(stmt) => {
+          let Object::Generator(iterator) = values.pop().unwrap() else {
+            return Err(SaturdayResult::runtime_error(
+              &stmt.name,
+              "Can only iterate over generators.",
+            ));
+          };
+
+          frames.push(Frame::ForIn {
+            stmt,
+            iterator,
+            environment: Rc::clone(&environment),
+          });
+        }
+        Task::Select(stmt) => {
+          let subject = values.pop().unwrap();
+          let arm = interpreter.with_environment(Rc::clone(&environment), || {
+            interpreter.select_arm(&stmt, subject)
+          })?;
+          if let Some((body, environment)) = arm {
+            frames.push(Frame::Block {
+              statements: Rc::new(vec![body]),
+              index: 0,
+              environment,
+            });
+          }
+        }
+        Task::Assert(stmt) => {
+          let (passed, operands) = match &*stmt.condition {
+            Expr::Binary(binary) => {
+              let right = values.pop().unwrap();
+              let left = values.pop().unwrap();
+              let result = interpreter.binary(&binary.operator, left.clone(), right.clone())?;
+              (interpreter.is_truthy(&result), Some((left, right)))
+            }
+            _ => (interpreter.is_truthy(&values.pop().unwrap()), None),
+          };
+
+          if !passed {
+            let extra = match stmt.message.clone() {
+              Some(extra) => Some(interpreter.evaluate_in(extra, Rc::clone(&environment))?),
+              None => None,
+            };
+            return Err(interpreter.assert_failure(&stmt, operands, extra));
+          }
+        }
+      }
+    }
+
+    Ok(None)
+  }
+}
+
+/// 可选链短路：丢弃链中剩下的任务，整条链的结果为nil
+fn short_circuit(tasks: &mut Vec<Task>, values: &mut Vec<Object>) {
+  while !matches!(tasks.pop(), Some(Task::ChainEnd) | None) {}
+  values.push(Object::Nil);
+}
+
+/// 表达式中是否有调用，没有调用的表达式不会挂起，可以整体求值
+fn contains_call(expr: &Expr) -> bool {
+  match expr {
+    Expr::Call(_) => true,
+    Expr::Assign(assign) => contains_call(&assign.value),
+    Expr::Binary(binary) => contains_call(&binary.left) || contains_call(&binary.right),
+    Expr::Get(get) => contains_call(&get.object),
+    Expr::Grouping(grouping) => contains_call(&grouping.expression),
+    Expr::Logical(logical) => contains_call(&logical.left) || contains_call(&logical.right),
+    Expr::Unary(unary) => contains_call(&unary.right),
+    Expr::List(list) => list.elements.iter().any(|e| contains_call(e)),
+    Expr::Literal(_) | Expr::Variable(_) => false,
+  }
+}
+
+impl Debug for Generator {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{self}")
+  }
+}
+
+impl Display for Generator {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<generator {}>", self.name.as_string())
+  }
+}
+
+impl PartialEq for Generator {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::scanner::Scanner;
+  use crate::token_type::TokenType;
+
+  fn run(source: &str) -> Interpreter {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    assert!(interpreter.interpreter(&statements).is_ok());
+    interpreter
+  }
+
+  fn global(interpreter: &Interpreter, name: &str) -> Object {
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+    interpreter.globals.borrow().get(&token).ok().unwrap()
+  }
+
+  #[test]
+  fn resumes_after_each_yield() {
+    let terp = run("fun* g() { def i = 0; while true { i = i + 1; if i > 2 { break; } yield i; } yield \"end\"; }
+       def it = g();");
+    let Object::Generator(it) = global(&terp, "it") else {
+      panic!("expect generator");
+    };
+    assert_eq!(it.resume(&terp).ok(), Some(Some(Object::Num(1.0))));
+    assert_eq!(it.resume(&terp).ok(), Some(Some(Object::Num(2.0))));
+    assert_eq!(
+      it.resume(&terp).ok(),
+      Some(Some(Object::Str("end".to_string())))
+    );
+    assert_eq!(it.resume(&terp).ok(), Some(None));
+    assert_eq!(it.resume(&terp).ok(), Some(None));
+  }
+
+  #[test]
+  fn for_in_drains_nested_generators() {
+    let terp = run(
+      "fun* count(n) { def i = 0; while i < n { yield i; i = i + 1; } }
+       fun* double(g) { for x in g { yield x * 2; } }
+       def sum = 0;
+       for v in double(count(4)) { sum = sum + v; }",
+    );
+    assert_eq!(global(&terp, "sum"), Object::Num(12.0));
+  }
+
+  #[test]
+  fn nested_generators_count_toward_call_depth() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_max_call_depth(100);
+    let mut scanner = Scanner::new(
+      "fun* g(n) { if n > 0 { for x in g(n - 1) { yield x; } } yield n; }
+       def total = 0;
+       for v in g(500) { total = total + v; }"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    match interpreter.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError { message, .. }) => assert_eq!(message, "Stack overflow."),
+      _ => panic!("expect stack overflow"),
+    }
+  }
+
+  #[test]
+  fn next_reports_non_generators_at_the_call() {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new("def x = 1;\ndef y = next(3);".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    match interpreter.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError { token, message, .. }) => {
+        assert_eq!(message, "next() expects a generator, got 3.");
+        assert_eq!(token.line, 2);
+      }
+      _ => panic!("expect runtime error"),
+    }
+  }
+}
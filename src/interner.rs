@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// # 符号
+/// ```
+/// 驻留后的字符串，同样的内容对应同一个符号，比较和哈希都只需要一个整数。
+/// 扫描时每个token的lexeme都会被驻留，环境和resolver用符号代替名字作为键，
+/// token也只保存符号，需要文本时再从表中取回。
+///
+/// 符号在整个线程中一直有效，所以表只增不减：占用的内存取决于源码中出现过多少种不同的lexeme，
+/// REPL中反复输入同样的名字不会增长，不断输入新的名字和字面量会一直增长。
+/// 运行时产生的字符串值（拼接、方法的结果）不驻留，否则表会随程序运行无限增长；
+/// 字符串字面量的值也因此仍然是普通的Object::Str，和运行时的字符串一样处理
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+  symbols: HashMap<Rc<str>, Symbol>,
+  strings: Vec<Rc<str>>,
+}
+
+thread_local! {
+  static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+/// 取得text对应的符号，第一次出现时登记
+pub fn intern(text: &str) -> Symbol {
+  INTERNER.with(|interner| {
+    let mut interner = interner.borrow_mut();
+    if let Some(symbol) = interner.symbols.get(text) {
+      return *symbol;
+    }
+
+    let symbol = Symbol(interner.strings.len() as u32);
+    let text: Rc<str> = Rc::from(text);
+    interner.strings.push(Rc::clone(&text));
+    interner.symbols.insert(text, symbol);
+    symbol
+  })
+}
+
+impl Symbol {
+  pub fn as_str(&self) -> Rc<str> {
+    INTERNER.with(|interner| Rc::clone(&interner.borrow().strings[self.0 as usize]))
+  }
+}
+
+impl Display for Symbol {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl Debug for Symbol {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_text_gives_same_symbol() {
+    let a = intern("counter");
+    let b = intern(&String::from("counter"));
+    assert_eq!(a, b);
+    assert_ne!(a, intern("Counter"));
+    assert_eq!(&*a.as_str(), "counter");
+  }
+}
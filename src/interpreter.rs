@@ -1,27 +1,94 @@
-use crate::callable::Callable;
+use crate::callable::{Callable, SaturdayCallable};
 use crate::environment::Environment;
 use crate::error::SaturdayResult;
 use crate::expr::*;
-use crate::native_functions::NativeClock;
+use crate::fiber::Scheduler;
+use crate::gc::Collector;
+use crate::interner::{intern, Symbol};
+use crate::memory;
+use crate::native_functions::native_globals;
+use crate::native_methods;
 use crate::object::*;
+use crate::saturday_enum::SaturdayEnum;
 use crate::saturday_function::SaturdayFunction;
+use crate::saturday_record::RecordConstructor;
 use crate::stmt::{
-  BlockStmt, BreakStmt, DefStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt,
-  StmtVisitor, WhileStmt,
+  AssertStmt, BlockStmt, BreakStmt, DefStmt, EnumStmt, ExpressionStmt, ForInStmt, FunctionStmt,
+  IfStmt, MatchStmt, PrintStmt, RecordStmt, ReturnStmt, Stmt, StmtVisitor, WhileStmt, YieldStmt,
 };
 use crate::token::Token;
 use crate::token_type::TokenType;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// 默认的最大调用深度，主线程的栈按这个深度预留
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/// 选中的match分支体以及绑定了模式变量的环境
+pub type SelectedArm = (Rc<Stmt>, Rc<RefCell<Environment>>);
 
 pub struct Interpreter {
   pub globals: Rc<RefCell<Environment>>,
   environment: RefCell<Rc<RefCell<Environment>>>,
-  locals: RefCell<HashMap<Rc<Expr>, usize>>,
+  /// resolver解析出的局部变量：(环境的距离, 环境中的槽)
+  locals: RefCell<HashMap<Rc<Expr>, (usize, usize)>>,
+  tail_calls: RefCell<HashSet<Rc<Expr>>>,
+  pub scheduler: Scheduler,
+  pub gc: Collector,
+  /// 为true时assert语句不执行
+  strip_asserts: bool,
+  /// 当前嵌套的调用层数，超过max_call_depth时报Stack overflow，不让Rust栈溢出
+  call_depth: Cell<usize>,
+  max_call_depth: usize,
+  /// 正在执行的函数，最底下一帧是顶层脚本；每帧记录它最近一次发起调用的行
+  frames: RefCell<Vec<Frame>>,
+  /// 剩余的执行步数，每执行一条语句或一次调用消耗一步；None表示不限制
+  fuel: Cell<Option<u64>>,
+  /// 中断标志，可以从其他线程或信号处理函数置位
+  interrupt: Arc<AtomicBool>,
+  /// 堆内存的上限（字节），None表示不限制
+  memory_limit: Cell<Option<usize>>,
+}
+
+struct Frame {
+  function: Option<Symbol>,
+  line: usize,
 }
 
 impl StmtVisitor<()> for Interpreter {
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<(), SaturdayResult> {
+    if self.strip_asserts {
+      return Ok(());
+    }
+
+    // 条件是二元表达式时分别求值两边，失败时一起报告
+    let (passed, operands) = match &*stmt.condition {
+      Expr::Binary(binary) => {
+        let left = self.evaluate(binary.left.clone())?;
+        let right = self.evaluate(binary.right.clone())?;
+        let result = self.binary(&binary.operator, left.clone(), right.clone())?;
+        (self.is_truthy(&result), Some((left, right)))
+      }
+      _ => {
+        let result = self.evaluate(stmt.condition.clone())?;
+        (self.is_truthy(&result), None)
+      }
+    };
+
+    if passed {
+      return Ok(());
+    }
+
+    let extra = match stmt.message.clone() {
+      Some(extra) => Some(self.evaluate(extra)?),
+      None => None,
+    };
+    Err(self.assert_failure(stmt, operands, extra))
+  }
+
   fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<(), SaturdayResult> {
     let e = Environment::new_with_enclosing(self.environment.borrow().clone());
     self.execute_block(&stmt.statements, e)
@@ -31,6 +98,27 @@ impl StmtVisitor<()> for Interpreter {
     Err(SaturdayResult::Break)
   }
 
+  fn visit_enum_stmt(&self, _: Rc<Stmt>, stmt: &EnumStmt) -> Result<(), SaturdayResult> {
+    let value = SaturdayEnum::new(&stmt.name, &stmt.variants);
+    self
+      .environment
+      .borrow()
+      .borrow_mut()
+      .define(stmt.name.symbol, Object::Enum(Rc::new(value)));
+    Ok(())
+  }
+
+  fn visit_record_stmt(&self, _: Rc<Stmt>, stmt: &RecordStmt) -> Result<(), SaturdayResult> {
+    let constructor = RecordConstructor::new(&stmt.name, &stmt.fields);
+    self.environment.borrow().borrow_mut().define(
+      stmt.name.symbol,
+      Object::Func(Callable {
+        func: Rc::new(constructor),
+      }),
+    );
+    Ok(())
+  }
+
   fn visit_expression_stmt(
     &self,
     _: Rc<Stmt>,
@@ -40,14 +128,37 @@ impl StmtVisitor<()> for Interpreter {
     Ok(())
   }
 
+  fn visit_forin_stmt(&self, _: Rc<Stmt>, stmt: &ForInStmt) -> Result<(), SaturdayResult> {
+    let Object::Generator(iterator) = self.evaluate(stmt.iterable.clone())? else {
+      return Err(SaturdayResult::runtime_error(
+        &stmt.name,
+        "Can only iterate over generators.",
+      ));
+    };
+
+    let body = Rc::new(vec![stmt.body.clone()]);
+    while let Some(value) = iterator.resume(self)? {
+      let mut e = Environment::new_with_enclosing(self.environment.borrow().clone());
+      e.define(stmt.name.symbol, value);
+      match self.execute_block(&body, e) {
+        Err(SaturdayResult::Break) => break,
+        Err(e) => return Err(e),
+        Ok(_) => {}
+      }
+    }
+
+    Ok(())
+  }
+
   fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
     let function = SaturdayFunction::new(&Rc::new(stmt), &self.environment.borrow());
-    self.environment.borrow().borrow_mut().define(
-      &stmt.name.as_string(),
-      Object::Func(Callable {
-        func: Rc::new(function),
-      }),
-    );
+    let func: Rc<dyn SaturdayCallable> = Rc::new(function);
+    self.gc.track(&func);
+    self
+      .environment
+      .borrow()
+      .borrow_mut()
+      .define(stmt.name.symbol, Object::Func(Callable { func }));
     Ok(())
   }
 
@@ -61,6 +172,14 @@ impl StmtVisitor<()> for Interpreter {
     }
   }
 
+  fn visit_match_stmt(&self, _: Rc<Stmt>, stmt: &MatchStmt) -> Result<(), SaturdayResult> {
+    let subject = self.evaluate(stmt.subject.clone())?;
+    match self.select_arm(stmt, subject)? {
+      Some((body, environment)) => self.execute_in(body, environment),
+      None => Ok(()),
+    }
+  }
+
   fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), SaturdayResult> {
     let value = self.evaluate(stmt.expression.clone())?;
     println!("{value}");
@@ -69,6 +188,19 @@ impl StmtVisitor<()> for Interpreter {
 
   fn visit_return_stmt(&self, _wrapper: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), SaturdayResult> {
     if let Some(value) = stmt.value.clone() {
+      if self.tail_calls.borrow().contains(&value) {
+        if let Expr::Call(call) = &*value {
+          return Err(match self.prepare_call(call)? {
+            Some((callee, arguments)) => SaturdayResult::TailCall {
+              callee,
+              paren: call.paren.dup(),
+              arguments,
+            },
+            None => SaturdayResult::return_value(Object::Nil),
+          });
+        }
+      }
+
       Err(SaturdayResult::return_value(self.evaluate(value)?))
     } else {
       Err(SaturdayResult::return_value(Object::Nil))
@@ -86,7 +218,7 @@ impl StmtVisitor<()> for Interpreter {
       .environment
       .borrow()
       .borrow_mut()
-      .define(&stmt.name.as_string(), value);
+      .define(stmt.name.symbol, value);
     Ok(())
   }
 
@@ -97,10 +229,19 @@ impl StmtVisitor<()> for Interpreter {
         Err(e) => return Err(e),
         Ok(_) => {}
       }
+      self.poll_interrupt()?;
     }
 
     Ok(())
   }
+
+  fn visit_yield_stmt(&self, _: Rc<Stmt>, stmt: &YieldStmt) -> Result<(), SaturdayResult> {
+    // 生成器函数体由Generator的帧栈执行，正常不会走到这里
+    Err(SaturdayResult::runtime_error(
+      &stmt.keyword,
+      "Can't yield outside of a generator function.",
+    ))
+  }
 }
 
 impl ExprVisitor<Object> for Interpreter {
@@ -110,28 +251,421 @@ impl ExprVisitor<Object> for Interpreter {
     expr: &AssignExpr,
   ) -> Result<Object, SaturdayResult> {
     let value = self.evaluate(expr.value.clone())?;
-    if let Some(distance) = self.locals.borrow().get(&wrapper) {
-      self
-        .environment
-        .borrow()
-        .borrow_mut()
-        .assign_at(*distance, &expr.name, value.clone())?;
-    } else {
-      self
-        .globals
-        .borrow_mut()
-        .assign(&expr.name, value.clone())?;
-    }
-
+    self.assign_variable(wrapper, &expr.name, value.clone())?;
     Ok(value)
   }
 
   fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<Object, SaturdayResult> {
     let left = self.evaluate(expr.left.clone())?;
     let right = self.evaluate(expr.right.clone())?;
-    let op = expr.operator.token_type();
+    self.binary(&expr.operator, left, right)
+  }
 
-    let result = match (left, right) {
+  fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Object, SaturdayResult> {
+    Ok(self.call_link(expr)?.unwrap_or(Object::Nil))
+  }
+
+  fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<Object, SaturdayResult> {
+    Ok(self.get_link(expr)?.unwrap_or(Object::Nil))
+  }
+
+  fn visit_grouping_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &GroupingExpr,
+  ) -> Result<Object, SaturdayResult> {
+    self.evaluate(expr.expression.clone())
+  }
+
+  fn visit_list_expr(&self, _: Rc<Expr>, expr: &ListExpr) -> Result<Object, SaturdayResult> {
+    let mut elements = Vec::with_capacity(expr.elements.len());
+    for element in &expr.elements {
+      elements.push(self.evaluate(element.clone())?);
+    }
+    Ok(Object::List(Rc::new(elements)))
+  }
+
+  fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<Object, SaturdayResult> {
+    Ok(expr.value.clone().unwrap())
+  }
+
+  fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<Object, SaturdayResult> {
+    let left = self.evaluate(expr.left.clone())?;
+
+    if expr.operator.is(TokenType::QuestionQuestion) {
+      if left != Object::Nil {
+        return Ok(left);
+      }
+    } else if expr.operator.is(TokenType::Or) {
+      if self.is_truthy(&left) {
+        return Ok(left);
+      }
+    } else if !self.is_truthy(&left) {
+      return Ok(left);
+    }
+
+    self.evaluate(expr.right.clone())
+  }
+
+  fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<Object, SaturdayResult> {
+    let right = self.evaluate(expr.right.clone())?;
+    self.unary(&expr.operator, right)
+  }
+
+  fn visit_variable_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &VariableExpr,
+  ) -> Result<Object, SaturdayResult> {
+    // self.environment.borrow().borrow().get(&expr.name)
+    self.look_up_variable(&expr.name, wrapper)
+  }
+}
+
+impl Interpreter {
+  pub fn new() -> Self {
+    let globals = Rc::new(RefCell::new(Environment::new()));
+    for (name, native) in native_globals() {
+      globals.borrow_mut().define(intern(name), native);
+    }
+
+    Self {
+      globals: Rc::clone(&globals),
+      environment: RefCell::new(Rc::clone(&globals)),
+      locals: RefCell::new(HashMap::new()),
+      tail_calls: RefCell::new(HashSet::new()),
+      scheduler: Scheduler::new(),
+      gc: Collector::new(),
+      strip_asserts: false,
+      call_depth: Cell::new(0),
+      max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+      frames: RefCell::new(vec![Frame {
+        function: None,
+        line: 0,
+      }]),
+      fuel: Cell::new(None),
+      interrupt: Arc::new(AtomicBool::new(false)),
+      memory_limit: Cell::new(None),
+    }
+  }
+
+  pub fn set_strip_asserts(&mut self, strip: bool) {
+    self.strip_asserts = strip;
+  }
+
+  pub fn strip_asserts(&self) -> bool {
+    self.strip_asserts
+  }
+
+  /// 在name对应的新帧中执行call，运行时错误经过时把这一帧记到trace里
+  pub fn in_frame<T>(
+    &self,
+    name: &Token,
+    call: impl FnOnce() -> Result<T, SaturdayResult>,
+  ) -> Result<T, SaturdayResult> {
+    self.frames.borrow_mut().push(Frame {
+      function: Some(name.symbol),
+      line: 0,
+    });
+    let result = call();
+    let frame = self.frames.borrow_mut().pop().unwrap();
+    result.map_err(|err| err.unwind(frame.function.map(|f| f.to_string()), frame.line))
+  }
+
+  /// 设置执行步数的预算，用完后执行以SaturdayResult::OutOfFuel中止
+  pub fn set_fuel(&self, fuel: Option<u64>) {
+    self.fuel.set(fuel);
+  }
+
+  /// 剩余的执行步数
+  pub fn fuel(&self) -> Option<u64> {
+    self.fuel.get()
+  }
+
+  /// 消耗一步执行预算
+  pub fn consume_fuel(&self) -> Result<(), SaturdayResult> {
+    match self.fuel.get() {
+      Some(0) => Err(SaturdayResult::OutOfFuel),
+      Some(fuel) => {
+        self.fuel.set(Some(fuel - 1));
+        Ok(())
+      }
+      None => Ok(()),
+    }
+  }
+
+  /// 共享的中断标志，置位后执行在下一个循环回跳或调用处以SaturdayResult::Interrupted中止。
+  /// 标志不会自动清除，再次执行前由调用者复位
+  pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+    Arc::clone(&self.interrupt)
+  }
+
+  pub fn poll_interrupt(&self) -> Result<(), SaturdayResult> {
+    if self.interrupt.load(Ordering::Relaxed) {
+      return Err(SaturdayResult::Interrupted);
+    }
+
+    Ok(())
+  }
+
+  pub fn set_memory_limit(&self, limit: Option<usize>) {
+    self.memory_limit.set(limit);
+  }
+
+  /// # 预留内存
+  /// ```
+  /// 确认还能再分配bytes字节。超过上限时先回收一次环循环，仍然不够就在token处报Out of memory。
+  /// 调用时用0检查当前的占用，拼接字符串这类可能一次分配很多的操作先按结果的大小预留
+  /// ```
+  pub fn reserve_memory(&self, token: &Token, bytes: usize) -> Result<(), SaturdayResult> {
+    let Some(limit) = self.memory_limit.get() else {
+      return Ok(());
+    };
+    if memory::allocated().saturating_add(bytes) <= limit {
+      return Ok(());
+    }
+
+    self.gc.collect();
+    if memory::allocated().saturating_add(bytes) <= limit {
+      return Ok(());
+    }
+    Err(SaturdayResult::runtime_error(token, "Out of memory."))
+  }
+
+  pub fn set_max_call_depth(&mut self, depth: usize) {
+    self.max_call_depth = depth;
+  }
+
+  pub fn max_call_depth(&self) -> usize {
+    self.max_call_depth
+  }
+
+  /// 在paren处发起调用：记录当前帧的调用行，在新的一层调用深度中执行call
+  pub fn call_at<T>(
+    &self,
+    paren: &Token,
+    call: impl FnOnce() -> Result<T, SaturdayResult>,
+  ) -> Result<T, SaturdayResult> {
+    self.charge_call(paren)?;
+    self.nested(paren, call)
+  }
+
+  /// 每次调用前的检查：计一步执行预算，检查中断和内存上限，记录当前帧的调用行。
+  /// 协程中直接执行的send()/recv()/yield_now()不经过call_at，也要先经过这里
+  pub fn charge_call(&self, paren: &Token) -> Result<(), SaturdayResult> {
+    self.consume_fuel()?;
+    self.poll_interrupt()?;
+    self.reserve_memory(paren, 0)?;
+    if let Some(frame) = self.frames.borrow_mut().last_mut() {
+      frame.line = paren.line;
+    }
+
+    Ok(())
+  }
+
+  /// 调用深度加一后执行call，返回时恢复；深度已经到上限时在token处报错。
+  /// 恢复生成器也会在Rust栈上递归，同样经过这里
+  pub fn nested<T>(
+    &self,
+    token: &Token,
+    call: impl FnOnce() -> Result<T, SaturdayResult>,
+  ) -> Result<T, SaturdayResult> {
+    let depth = self.call_depth.get();
+    if depth >= self.max_call_depth {
+      return Err(SaturdayResult::runtime_error(token, "Stack overflow."));
+    }
+
+    self.call_depth.set(depth + 1);
+    let result = call();
+    self.call_depth.set(depth);
+    result
+  }
+
+  fn evaluate(&self, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
+    expr.accept(expr.clone(), self)
+  }
+
+  fn execute(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
+    self.consume_fuel()?;
+    stmt.accept(stmt.clone(), self)
+  }
+
+  pub fn execute_block(
+    &self,
+    statements: &Rc<Vec<Rc<Stmt>>>,
+    environment: Environment,
+  ) -> Result<(), SaturdayResult> {
+    self.with_environment(Rc::new(RefCell::new(environment)), || {
+      statements
+        .iter()
+        .try_for_each(|statement| self.execute(statement.clone()))
+    })
+  }
+
+  /// 在指定的环境中执行单条语句（不新建作用域）
+  pub fn execute_in(
+    &self,
+    stmt: Rc<Stmt>,
+    environment: Rc<RefCell<Environment>>,
+  ) -> Result<(), SaturdayResult> {
+    self.with_environment(environment, || self.execute(stmt))
+  }
+
+  /// 在指定的环境中对表达式求值
+  pub fn evaluate_in(
+    &self,
+    expr: Rc<Expr>,
+    environment: Rc<RefCell<Environment>>,
+  ) -> Result<Object, SaturdayResult> {
+    self.with_environment(environment, || self.evaluate(expr))
+  }
+
+  pub fn with_environment<T>(
+    &self,
+    environment: Rc<RefCell<Environment>>,
+    f: impl FnOnce() -> Result<T, SaturdayResult>,
+  ) -> Result<T, SaturdayResult> {
+    let previous = self.environment.replace(environment);
+    let result = f();
+    self.environment.replace(previous);
+    result
+  }
+
+  /// 位运算只接受能精确表示为i64的整数
+  fn to_integer(&self, operator: &Token, n: f64) -> Result<i64, SaturdayResult> {
+    if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+      Ok(n as i64)
+    } else {
+      Err(SaturdayResult::runtime_error(
+        operator,
+        &format!(
+          "Operands of '{}' must be integers, got {n}.",
+          operator.as_string()
+        ),
+      ))
+    }
+  }
+
+  fn bitwise(&self, operator: &Token, left: f64, right: f64) -> Result<Object, SaturdayResult> {
+    let left = self.to_integer(operator, left)?;
+    let right = self.to_integer(operator, right)?;
+    let result = match operator.token_type() {
+      TokenType::Ampersand => left & right,
+      TokenType::Pipe => left | right,
+      TokenType::Caret => left ^ right,
+      TokenType::LessLess | TokenType::GreaterGreater => {
+        if !(0..64).contains(&right) {
+          return Err(SaturdayResult::runtime_error(
+            operator,
+            "Shift amount must be between 0 and 63.",
+          ));
+        }
+
+        if operator.is(TokenType::LessLess) {
+          left << right
+        } else {
+          left >> right
+        }
+      }
+      _ => unreachable!(),
+    };
+
+    Ok(Object::Num(result as f64))
+  }
+
+  /// 任何不等于Nil和False的识别为true
+  pub fn is_truthy(&self, object: &Object) -> bool {
+    !matches!(object, Object::Nil | Object::Bool(false))
+  }
+
+  pub fn interpreter(&self, statements: &[Rc<Stmt>]) -> Result<(), SaturdayResult> {
+    let run = || {
+      for statement in statements {
+        self.execute(statement.clone())?;
+      }
+
+      // 主程序结束后继续运行剩余的协程
+      self.scheduler.run_until_idle(self)
+    };
+    run().map_err(|err| err.unwind(None, self.frames.borrow()[0].line))
+  }
+
+  pub fn print_environment(&self) {
+    println!("{:?}", self.environment.borrow().borrow());
+  }
+
+  pub fn resolve(&self, expr: Rc<Expr>, depth: usize, slot: usize) {
+    self.locals.borrow_mut().insert(expr, (depth, slot));
+  }
+
+  /// 优化器替换了节点，把resolver记录在旧节点上的信息转移到新节点
+  pub fn replace_expr(&self, old: &Rc<Expr>, new: &Rc<Expr>) {
+    let local = self.locals.borrow_mut().remove(old);
+    if let Some(local) = local {
+      self.locals.borrow_mut().insert(Rc::clone(new), local);
+    }
+    let tail_call = self.tail_calls.borrow_mut().remove(old);
+    if tail_call {
+      self.tail_calls.borrow_mut().insert(Rc::clone(new));
+    }
+  }
+
+  /// 标记处于尾位置的调用（return f(...)），执行时通过trampoline复用栈帧
+  pub fn mark_tail_call(&self, expr: Rc<Expr>) {
+    self.tail_calls.borrow_mut().insert(expr);
+  }
+
+  /// 对已经求值的两个操作数应用二元运算符
+  pub fn binary(
+    &self,
+    operator: &Token,
+    left: Object,
+    right: Object,
+  ) -> Result<Object, SaturdayResult> {
+    let op = operator.token_type();
+    match (&left, &right) {
+      (Object::Num(left), Object::Num(right)) if Interpreter::is_bitwise(op) => {
+        return self.bitwise(operator, *left, *right);
+      }
+      (Object::Str(_), _) | (_, Object::Str(_)) if op == TokenType::Plus => {
+        // 非字符串的一边按格式化后的长度估算
+        let size = |value: &Object| match value {
+          Object::Str(s) => s.len(),
+          _ => 32,
+        };
+        self.reserve_memory(operator, size(&left) + size(&right))?;
+      }
+      _ => {}
+    }
+
+    let result = Interpreter::arithmetic(op, left, right);
+    if result == Object::ArithmeticError {
+      Err(SaturdayResult::runtime_error(
+        operator,
+        "Illegal expression",
+      ))
+    } else {
+      Ok(result)
+    }
+  }
+
+  fn is_bitwise(op: TokenType) -> bool {
+    matches!(
+      op,
+      TokenType::Ampersand
+        | TokenType::Pipe
+        | TokenType::Caret
+        | TokenType::LessLess
+        | TokenType::GreaterGreater
+    )
+  }
+
+  /// # 除位运算之外的二元运算
+  /// ```
+  /// 不报告错误，运算不合法时返回Object::ArithmeticError，优化器也用它折叠常量
+  /// ```
+  pub fn arithmetic(op: TokenType, left: Object, right: Object) -> Object {
+    match (left, right) {
       (Object::Num(left), Object::Num(right)) => match op {
         TokenType::Minus => Object::Num(left - right),
         TokenType::Slash => Object::Num(left / right),
@@ -166,6 +700,27 @@ impl ExprVisitor<Object> for Interpreter {
         TokenType::Equal => Object::Bool(left == right),
         _ => Object::ArithmeticError,
       },
+      (Object::Variant(left), Object::Variant(right)) => match op {
+        TokenType::BangEqual => Object::Bool(left != right),
+        TokenType::Equal => Object::Bool(left == right),
+        _ => Object::ArithmeticError,
+      },
+      (Object::List(left), Object::List(right)) => match op {
+        TokenType::BangEqual => Object::Bool(left != right),
+        TokenType::Equal => Object::Bool(left == right),
+        _ => Object::ArithmeticError,
+      },
+      (Object::Record(left), Object::Record(right)) => match op {
+        TokenType::BangEqual => Object::Bool(left != right),
+        TokenType::Equal => Object::Bool(left == right),
+        _ => Object::ArithmeticError,
+      },
+      (Object::Variant(_) | Object::List(_) | Object::Record(_), _)
+      | (_, Object::Variant(_) | Object::List(_) | Object::Record(_)) => match op {
+        TokenType::BangEqual => Object::Bool(true),
+        TokenType::Equal => Object::Bool(false),
+        _ => Object::ArithmeticError,
+      },
       (Object::Nil, Object::Nil) => match op {
         TokenType::BangEqual => Object::Bool(false),
         TokenType::Equal => Object::Bool(true),
@@ -177,163 +732,228 @@ impl ExprVisitor<Object> for Interpreter {
         _ => Object::ArithmeticError,
       },
       _ => Object::ArithmeticError,
-    };
+    }
+  }
+
+  /// # 选择match分支
+  /// ```
+  /// 返回第一个与subject匹配且guard成立的分支，以及绑定了模式变量的环境
+  /// ```
+  pub fn select_arm(
+    &self,
+    stmt: &MatchStmt,
+    subject: Object,
+  ) -> Result<Option<SelectedArm>, SaturdayResult> {
+    for arm in stmt.arms.iter() {
+      let mut types = Vec::new();
+      for type_name in arm.pattern.types() {
+        types.push(self.evaluate(type_name.clone())?);
+      }
 
-    if result == Object::ArithmeticError {
-      Err(SaturdayResult::runtime_error(
-        &expr.operator,
-        "Illegal expression",
-      ))
-    } else {
-      Ok(result)
+      let mut bindings = Vec::new();
+      if !arm.pattern.matches(&subject, &types, &mut bindings)? {
+        continue;
+      }
+
+      let mut e = Environment::new_with_enclosing(self.environment.borrow().clone());
+      for (name, value) in bindings {
+        e.define(name, value);
+      }
+
+      let e = Rc::new(RefCell::new(e));
+      if let Some(guard) = &arm.guard {
+        if !self.is_truthy(&self.evaluate_in(guard.clone(), e.clone())?) {
+          continue;
+        }
+      }
+
+      return Ok(Some((arm.body.clone(), e)));
     }
+
+    Ok(None)
   }
 
-  fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Object, SaturdayResult> {
-    let callee = self.evaluate(expr.callee.clone())?;
-    let mut arguments = Vec::new();
-    for argument in expr.arguments.clone() {
-      arguments.push(self.evaluate(argument)?);
+  /// # 可选链
+  /// ```
+  /// 连续的属性访问和调用组成一条链，a?.b.c()中a为nil时整条链短路，
+  /// 后面的属性访问、参数求值和调用都不执行，结果为nil。
+  /// 返回None表示链在某个?.处短路了；括号会结束一条链
+  /// ```
+  pub fn optional_chain(&self, expr: &Rc<Expr>) -> Result<Option<Object>, SaturdayResult> {
+    match &**expr {
+      Expr::Get(get) => self.get_link(get),
+      Expr::Call(call) => self.call_link(call),
+      _ => self.evaluate(Rc::clone(expr)).map(Some),
     }
+  }
 
-    if let Object::Func(function) = callee {
-      if arguments.len() != function.func.arity() {
-        return Err(SaturdayResult::runtime_error(
-          &expr.paren,
-          &format!(
-            "Expected {} arguments but got {}.",
-            function.func.arity(),
-            arguments.len()
-          ),
-        ));
-      }
+  fn get_link(&self, expr: &GetExpr) -> Result<Option<Object>, SaturdayResult> {
+    let Some(object) = self.optional_chain(&expr.object)? else {
+      return Ok(None);
+    };
+    if expr.optional && object == Object::Nil {
+      return Ok(None);
+    }
 
-      function.func.call(self, arguments)
-    } else {
-      Err(SaturdayResult::runtime_error(
-        &expr.paren,
-        "Can only call function and classes",
-      ))
+    self.get_property(object, &expr.name).map(Some)
+  }
+
+  fn call_link(&self, expr: &CallExpr) -> Result<Option<Object>, SaturdayResult> {
+    match self.prepare_call(expr)? {
+      Some((function, arguments)) => self
+        .call_at(&expr.paren, || {
+          function.func.call(self, &expr.paren, arguments)
+        })
+        .map(Some),
+      None => Ok(None),
     }
   }
 
-  fn visit_grouping_expr(
+  /// # 调用前的准备
+  /// ```
+  /// 对callee和参数求值并检查参数个数，可选链短路时返回None
+  /// ```
+  fn prepare_call(
     &self,
-    _: Rc<Expr>,
-    expr: &GroupingExpr,
-  ) -> Result<Object, SaturdayResult> {
-    self.evaluate(expr.expression.clone())
-  }
+    expr: &CallExpr,
+  ) -> Result<Option<(Callable, Vec<Object>)>, SaturdayResult> {
+    let Some(callee) = self.optional_chain(&expr.callee)? else {
+      return Ok(None);
+    };
+    if expr.optional && callee == Object::Nil {
+      return Ok(None);
+    }
 
-  fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<Object, SaturdayResult> {
-    Ok(expr.value.clone().unwrap())
+    let mut arguments = Vec::new();
+    for argument in expr.arguments.clone() {
+      arguments.push(self.evaluate(argument)?);
+    }
+
+    let function = self.check_call(&expr.paren, callee, arguments.len())?;
+    Ok(Some((function, arguments)))
   }
 
-  fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<Object, SaturdayResult> {
-    let left = self.evaluate(expr.left.clone())?;
+  /// 检查callee是否可以调用、参数个数是否正确
+  pub fn check_call(
+    &self,
+    paren: &Token,
+    callee: Object,
+    count: usize,
+  ) -> Result<Callable, SaturdayResult> {
+    let Object::Func(function) = callee else {
+      return Err(SaturdayResult::runtime_error(
+        paren,
+        "Can only call function and classes",
+      ));
+    };
 
-    if expr.operator.is(TokenType::Or) {
-      if self.is_truthy(&left) {
-        return Ok(left);
-      }
-    } else if !self.is_truthy(&left) {
-      return Ok(left);
+    if function.func.variadic() && count < function.func.arity() {
+      return Err(SaturdayResult::runtime_error(
+        paren,
+        &format!(
+          "Expected at least {} arguments but got {}.",
+          function.func.arity(),
+          count
+        ),
+      ));
     }
 
-    self.evaluate(expr.right.clone())
+    if !function.func.variadic() && count != function.func.arity() {
+      return Err(SaturdayResult::runtime_error(
+        paren,
+        &format!(
+          "Expected {} arguments but got {}.",
+          function.func.arity(),
+          count
+        ),
+      ));
+    }
+
+    Ok(function)
   }
 
-  fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<Object, SaturdayResult> {
-    let right = self.evaluate(expr.right.clone())?;
-    match expr.operator.token_type() {
+  /// 一元运算
+  pub fn unary(&self, operator: &Token, right: Object) -> Result<Object, SaturdayResult> {
+    match operator.token_type() {
       TokenType::Minus => match right {
         Object::Num(n) => Ok(Object::Num(-n)),
         _ => Ok(Object::Nil),
       },
       TokenType::Bang => Ok(Object::Bool(!self.is_truthy(&right))),
+      TokenType::Tilde => match right {
+        Object::Num(n) => Ok(Object::Num(!self.to_integer(operator, n)? as f64)),
+        _ => Err(SaturdayResult::runtime_error(
+          operator,
+          "Operand of '~' must be an integer.",
+        )),
+      },
       _ => Err(SaturdayResult::error(
-        expr.operator.line,
+        operator.line,
         "Unreachable according to Nystrom",
       )),
     }
   }
 
-  fn visit_variable_expr(
-    &self,
-    wrapper: Rc<Expr>,
-    expr: &VariableExpr,
-  ) -> Result<Object, SaturdayResult> {
-    // self.environment.borrow().borrow().get(&expr.name)
-    self.look_up_variable(&expr.name, wrapper)
-  }
-}
-
-impl Interpreter {
-  pub fn new() -> Self {
-    let globals = Rc::new(RefCell::new(Environment::new()));
-    globals.borrow_mut().define(
-      "clock",
-      Object::Func(Callable {
-        func: Rc::new(NativeClock {}),
-      }),
-    );
-
-    Self {
-      globals: Rc::clone(&globals),
-      environment: RefCell::new(Rc::clone(&globals)),
-      locals: RefCell::new(HashMap::new()),
+  /// 属性访问：枚举的变体，或者内置值的方法
+  pub fn get_property(&self, object: Object, name: &Token) -> Result<Object, SaturdayResult> {
+    match object {
+      Object::Enum(e) => e.get(name),
+      Object::Record(r) => r.get(name),
+      value => native_methods::bind(value, name),
     }
   }
 
-  fn evaluate(&self, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
-    expr.accept(expr.clone(), self)
-  }
+  /// assert失败时的错误，operands是二元条件两边的值
+  pub fn assert_failure(
+    &self,
+    stmt: &AssertStmt,
+    operands: Option<(Object, Object)>,
+    extra: Option<Object>,
+  ) -> SaturdayResult {
+    let mut message = format!("assert failed: {}", stmt.source);
+    if let Some((left, right)) = operands {
+      message.push_str(&format!(" (left: {left}, right: {right})"));
+    }
+    message.push_str(&format!(" at line {}", stmt.keyword.line));
+    if let Some(extra) = extra {
+      message.push_str(&format!(": {extra}"));
+    }
 
-  fn execute(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
-    stmt.accept(stmt.clone(), self)
+    SaturdayResult::runtime_error(&stmt.keyword, &message)
   }
 
-  pub fn execute_block(
+  fn assign_variable(
     &self,
-    statements: &Rc<Vec<Rc<Stmt>>>,
-    environment: Environment,
+    wrapper: Rc<Expr>,
+    name: &Token,
+    value: Object,
   ) -> Result<(), SaturdayResult> {
-    let previous = self.environment.replace(Rc::new(RefCell::new(environment)));
-    let result = statements
-      .iter()
-      .try_for_each(|statement| self.execute(statement.clone()));
-    self.environment.replace(previous);
-    result
-  }
-
-  /// 任何不等于Nil和False的识别为true
-  fn is_truthy(&self, object: &Object) -> bool {
-    !matches!(object, Object::Nil | Object::Bool(false))
-  }
-
-  pub fn interpreter(&self, statements: &[Rc<Stmt>]) -> Result<(), SaturdayResult> {
-    for statement in statements {
-      self.execute(statement.clone())?;
+    if let Some(&(distance, slot)) = self.locals.borrow().get(&wrapper) {
+      self
+        .environment
+        .borrow()
+        .borrow_mut()
+        .assign_at(distance, slot, value);
+      Ok(())
+    } else {
+      self.globals.borrow_mut().assign(name, value)
     }
-
-    Ok(())
-  }
-
-  pub fn print_environment(&self) {
-    println!("{:?}", self.environment.borrow().borrow());
   }
 
-  pub fn resolve(&self, expr: Rc<Expr>, depth: usize) {
-    self.locals.borrow_mut().insert(expr, depth);
+  /// 在指定的环境中给变量赋值，wrapper为对应的AssignExpr
+  pub fn assign_in(
+    &self,
+    wrapper: Rc<Expr>,
+    name: &Token,
+    value: Object,
+    environment: Rc<RefCell<Environment>>,
+  ) -> Result<(), SaturdayResult> {
+    self.with_environment(environment, || self.assign_variable(wrapper, name, value))
   }
 
   pub fn look_up_variable(&self, name: &Token, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
-    if let Some(distance) = self.locals.borrow().get(&expr) {
-      self
-        .environment
-        .borrow()
-        .borrow()
-        .get_at(*distance, &name.as_string())
+    if let Some(&(distance, slot)) = self.locals.borrow().get(&expr) {
+      Ok(self.environment.borrow().borrow().get_at(distance, slot))
     } else {
       self.globals.borrow().get(name)
     }
@@ -345,14 +965,25 @@ mod tests {
   use super::*;
   use crate::token::Token;
 
-  fn make_literal(o: Object) -> Box<Expr> {
-    Box::new(Expr::Literal(LiteralExpr { value: Some(o) }))
+  fn make_literal(o: Object) -> Rc<Expr> {
+    Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: Some(o) })))
   }
 
-  fn make_literal_string(s: &str) -> Box<Expr> {
+  fn make_literal_string(s: &str) -> Rc<Expr> {
     make_literal(Object::Str(s.to_string()))
   }
 
+  /// 直接调用visitor时使用的占位包装节点
+  fn expr_wrapper() -> Rc<Expr> {
+    make_literal(Object::Nil)
+  }
+
+  fn stmt_wrapper() -> Rc<Stmt> {
+    Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
+      expression: expr_wrapper(),
+    })))
+  }
+
   #[test]
   fn test_unary_minus() {
     let terp = Interpreter::new();
@@ -360,7 +991,7 @@ mod tests {
       operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
       right: make_literal(Object::Num(123.0)),
     };
-    let result = terp.visit_unary_expr(&unary_expr);
+    let result = terp.visit_unary_expr(expr_wrapper(), &unary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(-123.0)));
   }
@@ -372,7 +1003,7 @@ mod tests {
       operator: Token::new(TokenType::Bang, "!".to_string(), None, 123),
       right: make_literal(Object::Bool(false)),
     };
-    let result = terp.visit_unary_expr(&unary_expr);
+    let result = terp.visit_unary_expr(expr_wrapper(), &unary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(true)));
   }
@@ -385,7 +1016,7 @@ mod tests {
       operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(8.0)));
   }
@@ -398,7 +1029,7 @@ mod tests {
       operator: Token::new(TokenType::Slash, "/".to_string(), None, 123),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(3.0)));
   }
@@ -411,7 +1042,7 @@ mod tests {
       operator: Token::new(TokenType::Star, "*".to_string(), None, 123),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(105.0)));
   }
@@ -424,7 +1055,7 @@ mod tests {
       operator: Token::new(TokenType::Plus, "+".to_string(), None, 123),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(22.0)));
   }
@@ -437,7 +1068,7 @@ mod tests {
       operator: Token::new(TokenType::Plus, "+".to_string(), None, 123),
       right: make_literal_string("world!"),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Str("hello, world!".to_string())));
   }
@@ -450,7 +1081,7 @@ mod tests {
       operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
       right: make_literal(Object::Bool(true)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_err());
   }
 
@@ -462,7 +1093,7 @@ mod tests {
       operator: Token::new(TokenType::Greater, ">".to_string(), None, 123),
       right: make_literal(Object::Bool(true)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_err());
   }
 
@@ -490,7 +1121,7 @@ mod tests {
       operator: Token::new(TokenType::Equal, "==".to_string(), None, 123),
       right: make_literal_string("hellx"),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(false)));
   }
@@ -503,7 +1134,7 @@ mod tests {
       operator: Token::new(TokenType::Equal, "==".to_string(), None, 123),
       right: make_literal_string("world"),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(true)));
   }
@@ -516,7 +1147,7 @@ mod tests {
       operator: Token::new(TokenType::Equal, "==".to_string(), None, 123),
       right: make_literal(Object::Nil),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(true)));
   }
@@ -531,14 +1162,14 @@ mod tests {
         operator: tok.dup(),
         right: make_literal(Object::Num(15.0)),
       };
-      let result = terp.visit_binary_expr(&binary_expr);
+      let result = terp.visit_binary_expr(expr_wrapper(), &binary_expr);
       assert!(result.is_ok());
       assert_eq!(
         result.ok(),
         Some(Object::Bool(*c)),
         "Testing {} {} 15.0",
         nums,
-        tok.lexeme
+        tok.lexeme()
       );
     }
   }
@@ -575,15 +1206,558 @@ mod tests {
     );
   }
 
+  fn run_bitwise_test(operator: TokenType, lexeme: &str, left: f64, right: f64) -> Option<Object> {
+    let terp = Interpreter::new();
+    let binary_expr = BinaryExpr {
+      left: make_literal(Object::Num(left)),
+      operator: Token::new(operator, lexeme.to_string(), None, 123),
+      right: make_literal(Object::Num(right)),
+    };
+    terp.visit_binary_expr(expr_wrapper(), &binary_expr).ok()
+  }
+
+  #[test]
+  fn test_bitwise_operators() {
+    assert_eq!(
+      run_bitwise_test(TokenType::Ampersand, "&", 12.0, 10.0),
+      Some(Object::Num(8.0))
+    );
+    assert_eq!(
+      run_bitwise_test(TokenType::Pipe, "|", 12.0, 10.0),
+      Some(Object::Num(14.0))
+    );
+    assert_eq!(
+      run_bitwise_test(TokenType::Caret, "^", 12.0, 10.0),
+      Some(Object::Num(6.0))
+    );
+    assert_eq!(
+      run_bitwise_test(TokenType::LessLess, "<<", 1.0, 4.0),
+      Some(Object::Num(16.0))
+    );
+    assert_eq!(
+      run_bitwise_test(TokenType::GreaterGreater, ">>", -16.0, 2.0),
+      Some(Object::Num(-4.0))
+    );
+  }
+
+  #[test]
+  fn test_bitwise_error_for_fraction() {
+    assert_eq!(run_bitwise_test(TokenType::Ampersand, "&", 1.5, 1.0), None);
+    assert_eq!(run_bitwise_test(TokenType::LessLess, "<<", 1.0, 64.0), None);
+  }
+
+  #[test]
+  fn test_bitwise_not() {
+    let terp = Interpreter::new();
+    let unary_expr = UnaryExpr {
+      operator: Token::new(TokenType::Tilde, "~".to_string(), None, 123),
+      right: make_literal(Object::Num(5.0)),
+    };
+    let result = terp.visit_unary_expr(expr_wrapper(), &unary_expr);
+    assert_eq!(result.ok(), Some(Object::Num(-6.0)));
+  }
+
+  fn run_source(source: &str) -> Interpreter {
+    let terp = Interpreter::new();
+    let mut scanner = crate::scanner::Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    assert!(terp.interpreter(&statements).is_ok());
+    terp
+  }
+
+  #[test]
+  fn test_tail_calls_do_not_grow_the_stack() {
+    let terp = run_source(
+      "fun count(n, acc) { if n == 0 { return acc; } return count(n - 1, acc + 1); }
+       fun is_even(n) { if n == 0 { return true; } return is_odd(n - 1); }
+       fun is_odd(n) { if n == 0 { return false; } return is_even(n - 1); }
+       def total = count(100000, 0);
+       def even = is_even(100001);",
+    );
+    let total = Token::new(TokenType::Identifier, "total".to_string(), None, 0);
+    let even = Token::new(TokenType::Identifier, "even".to_string(), None, 0);
+    assert_eq!(
+      terp.globals.borrow().get(&total).ok(),
+      Some(Object::Num(100000.0))
+    );
+    assert_eq!(
+      terp.globals.borrow().get(&even).ok(),
+      Some(Object::Bool(false))
+    );
+  }
+
+  #[test]
+  fn test_call_depth_limit() {
+    let mut terp = Interpreter::new();
+    terp.set_max_call_depth(50);
+    let mut scanner = crate::scanner::Scanner::new(
+      "fun down(n) { if n == 0 { return 0; } return 1 + down(n - 1); }
+       def ok = down(49);
+       def loops = 0;
+       while loops < 3 { loops = loops + 1; down(40); }
+       down(50);"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    match terp.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError {
+        token,
+        message,
+        trace,
+      }) => {
+        assert_eq!(message, "Stack overflow.");
+        assert_eq!(token.line, 1);
+        // 50层down加上顶层脚本
+        assert_eq!(trace.frames.len(), 51);
+      }
+      _ => panic!("expect stack overflow"),
+    }
+
+    // 出错后调用深度恢复，之后的调用不受影响
+    let ok = Token::new(TokenType::Identifier, "ok".to_string(), None, 0);
+    assert_eq!(terp.globals.borrow().get(&ok).ok(), Some(Object::Num(49.0)));
+    assert_eq!(terp.call_depth.get(), 0);
+  }
+
+  #[test]
+  fn test_runtime_error_trace() {
+    let terp = Interpreter::new();
+    let mut scanner = crate::scanner::Scanner::new(
+      "fun inner(x) {
+         return x - \"a\";
+       }
+       fun outer() {
+         def y = inner(1);
+         return y;
+       }
+       outer();"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    let Err(SaturdayResult::RuntimeError { trace, .. }) = terp.interpreter(&statements) else {
+      panic!("expect runtime error");
+    };
+    let frames: Vec<(Option<String>, usize)> = trace
+      .frames
+      .into_iter()
+      .map(|frame| (frame.function, frame.line))
+      .collect();
+    assert_eq!(
+      frames,
+      vec![
+        (Some("inner".to_string()), 2),
+        (Some("outer".to_string()), 5),
+        (None, 8),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_fuel_budget() {
+    let terp = Interpreter::new();
+    let mut scanner = crate::scanner::Scanner::new(
+      "fun spin() { while true {} }
+       def ready = true;
+       spin();"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+
+    terp.set_fuel(Some(1000));
+    assert!(matches!(
+      terp.interpreter(&statements),
+      Err(SaturdayResult::OutOfFuel)
+    ));
+    assert_eq!(terp.fuel(), Some(0));
+
+    // 语句和调用各计一步：两条声明加一次调用
+    terp.set_fuel(Some(100));
+    let mut scanner = crate::scanner::Scanner::new("def x = clock(); def y = 1;".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    assert!(terp.interpreter(&statements).is_ok());
+    assert_eq!(terp.fuel(), Some(97));
+  }
+
+  #[test]
+  fn test_interrupt_flag() {
+    let terp = Interpreter::new();
+    let mut scanner = crate::scanner::Scanner::new("while true {}".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+
+    // 从另一个线程置位，模拟Ctrl-C
+    let flag = terp.interrupt_flag();
+    let interrupter = std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(20));
+      flag.store(true, Ordering::SeqCst);
+    });
+    assert!(matches!(
+      terp.interpreter(&statements),
+      Err(SaturdayResult::Interrupted)
+    ));
+    interrupter.join().unwrap();
+
+    // 尾调用在同一个Rust栈帧里循环，也要能被看门狗中止
+    let mut scanner = crate::scanner::Scanner::new("fun f() { return f(); }\nf();".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    terp.interrupt_flag().store(false, Ordering::SeqCst);
+    let _watchdog = crate::interrupt::Watchdog::start(
+      std::time::Duration::from_millis(20),
+      terp.interrupt_flag(),
+    );
+    assert!(matches!(
+      terp.interpreter(&statements),
+      Err(SaturdayResult::Interrupted)
+    ));
+  }
+
+  #[test]
+  fn test_memory_limit() {
+    let terp = Interpreter::new();
+    let mut scanner = crate::scanner::Scanner::new(
+      "def s = \"x\";
+       def doublings = 0;
+       while true { s = s + s; doublings = doublings + 1; }"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+
+    terp.set_memory_limit(Some(memory::allocated() + (4 << 20)));
+    match terp.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError { token, message, .. }) => {
+        assert_eq!(message, "Out of memory.");
+        assert_eq!(token.line, 3);
+      }
+      _ => panic!("expect out of memory"),
+    }
+
+    // 拼接前就按结果大小预留，字符串不会超过上限
+    let doublings = Token::new(TokenType::Identifier, "doublings".to_string(), None, 0);
+    let Some(Object::Num(n)) = terp.globals.borrow().get(&doublings).ok() else {
+      panic!("expect doublings");
+    };
+    assert!((20.0..23.0).contains(&n));
+  }
+
+  #[test]
+  fn test_match_binds_and_checks_guards() {
+    let terp = run_source(
+      "fun classify(x) {
+         def result = \"other\";
+         match x {
+           0 => { result = \"zero\"; }
+           n if n > 10 => { result = \"big \" + n; }
+           _ => { result = \"small\"; }
+         }
+         return result;
+       }
+       def a = classify(0);
+       def b = classify(42);
+       def c = classify(3);",
+    );
+    for (name, expected) in [("a", "zero"), ("b", "big 42"), ("c", "small")] {
+      let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+      assert_eq!(
+        terp.globals.borrow().get(&token).ok(),
+        Some(Object::Str(expected.to_string()))
+      );
+    }
+  }
+
+  #[test]
+  fn test_enum_variants_match_and_compare() {
+    let terp = run_source(
+      "enum Shape { Circle(r), Rect(w, h), Empty }
+       fun area(s) {
+         def result = 0;
+         match s {
+           Shape.Circle(r) => { result = 3 * r * r; }
+           Shape.Rect(w, h) if w == h => { result = w * w; }
+           Shape.Rect(w, h) => { result = w * h; }
+           Shape.Empty => { result = nil; }
+         }
+         return result;
+       }
+       def circle = area(Shape.Circle(2));
+       def square = area(Shape.Rect(3, 3));
+       def rect = area(Shape.Rect(2, 5));
+       def empty = area(Shape.Empty);
+       def same = Shape.Circle(2) == Shape.Circle(2);
+       def different = Shape.Circle(2) != Shape.Circle(3);
+       def unit = Shape.Empty == Shape.Empty;
+       def other = Shape.Empty == nil;
+       def shown = Shape.Rect(2, 5);",
+    );
+    let expected = [
+      ("circle", Object::Num(12.0)),
+      ("square", Object::Num(9.0)),
+      ("rect", Object::Num(10.0)),
+      ("empty", Object::Nil),
+      ("same", Object::Bool(true)),
+      ("different", Object::Bool(true)),
+      ("unit", Object::Bool(true)),
+      ("other", Object::Bool(false)),
+    ];
+    for (name, value) in expected {
+      let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+      assert_eq!(terp.globals.borrow().get(&token).ok(), Some(value));
+    }
+
+    let shown = Token::new(TokenType::Identifier, "shown".to_string(), None, 0);
+    let shown = terp.globals.borrow().get(&shown).ok().unwrap();
+    assert_eq!(shown.to_string(), "Shape.Rect(2, 5)");
+  }
+
+  #[test]
+  fn test_variant_patterns_resolve_the_enum() {
+    // 同名的另一个枚举的变体不匹配
+    let terp = run_source(
+      "enum Shape { Circle(r) }
+       def outer = Shape.Circle(1);
+       def matched;
+       {
+         enum Shape { Circle(r) }
+         match outer {
+           Shape.Circle(r) => { matched = \"inner\"; }
+           _ => { matched = \"other\"; }
+         }
+       }",
+    );
+    let matched = Token::new(TokenType::Identifier, "matched".to_string(), None, 0);
+    assert_eq!(
+      terp.globals.borrow().get(&matched).ok(),
+      Some(Object::Str("other".to_string()))
+    );
+
+    let cases = [
+      (
+        "match Shape.Circle(1) { Shape.Square => {} }",
+        "Undefined variant 'Square' of enum Shape.",
+      ),
+      (
+        "match Shape.Circle(1) { Shape.Circle(a, b) => {} }",
+        "Expected 1 fields but got 2.",
+      ),
+      (
+        "def x = 1; match Shape.Circle(1) { x.Circle(r) => {} }",
+        "'x' is not an enum.",
+      ),
+    ];
+    for (source, expected) in cases {
+      let terp = Interpreter::new();
+      let source = format!("enum Shape {{ Circle(r) }} {source}");
+      let mut scanner = crate::scanner::Scanner::new(source);
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+      let resolver = crate::resolver::Resolver::new(&terp);
+      assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+      match terp.interpreter(&statements) {
+        Err(SaturdayResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+        _ => panic!("expect runtime error"),
+      }
+    }
+  }
+
+  #[test]
+  fn test_list_and_record_values() {
+    let terp = run_source(
+      "record Point { x, y }
+       def field = Point(4, 1).y;
+       def same = Point(1, 2) == Point(1, 2) and [1, [2]] == [1, [2]];
+       def different = [1, 2] != [2, 1];
+       def shown = Point([1, \"a\"], nil);",
+    );
+    for (name, value) in [
+      ("field", Object::Num(1.0)),
+      ("same", Object::Bool(true)),
+      ("different", Object::Bool(true)),
+    ] {
+      let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+      assert_eq!(terp.globals.borrow().get(&token).ok(), Some(value));
+    }
+
+    let shown = Token::new(TokenType::Identifier, "shown".to_string(), None, 0);
+    let shown = terp.globals.borrow().get(&shown).ok().unwrap();
+    assert_eq!(shown.to_string(), "Point { x: [1, a], y: nil }");
+
+    let terp = Interpreter::new();
+    let mut scanner =
+      crate::scanner::Scanner::new("record Point { x, y } Point(1, 2).z;".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    let resolver = crate::resolver::Resolver::new(&terp);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    assert!(matches!(
+      terp.interpreter(&statements),
+      Err(SaturdayResult::RuntimeError { message, .. })
+      if message == "Undefined field 'z' of record Point."
+    ));
+  }
+
+  #[test]
+  fn test_list_and_record_patterns() {
+    let terp = run_source(
+      "record Point { x, y }
+       fun describe(v) {
+         match v {
+           [] => { return \"empty\"; }
+           [Point { x: 0, y }] => { return y; }
+           [first, ...rest] => { return rest; }
+           Point { x, y: 0 } => { return x; }
+           _ => { return \"other\"; }
+         }
+       }
+       def empty = describe([]);
+       def axis = describe([Point(0, 7)]);
+       def rest = describe([1, 2, 3]);
+       def single = describe([1]);
+       def point = describe(Point(4, 0));
+       def other = describe(Point(4, 1));",
+    );
+    let list = |values: Vec<Object>| Object::List(Rc::new(values));
+    let expected = [
+      ("empty", Object::Str("empty".to_string())),
+      ("axis", Object::Num(7.0)),
+      ("rest", list(vec![Object::Num(2.0), Object::Num(3.0)])),
+      ("single", list(vec![])),
+      ("point", Object::Num(4.0)),
+      ("other", Object::Str("other".to_string())),
+    ];
+    for (name, value) in expected {
+      let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+      assert_eq!(terp.globals.borrow().get(&token).ok(), Some(value));
+    }
+
+    let cases = [
+      (
+        "match Point(1, 2) { Point { z } => {} }",
+        "Undefined field 'z' of record Point.",
+      ),
+      (
+        "def x = 1; match 1 { x { y } => {} }",
+        "'x' is not a record.",
+      ),
+    ];
+    for (source, expected) in cases {
+      let terp = Interpreter::new();
+      let source = format!("record Point {{ x, y }} {source}");
+      let mut scanner = crate::scanner::Scanner::new(source);
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+      let resolver = crate::resolver::Resolver::new(&terp);
+      assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+      match terp.interpreter(&statements) {
+        Err(SaturdayResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+        _ => panic!("expect runtime error"),
+      }
+    }
+  }
+
+  #[test]
+  fn test_assert_reports_operands() {
+    let mut terp = Interpreter::new();
+    let mut scanner = crate::scanner::Scanner::new("assert 1 + 1 == 3, \"math\";".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(crate::parser::Parser::new(tokens).parse().ok().unwrap());
+    match terp.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError { message, .. }) => assert_eq!(
+        message,
+        "assert failed: 1 + 1 == 3 (left: 2, right: 3) at line 1: math"
+      ),
+      _ => panic!("expect assert failure"),
+    }
+
+    terp.set_strip_asserts(true);
+    assert!(terp.interpreter(&statements).is_ok());
+  }
+
+  #[test]
+  fn test_nil_coalesce() {
+    let terp = Interpreter::new();
+    let cases = vec![
+      (Object::Nil, Object::Num(1.0)),
+      (Object::Bool(false), Object::Bool(false)),
+      (Object::Num(0.0), Object::Num(0.0)),
+    ];
+
+    for (left, expected) in cases {
+      let logical_expr = LogicalExpr {
+        left: make_literal(left),
+        operator: Token::new(TokenType::QuestionQuestion, "??".to_string(), None, 123),
+        right: make_literal(Object::Num(1.0)),
+      };
+      let result = terp.visit_logical_expr(expr_wrapper(), &logical_expr);
+      assert_eq!(result.ok(), Some(expected));
+    }
+  }
+
+  #[test]
+  fn test_optional_call_on_nil() {
+    let terp = Interpreter::new();
+    let call_expr = CallExpr {
+      callee: make_literal(Object::Nil),
+      paren: Token::new(TokenType::RightParen, ")".to_string(), None, 123),
+      arguments: vec![make_literal(Object::Num(1.0))],
+      optional: true,
+    };
+    let result = terp.visit_call_expr(expr_wrapper(), &call_expr);
+    assert_eq!(result.ok(), Some(Object::Nil));
+  }
+
+  #[test]
+  fn test_optional_chain_short_circuits() {
+    let terp = run_source(
+      "def s = nil;
+       def calls = 0;
+       fun count() { calls = calls + 1; return calls; }
+       def len = s?.len();
+       def nested = s?.repeat(count()).len();
+       def some = \"ab\"?.repeat(count()).len();
+       fun wrap(x) { return x?.len(); }
+       def tail = wrap(nil);",
+    );
+    let expected = [
+      ("len", Object::Nil),
+      ("nested", Object::Nil),
+      ("calls", Object::Num(1.0)),
+      ("some", Object::Num(2.0)),
+      ("tail", Object::Nil),
+    ];
+    for (name, value) in expected {
+      let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+      assert_eq!(terp.globals.borrow().get(&token).ok(), Some(value));
+    }
+  }
+
   #[test]
   fn test_var_stmt_with_initializer() {
     let terp = Interpreter::new();
     let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
     let def_stmt = DefStmt {
       name: name.dup(),
-      initializer: Some(*make_literal(Object::Num(23.0))),
+      initializer: Some(make_literal(Object::Num(23.0))),
+      annotation: None,
     };
-    assert!(terp.visit_def_stmt(&def_stmt).is_ok());
+    assert!(terp.visit_def_stmt(stmt_wrapper(), &def_stmt).is_ok());
     assert_eq!(
       terp.environment.borrow().borrow().get(&name).ok(),
       Some(Object::Num(23.0))
@@ -597,8 +1771,9 @@ mod tests {
     let def_stmt = DefStmt {
       name: name.dup(),
       initializer: None,
+      annotation: None,
     };
-    assert!(terp.visit_def_stmt(&def_stmt).is_ok());
+    assert!(terp.visit_def_stmt(stmt_wrapper(), &def_stmt).is_ok());
     assert_eq!(
       terp.environment.borrow().borrow().get(&name).ok(),
       Some(Object::Nil)
@@ -611,14 +1786,15 @@ mod tests {
     let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
     let def_stmt = DefStmt {
       name: name.dup(),
-      initializer: Some(*make_literal(Object::Num(23.0))),
+      initializer: Some(make_literal(Object::Num(23.0))),
+      annotation: None,
     };
 
-    assert!(terp.visit_def_stmt(&def_stmt).is_ok());
+    assert!(terp.visit_def_stmt(stmt_wrapper(), &def_stmt).is_ok());
 
     let def_expr = VariableExpr { name: name.dup() };
     assert_eq!(
-      terp.visit_variable_expr(&def_expr).ok(),
+      terp.visit_variable_expr(expr_wrapper(), &def_expr).ok(),
       Some(Object::Num(23.0))
     );
   }
@@ -628,6 +1804,6 @@ mod tests {
     let terp = Interpreter::new();
     let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
     let def_expr = VariableExpr { name: name.dup() };
-    assert!(terp.visit_variable_expr(&def_expr).is_err());
+    assert!(terp.visit_variable_expr(expr_wrapper(), &def_expr).is_err());
   }
 }
@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Ctrl-C时置位的标志，信号处理函数只能访问静态数据
+static SIGINT_FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// # 捕获Ctrl-C
+/// ```
+/// 返回的守卫存在期间，SIGINT不再直接结束进程，而是置位flag，由解释器在循环回跳和调用处检查后中止执行。
+/// 守卫drop后恢复默认的处理，REPL等待输入时Ctrl-C仍然会退出。
+/// flag只记录第一次传入的，之后的调用只重新安装处理函数
+/// ```
+pub fn catch_sigint(flag: Arc<AtomicBool>) -> SigintGuard {
+  let _ = SIGINT_FLAG.set(flag);
+  sys::install();
+  SigintGuard
+}
+
+pub struct SigintGuard;
+
+impl Drop for SigintGuard {
+  fn drop(&mut self) {
+    sys::restore();
+  }
+}
+
+#[cfg(unix)]
+mod sys {
+  use std::sync::atomic::Ordering;
+
+  const SIGINT: i32 = 2;
+  const SIG_DFL: usize = 0;
+
+  extern "C" {
+    fn signal(signum: i32, handler: usize) -> usize;
+  }
+
+  extern "C" fn on_sigint(_: i32) {
+    if let Some(flag) = super::SIGINT_FLAG.get() {
+      flag.store(true, Ordering::SeqCst);
+    }
+  }
+
+  pub fn install() {
+    unsafe {
+      signal(SIGINT, on_sigint as extern "C" fn(i32) as usize);
+    }
+  }
+
+  pub fn restore() {
+    unsafe {
+      signal(SIGINT, SIG_DFL);
+    }
+  }
+}
+
+#[cfg(not(unix))]
+mod sys {
+  pub fn install() {}
+
+  pub fn restore() {}
+}
+
+/// # 超时看门狗
+/// ```
+/// 在后台线程等待timeout，到时间后置位flag；看门狗在到时间前被drop则取消
+/// ```
+pub struct Watchdog {
+  cancel: Sender<()>,
+  fired: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+  pub fn start(timeout: Duration, flag: Arc<AtomicBool>) -> Self {
+    let (cancel, cancelled) = channel();
+    let fired = Arc::new(AtomicBool::new(false));
+    let watchdog = Self {
+      cancel,
+      fired: Arc::clone(&fired),
+    };
+    std::thread::spawn(move || {
+      if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+        fired.store(true, Ordering::SeqCst);
+        flag.store(true, Ordering::SeqCst);
+      }
+    });
+
+    watchdog
+  }
+
+  /// 是否已经到时间，用来区分超时和Ctrl-C
+  pub fn fired(&self) -> bool {
+    self.fired.load(Ordering::SeqCst)
+  }
+}
+
+impl Drop for Watchdog {
+  fn drop(&mut self) {
+    let _ = self.cancel.send(());
+  }
+}
+
+/// 解析5s、500ms、2m这样的时长，不带单位时按秒计
+pub fn parse_duration(text: &str) -> Option<Duration> {
+  let (number, unit) = match text.find(|c: char| !c.is_ascii_digit() && c != '.') {
+    Some(i) => text.split_at(i),
+    None => (text, "s"),
+  };
+  let number: f64 = number.parse().ok()?;
+  let seconds = match unit {
+    "ms" => number / 1000.0,
+    "s" => number,
+    "m" => number * 60.0,
+    _ => return None,
+  };
+
+  Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_durations() {
+    assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+    assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+    assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+    assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+    assert_eq!(parse_duration("5h"), None);
+    assert_eq!(parse_duration("s"), None);
+  }
+
+  #[test]
+  fn watchdog_sets_flag_unless_dropped() {
+    let flag = Arc::new(AtomicBool::new(false));
+    drop(Watchdog::start(
+      Duration::from_millis(10),
+      Arc::clone(&flag),
+    ));
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!flag.load(Ordering::SeqCst));
+
+    let watchdog = Watchdog::start(Duration::from_millis(10), Arc::clone(&flag));
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(flag.load(Ordering::SeqCst));
+    assert!(watchdog.fired());
+  }
+}
@@ -4,52 +4,195 @@ use std::env::args;
 use std::io;
 use std::io::{stdout, BufRead, Write};
 use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use error::*;
 use scanner::*;
 // use crate::ast_printer::AstPrinter;
-use crate::interpreter::Interpreter;
+use crate::checker::Checker;
+use crate::compiler::Compiler;
+use crate::interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH};
+use crate::interrupt::{catch_sigint, parse_duration, Watchdog};
+use crate::memory::parse_size;
+use crate::optimizer::Optimizer;
 use crate::parser::Parser;
 use crate::resolver::Resolver;
+use crate::stmt::Stmt;
 
 mod error;
 mod expr;
 mod parser;
+mod pattern;
 mod scanner;
 mod token;
 mod token_type;
+mod types;
 // mod ast_printer;
 mod callable;
+mod checker;
+mod chunk;
+mod compiler;
 mod environment;
+mod fiber;
+mod format;
+mod gc;
+mod generator;
+mod interner;
 mod interpreter;
+mod interrupt;
+mod memory;
 mod native_functions;
+mod native_methods;
 mod object;
+mod optimizer;
 mod resolver;
+mod saturday_enum;
 mod saturday_function;
+mod saturday_record;
 mod stmt;
+mod unicode;
+mod unicode_tables;
+mod vm;
+
+/// 每层调用预留的栈空间，解释器的递归下降在debug构建下每层调用要用掉几KB
+const STACK_PER_CALL: usize = 32 * 1024;
+const MIN_STACK_SIZE: usize = 8 * 1024 * 1024;
+/// --max-call-depth的上限，对应3.2GB的线程栈，再大一般就申请不下来了
+const MAX_CALL_DEPTH: usize = 100_000;
 
 fn main() {
-  let args: Vec<String> = args().collect();
-  let saturday = Saturday::new();
-
-  match args.len() {
-    1 => saturday.run_prompt(),
-    2 => saturday.run_file(&args[1]).expect("Could not run file"),
-    _ => {
-      println!("Usage: saturday-ast [script]");
-      std::process::exit(64);
+  let mut options = Options::default();
+  let mut scripts = Vec::new();
+  let mut args = args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--nfc" => options.nfc = true,
+      "--optimize" => options.optimize = true,
+      "--strict" => options.strict = true,
+      "--strip-asserts" => options.strip_asserts = true,
+      "--vm" => options.use_vm = true,
+      flag if flag.starts_with("--max-call-depth=") => {
+        options.max_call_depth = parsed(option_value(flag), |text| {
+          text.parse().ok().filter(|depth| *depth <= MAX_CALL_DEPTH)
+        })
+      }
+      flag if flag.starts_with("--fuel=") => options.fuel = Some(flag_value(flag)),
+      "--timeout" => options.timeout = Some(parsed(args.next().as_deref(), parse_duration)),
+      flag if flag.starts_with("--timeout=") => {
+        options.timeout = Some(parsed(option_value(flag), parse_duration))
+      }
+      flag if flag.starts_with("--memory-limit=") => {
+        options.memory_limit = Some(parsed(option_value(flag), parse_size))
+      }
+      flag if flag.starts_with("--") => usage(),
+      _ => scripts.push(arg),
+    }
+  }
+
+  let script = match scripts.len() {
+    0 => None,
+    1 => Some(scripts[0].clone()),
+    _ => usage(),
+  };
+
+  // 在栈足够大的线程里执行，调用深度先到上限，报Stack overflow而不是让进程崩溃
+  let stack_size = options.max_call_depth.saturating_mul(STACK_PER_CALL);
+  let runner = std::thread::Builder::new()
+    .stack_size(stack_size.max(MIN_STACK_SIZE))
+    .spawn(move || {
+      let saturday = Saturday::new(options);
+      match script {
+        None => saturday.run_prompt(),
+        Some(script) => saturday.run_file(&script).expect("Could not run file"),
+      }
+    })
+    .unwrap_or_else(|err| {
+      eprintln!("Could not start interpreter thread with a {stack_size} byte stack: {err}");
+      std::process::exit(71);
+    });
+  if let Err(panic) = runner.join() {
+    std::panic::resume_unwind(panic);
+  }
+}
+
+/// --name=value形式的参数值，解析失败时输出用法
+fn flag_value<T: FromStr>(flag: &str) -> T {
+  match option_value(flag).map(str::parse) {
+    Some(Ok(value)) => value,
+    _ => usage(),
+  }
+}
+
+/// --name=value中的value
+fn option_value(flag: &str) -> Option<&str> {
+  flag.split_once('=').map(|(_, value)| value)
+}
+
+/// 用parse解析参数值，解析失败时输出用法
+fn parsed<T>(text: Option<&str>, parse: fn(&str) -> Option<T>) -> T {
+  text.and_then(parse).unwrap_or_else(|| usage())
+}
+
+fn usage() -> ! {
+  println!(
+    "Usage: saturday-ast [--nfc] [--optimize] [--strict] [--strip-asserts] [--vm] [--max-call-depth=N] [--fuel=N] [--timeout DURATION] [--memory-limit=SIZE] [script]"
+  );
+  std::process::exit(64);
+}
+
+/// 命令行选项
+struct Options {
+  /// 标识符是否做NFC规范化
+  nfc: bool,
+  /// 执行前是否做常量折叠等优化
+  optimize: bool,
+  /// 类型推断的警告是否按错误处理
+  strict: bool,
+  strip_asserts: bool,
+  /// 是否编译成字节码在VM中执行
+  use_vm: bool,
+  max_call_depth: usize,
+  /// 执行步数的预算，None表示不限制
+  fuel: Option<u64>,
+  /// 每次执行的时间上限
+  timeout: Option<Duration>,
+  /// 堆内存的上限（字节）
+  memory_limit: Option<usize>,
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Self {
+      nfc: false,
+      optimize: false,
+      strict: false,
+      strip_asserts: false,
+      use_vm: false,
+      max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+      fuel: None,
+      timeout: None,
+      memory_limit: None,
     }
   }
 }
 
 struct Saturday {
   interpreter: Interpreter,
+  options: Options,
 }
 
 impl Saturday {
-  pub fn new() -> Self {
+  fn new(options: Options) -> Self {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_strip_asserts(options.strip_asserts);
+    interpreter.set_max_call_depth(options.max_call_depth);
+    interpreter.set_fuel(options.fuel);
+    interpreter.set_memory_limit(options.memory_limit);
     Self {
-      interpreter: Interpreter::new(),
+      interpreter,
+      options,
     }
   }
 
@@ -57,7 +200,11 @@ impl Saturday {
     let buf = std::fs::read_to_string(path)?;
     match self.run(buf) {
       Ok(_) => std::process::exit(0),
-      Err(SaturdayResult::RuntimeError { .. }) => std::process::exit(70),
+      Err(
+        SaturdayResult::RuntimeError { .. }
+        | SaturdayResult::OutOfFuel
+        | SaturdayResult::Interrupted,
+      ) => std::process::exit(70),
       _ => std::process::exit(65),
     }
   }
@@ -72,6 +219,8 @@ impl Saturday {
           break;
         }
 
+        // Ctrl-C只中断正在执行的语句，回到提示符；等待输入时仍然退出REPL
+        let _sigint = catch_sigint(self.interpreter.interrupt_flag());
         let _ = self.run(line);
       } else {
         break;
@@ -89,6 +238,7 @@ impl Saturday {
     }
 
     let mut scanner = Scanner::new(source);
+    scanner.set_nfc(self.options.nfc);
     let tokens = scanner.scan_tokens()?;
     let mut parser = Parser::new(tokens);
     let statements = parser.parse()?;
@@ -97,11 +247,57 @@ impl Saturday {
     let s = Rc::new(statements);
     resolver.resolve(&Rc::clone(&s))?;
 
-    if resolver.success() {
-      self.interpreter.interpreter(&Rc::clone(&s))?;
-    } else {
+    if !resolver.success() {
       std::process::exit(65);
     }
-    Ok(())
+
+    let mut checker = Checker::new();
+    checker.set_strict(self.options.strict);
+    checker.check(&s)?;
+    if !checker.success() {
+      std::process::exit(65);
+    }
+
+    let s = if self.options.optimize {
+      Rc::new(Optimizer::new(&self.interpreter).optimize(&s)?)
+    } else {
+      s
+    };
+
+    // 上一次的中断已经处理过，重新计时
+    let interrupt = self.interpreter.interrupt_flag();
+    interrupt.store(false, Ordering::SeqCst);
+    let watchdog = self
+      .options
+      .timeout
+      .map(|timeout| Watchdog::start(timeout, interrupt));
+    let result = self.execute(&s);
+
+    // 运行时错误展开到这里才有完整的调用栈
+    match &result {
+      Err(SaturdayResult::Interrupted) if watchdog.as_ref().is_some_and(Watchdog::fired) => {
+        eprintln!(
+          "Execution timed out after {:?}.",
+          self.options.timeout.unwrap()
+        )
+      }
+      Err(
+        err @ (SaturdayResult::RuntimeError { .. }
+        | SaturdayResult::OutOfFuel
+        | SaturdayResult::Interrupted),
+      ) => err.report(""),
+      _ => {}
+    }
+    result
+  }
+
+  fn execute(&self, statements: &Rc<Vec<Rc<Stmt>>>) -> Result<(), SaturdayResult> {
+    if self.options.use_vm {
+      let compiler = Compiler::new(self.interpreter.strip_asserts());
+      let script = compiler.compile(statements)?;
+      vm::run(&self.interpreter, script)
+    } else {
+      self.interpreter.interpreter(statements)
+    }
   }
 }
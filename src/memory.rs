@@ -0,0 +1,111 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// # 内存计数
+/// ```
+/// 包装系统分配器，按线程记录堆上还在使用的字节数。
+/// 字符串、环境、通道缓冲这些对象的内存都从这里分配，解释器用它估算脚本占用的内存。
+/// 在一个线程分配、在另一个线程释放的内存会让两边的计数都有偏差，解释器的对象都不跨线程，
+/// 所以只是近似值
+/// ```
+struct CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+  static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+/// 线程退出时局部存储可能已经销毁，这时不再计数
+fn count(delta: isize) {
+  let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + delta));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let ptr = System.alloc(layout);
+    if !ptr.is_null() {
+      count(layout.size() as isize);
+    }
+    ptr
+  }
+
+  unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+    let ptr = System.alloc_zeroed(layout);
+    if !ptr.is_null() {
+      count(layout.size() as isize);
+    }
+    ptr
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout);
+    count(-(layout.size() as isize));
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    let new_ptr = System.realloc(ptr, layout, new_size);
+    if !new_ptr.is_null() {
+      count(new_size as isize - layout.size() as isize);
+    }
+    new_ptr
+  }
+}
+
+/// 当前线程在堆上使用的字节数
+pub fn allocated() -> usize {
+  live().max(0) as usize
+}
+
+/// 当前线程分配减去释放的字节数，释放别的线程分配的内存时可能为负
+fn live() -> isize {
+  ALLOCATED.with(Cell::get)
+}
+
+/// 解析64M、512K、1G这样的大小，不带单位时按字节计
+pub fn parse_size(text: &str) -> Option<usize> {
+  let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+    Some(i) => text.split_at(i),
+    None => (text, ""),
+  };
+  let number: usize = number.parse().ok()?;
+  let scale: usize = match unit.to_ascii_uppercase().as_str() {
+    "" | "B" => 1,
+    "K" | "KB" => 1 << 10,
+    "M" | "MB" => 1 << 20,
+    "G" | "GB" => 1 << 30,
+    _ => return None,
+  };
+
+  number.checked_mul(scale)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn counts_live_allocations() {
+    // 直接调用分配器：经过alloc()时编译器认为分配不会改动其他内存，可能调换读取计数的顺序
+    let layout = Layout::from_size_align(1 << 20, 8).unwrap();
+    let before = live();
+    let ptr = unsafe { ALLOCATOR.alloc(layout) };
+    assert_eq!(live(), before + (1 << 20));
+
+    let ptr = unsafe { ALLOCATOR.realloc(ptr, layout, 1 << 10) };
+    assert_eq!(live(), before + (1 << 10));
+    unsafe { ALLOCATOR.dealloc(ptr, Layout::from_size_align(1 << 10, 8).unwrap()) };
+    assert_eq!(live(), before);
+  }
+
+  #[test]
+  fn parses_sizes() {
+    assert_eq!(parse_size("4096"), Some(4096));
+    assert_eq!(parse_size("64M"), Some(64 << 20));
+    assert_eq!(parse_size("512kb"), Some(512 << 10));
+    assert_eq!(parse_size("1G"), Some(1 << 30));
+    assert_eq!(parse_size("10T"), None);
+    assert_eq!(parse_size("M"), None);
+  }
+}
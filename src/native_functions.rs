@@ -1,15 +1,46 @@
-use crate::callable::SaturdayCallable;
+use crate::callable::{Callable, SaturdayCallable};
 use crate::error::SaturdayResult;
+use crate::fiber::FiberOp;
+use crate::format::format;
 use crate::interpreter::Interpreter;
 use crate::object::Object;
+use crate::token::Token;
+use std::io::{stdout, Write};
+use std::rc::Rc;
 use std::time::SystemTime;
 
+/// 所有内置的全局函数，Interpreter和VM都用它初始化全局变量
+pub fn native_globals() -> Vec<(&'static str, Object)> {
+  let natives: Vec<(&'static str, Rc<dyn SaturdayCallable>)> = vec![
+    ("clock", Rc::new(NativeClock)),
+    ("next", Rc::new(NativeNext)),
+    ("spawn", Rc::new(NativeSpawn)),
+    ("yield_now", Rc::new(NativeYieldNow)),
+    ("chan", Rc::new(NativeChan)),
+    ("send", Rc::new(NativeSend)),
+    ("recv", Rc::new(NativeRecv)),
+    ("format", Rc::new(NativeFormat)),
+    ("printf", Rc::new(NativePrintf)),
+    ("write", Rc::new(NativeWrite)),
+    ("eprint", Rc::new(NativeEprint)),
+    ("gc", Rc::new(NativeGc)),
+    ("gc_stats", Rc::new(NativeGcStats)),
+    ("fuel", Rc::new(NativeFuel)),
+  ];
+
+  natives
+    .into_iter()
+    .map(|(name, func)| (name, Object::Func(Callable { func })))
+    .collect()
+}
+
 pub struct NativeClock;
 
 impl SaturdayCallable for NativeClock {
   fn call(
     &self,
     _interpreter: &Interpreter,
+    _paren: &Token,
     _arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult> {
     match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
@@ -29,3 +60,382 @@ impl SaturdayCallable for NativeClock {
     String::from("Native:Clock")
   }
 }
+
+pub struct NativeNext;
+
+impl SaturdayCallable for NativeNext {
+  /// 取生成器的下一个值，生成器结束后返回nil
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    match &arguments[0] {
+      Object::Generator(generator) => Ok(generator.resume(interpreter)?.unwrap_or(Object::Nil)),
+      other => Err(SaturdayResult::runtime_error(
+        paren,
+        &format!("next() expects a generator, got {other}."),
+      )),
+    }
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Next")
+  }
+}
+
+pub struct NativeSpawn;
+
+impl SaturdayCallable for NativeSpawn {
+  /// 用无参函数创建协程，返回协程编号
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let function = match &arguments[0] {
+      // 协程的函数体由解释器恢复执行，VM编译的闭包还不能作为协程运行
+      Object::Func(callable) if callable.as_closure().is_some() => {
+        return Err(SaturdayResult::runtime_error(
+          paren,
+          "spawn() is not supported by the VM yet.",
+        ));
+      }
+      Object::Func(callable) => callable.as_function(),
+      _ => None,
+    };
+
+    match function {
+      Some(function) if function.arity() == 0 => {
+        let id = interpreter
+          .scheduler
+          .spawn(function.to_string(), function.resumable(Vec::new()));
+        Ok(Object::Num(id as f64))
+      }
+      _ => Err(SaturdayResult::runtime_error(
+        paren,
+        &format!(
+          "spawn() expects a function without parameters, got {}.",
+          arguments[0]
+        ),
+      )),
+    }
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Spawn")
+  }
+}
+
+pub struct NativeYieldNow;
+
+impl SaturdayCallable for NativeYieldNow {
+  /// 不在协程语句中调用时，让其他协程运行一轮
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    interpreter.scheduler.run_round(interpreter)?;
+    Ok(Object::Nil)
+  }
+
+  fn arity(&self) -> usize {
+    0
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:YieldNow")
+  }
+
+  fn fiber_op(&self) -> Option<FiberOp> {
+    Some(FiberOp::YieldNow)
+  }
+}
+
+pub struct NativeChan;
+
+impl SaturdayCallable for NativeChan {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(Object::Channel(interpreter.scheduler.channel()))
+  }
+
+  fn arity(&self) -> usize {
+    0
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Chan")
+  }
+}
+
+pub struct NativeSend;
+
+impl SaturdayCallable for NativeSend {
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    match &arguments[0] {
+      Object::Channel(channel) => {
+        channel.send(arguments[1].clone());
+        Ok(Object::Nil)
+      }
+      other => Err(SaturdayResult::runtime_error(
+        paren,
+        &format!("send() expects a channel, got {other}."),
+      )),
+    }
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Send")
+  }
+
+  fn fiber_op(&self) -> Option<FiberOp> {
+    Some(FiberOp::Send)
+  }
+}
+
+pub struct NativeRecv;
+
+impl SaturdayCallable for NativeRecv {
+  /// 不在协程语句中调用时，驱动其他协程直到通道中有值
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    match &arguments[0] {
+      Object::Channel(channel) => interpreter.scheduler.recv(interpreter, paren, channel),
+      other => Err(SaturdayResult::runtime_error(
+        paren,
+        &format!("recv() expects a channel, got {other}."),
+      )),
+    }
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Recv")
+  }
+
+  fn fiber_op(&self) -> Option<FiberOp> {
+    Some(FiberOp::Recv)
+  }
+}
+
+/// 第一个参数必须是格式字符串，剩下的参数依次填入占位符
+fn format_arguments(
+  name: &str,
+  paren: &Token,
+  arguments: &[Object],
+) -> Result<String, SaturdayResult> {
+  match &arguments[0] {
+    Object::Str(template) => format(template, &arguments[1..], paren),
+    other => Err(SaturdayResult::runtime_error(
+      paren,
+      &format!("{name}() expects a format string, got {other}."),
+    )),
+  }
+}
+
+pub struct NativeFormat;
+
+impl SaturdayCallable for NativeFormat {
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(Object::Str(format_arguments("format", paren, &arguments)?))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn variadic(&self) -> bool {
+    true
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Format")
+  }
+}
+
+pub struct NativePrintf;
+
+impl SaturdayCallable for NativePrintf {
+  /// 和format一样格式化，输出时不加换行
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let text = format_arguments("printf", paren, &arguments)?;
+    print!("{text}");
+    let _ = stdout().flush();
+    Ok(Object::Nil)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn variadic(&self) -> bool {
+    true
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Printf")
+  }
+}
+
+pub struct NativeWrite;
+
+impl SaturdayCallable for NativeWrite {
+  /// 和print语句一样输出，但不加换行
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    _paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    print!("{}", arguments[0]);
+    let _ = stdout().flush();
+    Ok(Object::Nil)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Write")
+  }
+}
+
+pub struct NativeEprint;
+
+impl SaturdayCallable for NativeEprint {
+  /// 和print语句一样输出，但输出到stderr
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    _paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    eprintln!("{}", arguments[0]);
+    Ok(Object::Nil)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Eprint")
+  }
+}
+
+/// gc()：立即回收环，返回释放的环境和函数的个数
+pub struct NativeGc;
+
+impl SaturdayCallable for NativeGc {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(Object::Num(interpreter.gc.collect() as f64))
+  }
+
+  fn arity(&self) -> usize {
+    0
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Gc")
+  }
+}
+
+/// gc_stats()：回收次数、累计释放的对象和当前登记的函数
+pub struct NativeGcStats;
+
+impl SaturdayCallable for NativeGcStats {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let stats = interpreter.gc.stats();
+    Ok(Object::Str(format!(
+      "collections: {}, freed environments: {}, freed functions: {}, tracked functions: {}",
+      stats.collections, stats.environments, stats.functions, stats.tracked
+    )))
+  }
+
+  fn arity(&self) -> usize {
+    0
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:GcStats")
+  }
+}
+
+/// fuel()：剩余的执行步数，没有设置预算时返回nil
+pub struct NativeFuel;
+
+impl SaturdayCallable for NativeFuel {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(match interpreter.fuel() {
+      Some(fuel) => Object::Num(fuel as f64),
+      None => Object::Nil,
+    })
+  }
+
+  fn arity(&self) -> usize {
+    0
+  }
+
+  fn to_string(&self) -> String {
+    String::from("Native:Fuel")
+  }
+}
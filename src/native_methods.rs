@@ -0,0 +1,473 @@
+use crate::callable::{Callable, SaturdayCallable};
+use crate::error::SaturdayResult;
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::token::Token;
+use std::rc::Rc;
+
+/// 方法生成的字符串最多这么多字节，超过时报错而不是让分配失败
+const MAX_STRING_LEN: usize = 1 << 30;
+
+type MethodFn = fn(&Interpreter, &Token, &Object, &[Object]) -> Result<Object, SaturdayResult>;
+
+/// 内置类型的一个方法，receiver是调用方法的值
+pub struct NativeMethod {
+  name: &'static str,
+  arity: usize,
+  func: MethodFn,
+}
+
+const STRING_METHODS: &[NativeMethod] = &[
+  NativeMethod {
+    name: "len",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_str(receiver).chars().count() as f64)),
+  },
+  NativeMethod {
+    name: "upper",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Str(as_str(receiver).to_uppercase())),
+  },
+  NativeMethod {
+    name: "lower",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Str(as_str(receiver).to_lowercase())),
+  },
+  NativeMethod {
+    name: "trim",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Str(as_str(receiver).trim().to_string())),
+  },
+  NativeMethod {
+    name: "contains",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      let needle = str_arg(name, &args[0])?;
+      Ok(Object::Bool(as_str(receiver).contains(needle)))
+    },
+  },
+  NativeMethod {
+    name: "starts_with",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      let prefix = str_arg(name, &args[0])?;
+      Ok(Object::Bool(as_str(receiver).starts_with(prefix)))
+    },
+  },
+  NativeMethod {
+    name: "ends_with",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      let suffix = str_arg(name, &args[0])?;
+      Ok(Object::Bool(as_str(receiver).ends_with(suffix)))
+    },
+  },
+  NativeMethod {
+    name: "index_of",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      // 按字符而不是字节计算位置，找不到时返回-1
+      let receiver = as_str(receiver);
+      let index = receiver
+        .find(str_arg(name, &args[0])?)
+        .map(|byte| receiver[..byte].chars().count() as f64)
+        .unwrap_or(-1.0);
+      Ok(Object::Num(index))
+    },
+  },
+  NativeMethod {
+    name: "char_at",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      let index = index_arg(name, &args[0])?;
+      Ok(
+        as_str(receiver)
+          .chars()
+          .nth(index)
+          .map(|ch| Object::Str(ch.to_string()))
+          .unwrap_or(Object::Nil),
+      )
+    },
+  },
+  NativeMethod {
+    name: "replace",
+    arity: 2,
+    func: |interpreter, name, receiver, args| {
+      let from = str_arg(name, &args[0])?;
+      let to = str_arg(name, &args[1])?;
+      let receiver = as_str(receiver);
+      let grown = receiver.matches(from).count().checked_mul(to.len());
+      let size = string_len(
+        name,
+        grown.and_then(|grown| grown.checked_add(receiver.len())),
+      )?;
+      interpreter.reserve_memory(name, size)?;
+      Ok(Object::Str(receiver.replace(from, to)))
+    },
+  },
+  NativeMethod {
+    name: "repeat",
+    arity: 1,
+    func: |interpreter, name, receiver, args| {
+      let count = index_arg(name, &args[0])?;
+      let size = string_len(name, as_str(receiver).len().checked_mul(count))?;
+      interpreter.reserve_memory(name, size)?;
+      Ok(Object::Str(as_str(receiver).repeat(count)))
+    },
+  },
+];
+
+const NUMBER_METHODS: &[NativeMethod] = &[
+  NativeMethod {
+    name: "round",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_num(receiver).round())),
+  },
+  NativeMethod {
+    name: "floor",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_num(receiver).floor())),
+  },
+  NativeMethod {
+    name: "ceil",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_num(receiver).ceil())),
+  },
+  NativeMethod {
+    name: "abs",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_num(receiver).abs())),
+  },
+  NativeMethod {
+    name: "sqrt",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_num(receiver).sqrt())),
+  },
+  NativeMethod {
+    name: "min",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      let other = num_arg(name, &args[0])?;
+      Ok(Object::Num(as_num(receiver).min(other)))
+    },
+  },
+  NativeMethod {
+    name: "max",
+    arity: 1,
+    func: |_, name, receiver, args| {
+      let other = num_arg(name, &args[0])?;
+      Ok(Object::Num(as_num(receiver).max(other)))
+    },
+  },
+  NativeMethod {
+    name: "to_string",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Str(receiver.to_string())),
+  },
+];
+
+const BOOL_METHODS: &[NativeMethod] = &[NativeMethod {
+  name: "to_string",
+  arity: 0,
+  func: |_, _, receiver, _| Ok(Object::Str(receiver.to_string())),
+}];
+
+const GENERATOR_METHODS: &[NativeMethod] = &[NativeMethod {
+  name: "next",
+  arity: 0,
+  func: |interpreter, _, receiver, _| match receiver {
+    Object::Generator(generator) => Ok(generator.resume(interpreter)?.unwrap_or(Object::Nil)),
+    _ => unreachable!(),
+  },
+}];
+
+/// 列表和字符串一样是不可变的值，push返回追加了元素的新列表，原来的列表不变
+const LIST_METHODS: &[NativeMethod] = &[
+  NativeMethod {
+    name: "len",
+    arity: 0,
+    func: |_, _, receiver, _| Ok(Object::Num(as_list(receiver).len() as f64)),
+  },
+  NativeMethod {
+    name: "push",
+    arity: 1,
+    func: |interpreter, name, receiver, args| {
+      let list = as_list(receiver);
+      interpreter.reserve_memory(name, (list.len() + 1) * std::mem::size_of::<Object>())?;
+      let mut pushed = Vec::with_capacity(list.len() + 1);
+      pushed.extend_from_slice(list);
+      pushed.push(args[0].clone());
+      Ok(Object::List(Rc::new(pushed)))
+    },
+  },
+];
+
+fn as_str(receiver: &Object) -> &str {
+  match receiver {
+    Object::Str(s) => s,
+    _ => unreachable!(),
+  }
+}
+
+fn as_list(receiver: &Object) -> &[Object] {
+  match receiver {
+    Object::List(list) => list,
+    _ => unreachable!(),
+  }
+}
+
+fn as_num(receiver: &Object) -> f64 {
+  match receiver {
+    Object::Num(n) => *n,
+    _ => unreachable!(),
+  }
+}
+
+fn str_arg<'a>(name: &Token, arg: &'a Object) -> Result<&'a str, SaturdayResult> {
+  match arg {
+    Object::Str(s) => Ok(s),
+    other => Err(SaturdayResult::runtime_error(
+      name,
+      &format!(
+        "Argument to '{}' must be a string, got {}.",
+        name.as_string(),
+        type_name(other)
+      ),
+    )),
+  }
+}
+
+fn num_arg(name: &Token, arg: &Object) -> Result<f64, SaturdayResult> {
+  match arg {
+    Object::Num(n) => Ok(*n),
+    other => Err(SaturdayResult::runtime_error(
+      name,
+      &format!(
+        "Argument to '{}' must be a number, got {}.",
+        name.as_string(),
+        type_name(other)
+      ),
+    )),
+  }
+}
+
+fn index_arg(name: &Token, arg: &Object) -> Result<usize, SaturdayResult> {
+  match arg {
+    Object::Num(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+    other => Err(SaturdayResult::runtime_error(
+      name,
+      &format!(
+        "Argument to '{}' must be a non-negative integer, got {other}.",
+        name.as_string()
+      ),
+    )),
+  }
+}
+
+/// 检查要生成的字符串长度，溢出或超过上限时报错
+fn string_len(name: &Token, len: Option<usize>) -> Result<usize, SaturdayResult> {
+  match len {
+    Some(len) if len <= MAX_STRING_LEN => Ok(len),
+    _ => Err(SaturdayResult::runtime_error(
+      name,
+      &format!("Result of '{}' is too large.", name.as_string()),
+    )),
+  }
+}
+
+/// 值的类型名，用于错误信息
+pub fn type_name(value: &Object) -> &'static str {
+  match value {
+    Object::Num(_) => "number",
+    Object::Str(_) => "string",
+    Object::Bool(_) => "bool",
+    Object::Func(_) => "function",
+    Object::Generator(_) => "generator",
+    Object::Channel(_) => "channel",
+    Object::Enum(_) => "enum",
+    Object::Variant(_) => "enum variant",
+    Object::List(_) => "list",
+    Object::Record(_) => "record",
+    Object::Nil => "nil",
+    Object::ArithmeticError => unreachable!(),
+  }
+}
+
+fn methods(value: &Object) -> &'static [NativeMethod] {
+  match value {
+    Object::Str(_) => STRING_METHODS,
+    Object::Num(_) => NUMBER_METHODS,
+    Object::Bool(_) => BOOL_METHODS,
+    Object::Generator(_) => GENERATOR_METHODS,
+    Object::List(_) => LIST_METHODS,
+    _ => &[],
+  }
+}
+
+/// 编辑距离，用于给拼错的方法名提供建议
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut row: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.chars().enumerate() {
+    let mut previous = row[0];
+    row[0] = i + 1;
+    for (j, &cb) in b.iter().enumerate() {
+      let current = row[j + 1];
+      row[j + 1] = if ca == cb {
+        previous
+      } else {
+        1 + previous.min(row[j]).min(current)
+      };
+      previous = current;
+    }
+  }
+  row[b.len()]
+}
+
+/// # 查找方法
+/// ```
+/// 找到时返回绑定了receiver的方法，找不到时报错并提示相近的方法名
+/// ```
+pub fn bind(receiver: Object, name: &Token) -> Result<Object, SaturdayResult> {
+  let table = methods(&receiver);
+  let wanted = name.as_string();
+  if let Some(method) = table.iter().find(|m| m.name == wanted) {
+    return Ok(Object::Func(Callable {
+      func: Rc::new(BoundMethod {
+        receiver,
+        name: name.dup(),
+        method,
+      }),
+    }));
+  }
+
+  let mut message = format!("Undefined method '{wanted}' for {}.", type_name(&receiver));
+  let limit = (wanted.chars().count() / 3).max(2);
+  let mut close: Vec<(usize, &str)> = table
+    .iter()
+    .map(|m| (edit_distance(&wanted, m.name), m.name))
+    .filter(|&(distance, _)| distance <= limit)
+    .collect();
+  close.sort();
+  if !close.is_empty() {
+    let names: Vec<String> = close.iter().map(|(_, n)| format!("'{n}'")).collect();
+    message.push_str(&format!(" Did you mean {}?", names.join(" or ")));
+  }
+
+  Err(SaturdayResult::runtime_error(name, &message))
+}
+
+struct BoundMethod {
+  receiver: Object,
+  name: Token,
+  method: &'static NativeMethod,
+}
+
+impl SaturdayCallable for BoundMethod {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    (self.method.func)(interpreter, &self.name, &self.receiver, &arguments)
+  }
+
+  fn arity(&self) -> usize {
+    self.method.arity
+  }
+
+  fn to_string(&self) -> String {
+    format!(
+      "<method {}.{}>",
+      type_name(&self.receiver),
+      self.method.name
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::token_type::TokenType;
+
+  fn call(receiver: Object, name: &str, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let terp = Interpreter::new();
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, 1);
+    match bind(receiver, &token)? {
+      Object::Func(method) => method.call(&terp, &token, arguments),
+      _ => unreachable!(),
+    }
+  }
+
+  #[test]
+  fn string_methods() {
+    let s = || Object::Str("héllo".to_string());
+    assert_eq!(call(s(), "len", vec![]).ok(), Some(Object::Num(5.0)));
+    assert_eq!(
+      call(s(), "upper", vec![]).ok(),
+      Some(Object::Str("HÉLLO".to_string()))
+    );
+    assert_eq!(
+      call(s(), "index_of", vec![Object::Str("l".to_string())]).ok(),
+      Some(Object::Num(2.0))
+    );
+    assert!(call(s(), "contains", vec![Object::Num(1.0)]).is_err());
+  }
+
+  #[test]
+  fn oversized_strings_are_errors() {
+    let s = || Object::Str("ab".to_string());
+    assert_eq!(
+      call(s(), "repeat", vec![Object::Num(3.0)]).ok(),
+      Some(Object::Str("ababab".to_string()))
+    );
+    for count in [1e12, 1e20] {
+      match call(s(), "repeat", vec![Object::Num(count)]) {
+        Err(SaturdayResult::RuntimeError { message, .. }) => {
+          assert_eq!(message, "Result of 'repeat' is too large.");
+        }
+        _ => panic!("expected a runtime error"),
+      }
+    }
+  }
+
+  #[test]
+  fn number_methods() {
+    assert_eq!(
+      call(Object::Num(2.5), "floor", vec![]).ok(),
+      Some(Object::Num(2.0))
+    );
+    assert_eq!(
+      call(Object::Num(2.0), "max", vec![Object::Num(7.0)]).ok(),
+      Some(Object::Num(7.0))
+    );
+  }
+
+  #[test]
+  fn list_push_returns_a_new_list() {
+    let xs = Object::List(Rc::new(vec![Object::Num(1.0)]));
+    assert_eq!(
+      call(xs.clone(), "push", vec![Object::Num(4.0)]).ok(),
+      Some(Object::List(Rc::new(vec![
+        Object::Num(1.0),
+        Object::Num(4.0)
+      ])))
+    );
+    assert_eq!(call(xs, "len", vec![]).ok(), Some(Object::Num(1.0)));
+  }
+
+  #[test]
+  fn unknown_method_suggests_close_names() {
+    let token = Token::new(TokenType::Identifier, "uper".to_string(), None, 1);
+    match bind(Object::Str("abc".to_string()), &token) {
+      Err(SaturdayResult::RuntimeError { message, .. }) => {
+        assert_eq!(
+          message,
+          "Undefined method 'uper' for string. Did you mean 'upper'?"
+        );
+      }
+      _ => panic!("expected a runtime error"),
+    }
+  }
+}
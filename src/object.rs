@@ -1,6 +1,11 @@
 use crate::callable::Callable;
+use crate::fiber::Channel;
+use crate::generator::Generator;
+use crate::saturday_enum::{EnumVariant, SaturdayEnum};
+use crate::saturday_record::Record;
 use std::fmt;
 use std::fmt::Formatter;
+use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Object {
@@ -8,6 +13,12 @@ pub enum Object {
   Str(String),
   Bool(bool),
   Func(Callable),
+  Generator(Rc<Generator>),
+  Channel(Rc<Channel>),
+  Enum(Rc<SaturdayEnum>),
+  Variant(Rc<EnumVariant>),
+  List(Rc<Vec<Object>>),
+  Record(Rc<Record>),
   Nil,
   ArithmeticError,
 }
@@ -25,6 +36,15 @@ impl fmt::Display for Object {
         }
       }
       Object::Func(_) => write!(f, "<func>"),
+      Object::Generator(g) => write!(f, "{g}"),
+      Object::Channel(c) => write!(f, "{c}"),
+      Object::Enum(e) => write!(f, "{e}"),
+      Object::Variant(v) => write!(f, "{v}"),
+      Object::List(elements) => {
+        let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+        write!(f, "[{}]", elements.join(", "))
+      }
+      Object::Record(r) => write!(f, "{r}"),
       Object::Nil => write!(f, "nil"),
       Object::ArithmeticError => panic!("Should not be trying to print this"),
     }
@@ -0,0 +1,351 @@
+use crate::error::SaturdayResult;
+use crate::expr::{
+  BinaryExpr, Expr, ExprRewriter, GroupingExpr, LiteralExpr, LogicalExpr, UnaryExpr,
+};
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::pattern::MatchArm;
+use crate::stmt::{AssertStmt, BlockStmt, IfStmt, MatchStmt, Stmt, StmtRewriter, WhileStmt};
+use crate::token_type::TokenType;
+use std::rc::Rc;
+
+/// # 优化器
+/// ```
+/// 在resolver之后、执行之前改写语法树：折叠常量运算和字符串拼接，
+/// 去掉条件为常量的if分支和条件为假的while循环。
+/// 只折叠一定成功的运算，会出错的运算（比如 "a" - 1）保留到运行时在原来的位置报错
+/// ```
+pub struct Optimizer<'a> {
+  interpreter: &'a Interpreter,
+}
+
+impl<'a> Optimizer<'a> {
+  pub fn new(interpreter: &'a Interpreter) -> Self {
+    Self { interpreter }
+  }
+
+  pub fn optimize(&self, statements: &[Rc<Stmt>]) -> Result<Vec<Rc<Stmt>>, SaturdayResult> {
+    statements
+      .iter()
+      .map(|statement| self.rewrite_stmt(statement.clone()))
+      .collect()
+  }
+
+  fn literal(expr: &Expr) -> Option<&Object> {
+    match expr {
+      Expr::Literal(literal) => literal.value.as_ref(),
+      _ => None,
+    }
+  }
+
+  fn make_literal(value: Object) -> Rc<Expr> {
+    Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: Some(value) })))
+  }
+
+  fn empty_block() -> Rc<Stmt> {
+    Rc::new(Stmt::Block(Rc::new(BlockStmt {
+      statements: Rc::new(Vec::new()),
+    })))
+  }
+}
+
+impl<'a> ExprRewriter for Optimizer<'a> {
+  fn replaced_expr(&self, old: &Rc<Expr>, new: &Rc<Expr>) {
+    self.interpreter.replace_expr(old, new);
+  }
+
+  fn rewrite_binary_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &BinaryExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    let rewritten = self.walk_binary_expr(wrapper, expr)?;
+    let Expr::Binary(binary) = &*rewritten else {
+      return Ok(rewritten);
+    };
+
+    // 位运算可能因为小数报错，不折叠
+    let foldable = matches!(
+      binary.operator.token_type(),
+      TokenType::Plus
+        | TokenType::Minus
+        | TokenType::Star
+        | TokenType::Slash
+        | TokenType::Greater
+        | TokenType::GreaterEqual
+        | TokenType::Less
+        | TokenType::LessEqual
+        | TokenType::Equal
+        | TokenType::BangEqual
+    );
+    match (Self::literal(&binary.left), Self::literal(&binary.right)) {
+      (Some(left), Some(right)) if foldable => {
+        match Interpreter::arithmetic(binary.operator.token_type(), left.clone(), right.clone()) {
+          Object::ArithmeticError => Ok(rewritten),
+          value => Ok(Self::make_literal(value)),
+        }
+      }
+      _ => Ok(rewritten),
+    }
+  }
+
+  fn rewrite_grouping_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &GroupingExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    let inner = self.rewrite_expr(expr.expression.clone())?;
+    if Self::literal(&inner).is_some() {
+      return Ok(inner);
+    }
+
+    self.walk_grouping_expr(wrapper, expr)
+  }
+
+  fn rewrite_logical_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &LogicalExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    let rewritten = self.walk_logical_expr(wrapper, expr)?;
+    let Expr::Logical(logical) = &*rewritten else {
+      return Ok(rewritten);
+    };
+    let Some(left) = Self::literal(&logical.left) else {
+      return Ok(rewritten);
+    };
+
+    // 左边是常量时结果要么是左边，要么是右边
+    let keep_left = match logical.operator.token_type() {
+      TokenType::QuestionQuestion => *left != Object::Nil,
+      TokenType::Or => self.interpreter.is_truthy(left),
+      _ => !self.interpreter.is_truthy(left),
+    };
+    Ok(if keep_left {
+      logical.left.clone()
+    } else {
+      logical.right.clone()
+    })
+  }
+
+  fn rewrite_unary_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &UnaryExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    let rewritten = self.walk_unary_expr(wrapper, expr)?;
+    let Expr::Unary(unary) = &*rewritten else {
+      return Ok(rewritten);
+    };
+
+    let value = match (unary.operator.token_type(), Self::literal(&unary.right)) {
+      (TokenType::Minus, Some(Object::Num(n))) => Object::Num(-n),
+      (TokenType::Bang, Some(value)) => Object::Bool(!self.interpreter.is_truthy(value)),
+      _ => return Ok(rewritten),
+    };
+    Ok(Self::make_literal(value))
+  }
+}
+
+impl<'a> StmtRewriter for Optimizer<'a> {
+  fn rewrite_assert_stmt(
+    &self,
+    wrapper: Rc<Stmt>,
+    stmt: &AssertStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    // 二元条件失败时要报告两边的值，只折叠两边，保留最外层的比较
+    let Expr::Binary(binary) = &*stmt.condition else {
+      return self.walk_assert_stmt(wrapper, stmt);
+    };
+    let left = self.rewrite_expr(binary.left.clone())?;
+    let right = self.rewrite_expr(binary.right.clone())?;
+    let message = match &stmt.message {
+      Some(message) => Some(self.rewrite_expr(message.clone())?),
+      None => None,
+    };
+    let changed = !Rc::ptr_eq(&left, &binary.left)
+      || !Rc::ptr_eq(&right, &binary.right)
+      || matches!((&message, &stmt.message), (Some(a), Some(b)) if !Rc::ptr_eq(a, b));
+    if !changed {
+      return Ok(wrapper);
+    }
+
+    let condition = Rc::new(Expr::Binary(Rc::new(BinaryExpr {
+      left,
+      operator: binary.operator.clone(),
+      right,
+    })));
+    self.replaced_expr(&stmt.condition, &condition);
+    Ok(Rc::new(Stmt::Assert(Rc::new(AssertStmt {
+      keyword: stmt.keyword.clone(),
+      condition,
+      message,
+      source: stmt.source.clone(),
+    }))))
+  }
+
+  fn rewrite_if_stmt(&self, wrapper: Rc<Stmt>, stmt: &IfStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    let rewritten = self.walk_if_stmt(wrapper, stmt)?;
+    let Stmt::If(stmt) = &*rewritten else {
+      return Ok(rewritten);
+    };
+    let Some(condition) = Self::literal(&stmt.condition) else {
+      return Ok(rewritten);
+    };
+
+    if self.interpreter.is_truthy(condition) {
+      Ok(stmt.then_branch.clone())
+    } else {
+      Ok(stmt.else_branch.clone().unwrap_or_else(Self::empty_block))
+    }
+  }
+
+  fn rewrite_match_stmt(
+    &self,
+    wrapper: Rc<Stmt>,
+    stmt: &MatchStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    // MatchArm不是语法树节点，生成的walk不会进入分支，这里单独改写guard和分支体
+    let subject = self.rewrite_expr(stmt.subject.clone())?;
+    let mut changed = !Rc::ptr_eq(&subject, &stmt.subject);
+    let mut arms = Vec::new();
+    for arm in stmt.arms.iter() {
+      let guard = match &arm.guard {
+        Some(guard) => Some(self.rewrite_expr(guard.clone())?),
+        None => None,
+      };
+      let body = self.rewrite_stmt(arm.body.clone())?;
+      changed |= !Rc::ptr_eq(&body, &arm.body)
+        || matches!((&guard, &arm.guard), (Some(a), Some(b)) if !Rc::ptr_eq(a, b));
+      arms.push(MatchArm {
+        pattern: arm.pattern.clone(),
+        guard,
+        body,
+      });
+    }
+
+    if !changed {
+      return Ok(wrapper);
+    }
+    Ok(Rc::new(Stmt::Match(Rc::new(MatchStmt {
+      keyword: stmt.keyword.clone(),
+      subject,
+      arms: Rc::new(arms),
+    }))))
+  }
+
+  fn rewrite_while_stmt(
+    &self,
+    wrapper: Rc<Stmt>,
+    stmt: &WhileStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    let rewritten = self.walk_while_stmt(wrapper, stmt)?;
+    let Stmt::While(stmt) = &*rewritten else {
+      return Ok(rewritten);
+    };
+
+    match Self::literal(&stmt.condition) {
+      Some(condition) if !self.interpreter.is_truthy(condition) => Ok(Self::empty_block()),
+      _ => Ok(rewritten),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::scanner::Scanner;
+  use crate::token::Token;
+
+  fn optimize(source: &str) -> (Interpreter, Vec<Rc<Stmt>>) {
+    let interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(&interpreter);
+    assert!(resolver.resolve(&statements).is_ok() && resolver.success());
+    let optimized = Optimizer::new(&interpreter)
+      .optimize(&statements)
+      .ok()
+      .unwrap();
+    (interpreter, optimized)
+  }
+
+  fn initializer(stmt: &Stmt) -> Option<Object> {
+    match stmt {
+      Stmt::Def(def) => Optimizer::literal(def.initializer.as_ref()?).cloned(),
+      _ => None,
+    }
+  }
+
+  #[test]
+  fn folds_constant_expressions() {
+    let (_, statements) = optimize(
+      "def a = (1 + 2) * 4;
+       def b = \"n=\" + (10 / 4);
+       def c = !(1 < 2) or nil ?? 3;
+       def d = -(2 - 5);",
+    );
+    let values: Vec<Option<Object>> = statements.iter().map(|s| initializer(s)).collect();
+    assert_eq!(
+      values,
+      vec![
+        Some(Object::Num(12.0)),
+        Some(Object::Str("n=2.5".to_string())),
+        Some(Object::Num(3.0)),
+        Some(Object::Num(3.0)),
+      ]
+    );
+  }
+
+  #[test]
+  fn keeps_operations_that_fail_at_runtime() {
+    let (interpreter, statements) = optimize("def a = \"a\" - 1;\ndef b = 1 | 2;");
+    assert!(initializer(&statements[0]).is_none());
+    assert!(initializer(&statements[1]).is_none());
+
+    let result = interpreter.interpreter(&statements);
+    assert!(matches!(result, Err(SaturdayResult::RuntimeError { token, .. }) if token.line == 1));
+  }
+
+  #[test]
+  fn keeps_assert_operands() {
+    let (interpreter, statements) = optimize("def x = 1;\nassert 1 + 1 == x * (1 + 2);");
+    let Stmt::Assert(assert) = &*statements[1] else {
+      panic!("expect assert");
+    };
+    let Expr::Binary(binary) = &*assert.condition else {
+      panic!("expect binary condition");
+    };
+    assert_eq!(Optimizer::literal(&binary.left), Some(&Object::Num(2.0)));
+
+    let result = interpreter.interpreter(&statements);
+    assert!(
+      matches!(result, Err(SaturdayResult::RuntimeError { message, .. })
+      if message == "assert failed: 1 + 1 == x * (1 + 2) (left: 2, right: 3) at line 2")
+    );
+  }
+
+  #[test]
+  fn removes_constant_branches_and_dead_loops() {
+    let (interpreter, statements) = optimize(
+      "def x = 0;
+       if 1 > 2 { x = 1; } else { x = 2; }
+       while false { x = 3; }
+       fun f(n) { def y = n; if true { y = y + 1 * 2; } return y; }
+       def z = f(x);",
+    );
+    assert!(matches!(&*statements[1], Stmt::Block(_)));
+    assert!(matches!(&*statements[2], Stmt::Block(b) if b.statements.is_empty()));
+    assert!(interpreter.interpreter(&statements).is_ok());
+
+    // 改写过的赋值语句仍然能找到resolver解析的局部变量
+    let z = Token::new(TokenType::Identifier, "z".to_string(), None, 0);
+    assert_eq!(
+      interpreter.globals.borrow().get(&z).ok(),
+      Some(Object::Num(4.0))
+    );
+  }
+}
@@ -1,14 +1,17 @@
 use crate::expr::{
-  AssignExpr, BinaryExpr, CallExpr, Expr, GroupingExpr, LiteralExpr, LogicalExpr, UnaryExpr,
-  VariableExpr,
+  AssignExpr, BinaryExpr, CallExpr, Expr, GetExpr, GroupingExpr, ListExpr, LiteralExpr,
+  LogicalExpr, UnaryExpr, VariableExpr,
 };
 use crate::object::Object;
+use crate::pattern::{MatchArm, Pattern};
+use crate::saturday_enum::VariantDecl;
 use crate::stmt::{
-  BlockStmt, BreakStmt, DefStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt,
-  WhileStmt,
+  AssertStmt, BlockStmt, BreakStmt, DefStmt, EnumStmt, ExpressionStmt, ForInStmt, FunctionStmt,
+  IfStmt, MatchStmt, PrintStmt, RecordStmt, ReturnStmt, Stmt, WhileStmt, YieldStmt,
 };
 use crate::token::Token;
 use crate::token_type::*;
+use crate::types::{Type, TypeAnnotation};
 use crate::SaturdayResult;
 use std::rc::Rc;
 
@@ -46,6 +49,10 @@ impl<'a> Parser<'a> {
       self.function("function")
     } else if self.is_match(&[TokenType::Def]) {
       self.def_declaration()
+    } else if self.is_match(&[TokenType::Enum]) {
+      self.enum_declaration()
+    } else if self.is_match(&[TokenType::Record]) {
+      self.record_declaration()
     } else {
       self.statement()
     };
@@ -57,8 +64,75 @@ impl<'a> Parser<'a> {
     result
   }
 
+  /// enum Name { Variant(field, ...), Unit, }
+  fn enum_declaration(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let name = self.consume(TokenType::Identifier, "Expect enum name.")?;
+    self.consume(TokenType::LeftBrace, "Expect '{' before enum body.")?;
+
+    let mut variants: Vec<VariantDecl> = Vec::new();
+    while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+      let variant = self.consume(TokenType::Identifier, "Expect variant name.")?;
+      if variants.iter().any(|v| v.name.symbol == variant.symbol) {
+        self.error(&variant, "Duplicate variant in enum.");
+      }
+
+      let mut fields = Vec::new();
+      if self.is_match(&[TokenType::LeftParen]) {
+        if !self.check(TokenType::RightParen) {
+          loop {
+            fields.push(self.consume(TokenType::Identifier, "Expect field name.")?);
+            if !self.is_match(&[TokenType::Comma]) {
+              break;
+            }
+          }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after variant fields.")?;
+      }
+      variants.push(VariantDecl {
+        name: variant,
+        fields,
+      });
+
+      if !self.is_match(&[TokenType::Comma]) {
+        break;
+      }
+    }
+
+    self.consume(TokenType::RightBrace, "Expect '}' after enum body.")?;
+    Ok(Rc::new(Stmt::Enum(Rc::new(EnumStmt {
+      name,
+      variants: Rc::new(variants),
+    }))))
+  }
+
+  /// record Name { field, ... }
+  fn record_declaration(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let name = self.consume(TokenType::Identifier, "Expect record name.")?;
+    self.consume(TokenType::LeftBrace, "Expect '{' before record body.")?;
+
+    let mut fields: Vec<Token> = Vec::new();
+    while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+      let field = self.consume(TokenType::Identifier, "Expect field name.")?;
+      if fields.iter().any(|f| f.symbol == field.symbol) {
+        self.error(&field, "Duplicate field in record.");
+      }
+      fields.push(field);
+
+      if !self.is_match(&[TokenType::Comma]) {
+        break;
+      }
+    }
+
+    self.consume(TokenType::RightBrace, "Expect '}' after record body.")?;
+    Ok(Rc::new(Stmt::Record(Rc::new(RecordStmt {
+      name,
+      fields: Rc::new(fields),
+    }))))
+  }
+
   fn def_declaration(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
     let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
+    let annotation = self.optional_annotation()?;
     let initializer = if self.is_match(&[TokenType::Assign]) {
       Some(Rc::new(self.expression()?))
     } else {
@@ -69,7 +143,37 @@ impl<'a> Parser<'a> {
       TokenType::SemiColon,
       "Expect ';' after variable declaration",
     )?;
-    Ok(Rc::new(Stmt::Def(Rc::new(DefStmt { name, initializer }))))
+    Ok(Rc::new(Stmt::Def(Rc::new(DefStmt {
+      name,
+      initializer,
+      annotation,
+    }))))
+  }
+
+  /// 名字后面可选的 `: type`
+  fn optional_annotation(&mut self) -> Result<Option<TypeAnnotation>, SaturdayResult> {
+    if self.is_match(&[TokenType::Colon]) {
+      Ok(Some(self.type_annotation()?))
+    } else {
+      Ok(None)
+    }
+  }
+
+  /// 类型名，nil和fun是关键字，需要单独接受
+  fn type_annotation(&mut self) -> Result<TypeAnnotation, SaturdayResult> {
+    if !self.is_match(&[TokenType::Identifier, TokenType::Nil, TokenType::Fun]) {
+      let peek = self.peek().dup();
+      return Err(self.error(&peek, "Expect type name."));
+    }
+
+    let token = self.previous().dup();
+    match Type::from_name(&token.lexeme()) {
+      Some(ty) => Ok(TypeAnnotation { token, ty }),
+      None => {
+        let message = format!("Unknown type '{}'.", token.lexeme());
+        Err(self.error(&token, &message))
+      }
+    }
   }
 
   fn while_statement(&mut self) -> Result<Stmt, SaturdayResult> {
@@ -90,6 +194,10 @@ impl<'a> Parser<'a> {
   }
 
   fn statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    if self.is_match(&[TokenType::Assert]) {
+      return Ok(Rc::new(self.assert_statement()?));
+    }
+
     if self.is_match(&[TokenType::Break]) {
       let token = self.previous().dup();
       self.consume(TokenType::SemiColon, "expect ';' after break statement.")?;
@@ -104,6 +212,10 @@ impl<'a> Parser<'a> {
       return Ok(Rc::new(self.if_statement()?));
     }
 
+    if self.is_match(&[TokenType::Match]) {
+      return Ok(Rc::new(self.match_statement()?));
+    }
+
     if self.is_match(&[TokenType::Print]) {
       return Ok(Rc::new(self.print_statement()?));
     }
@@ -116,6 +228,10 @@ impl<'a> Parser<'a> {
       return Ok(Rc::new(self.while_statement()?));
     }
 
+    if self.is_match(&[TokenType::Yield]) {
+      return Ok(Rc::new(self.yield_statement()?));
+    }
+
     if self.is_match(&[TokenType::LeftBrace]) {
       return Ok(Rc::new(Stmt::Block(Rc::new(BlockStmt {
         statements: Rc::new(self.block()?),
@@ -126,6 +242,10 @@ impl<'a> Parser<'a> {
   }
 
   fn for_statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    if self.check(TokenType::Identifier) && self.check_next(TokenType::In) {
+      return self.for_in_statement();
+    }
+
     let initializer = if self.is_match(&[TokenType::SemiColon]) {
       None
     } else if self.is_match(&[TokenType::Def]) {
@@ -182,6 +302,26 @@ impl<'a> Parser<'a> {
     Ok(body)
   }
 
+  /// for x in generator { ... }
+  fn for_in_statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let name = self.advance().dup();
+    self.consume(TokenType::In, "Expect 'in' after loop variable.")?;
+    let iterable = Rc::new(self.expression()?);
+    if !self.peek().is(TokenType::LeftBrace) {
+      return Err(SaturdayResult::parse_error(
+        self.peek(),
+        "for body must wrap by '{}'.",
+      ));
+    }
+
+    let body = self.statement()?;
+    Ok(Rc::new(Stmt::ForIn(Rc::new(ForInStmt {
+      name,
+      iterable,
+      body,
+    }))))
+  }
+
   fn if_statement(&mut self) -> Result<Stmt, SaturdayResult> {
     // 实现condition不带括号且必须有{的条件语句
     let condition = Rc::new(self.expression()?);
@@ -213,6 +353,224 @@ impl<'a> Parser<'a> {
     })))
   }
 
+  /// # match语句
+  /// ```
+  /// match subject {
+  ///   pattern [if guard] => { ... }
+  /// }
+  /// ```
+  fn match_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let subject = Rc::new(self.expression()?);
+    self.consume(TokenType::LeftBrace, "Expect '{' after match subject.")?;
+
+    let mut arms = Vec::new();
+    while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+      let pattern = self.pattern()?;
+      let guard = if self.is_match(&[TokenType::If]) {
+        Some(Rc::new(self.expression()?))
+      } else {
+        None
+      };
+
+      self.consume(TokenType::FatArrow, "Expect '=>' after match pattern.")?;
+      if !self.peek().is(TokenType::LeftBrace) {
+        return Err(SaturdayResult::parse_error(
+          self.peek(),
+          "match arm must wrap by '{}'.",
+        ));
+      }
+
+      let body = self.statement()?;
+      arms.push(MatchArm {
+        pattern,
+        guard,
+        body,
+      });
+    }
+
+    self.consume(TokenType::RightBrace, "Expect '}' after match arms.")?;
+    Ok(Stmt::Match(Rc::new(MatchStmt {
+      keyword,
+      subject,
+      arms: Rc::new(arms),
+    })))
+  }
+
+  fn pattern(&mut self) -> Result<Pattern, SaturdayResult> {
+    if self.check(TokenType::Identifier) && self.check_next(TokenType::Dot) {
+      return self.variant_pattern();
+    }
+
+    if self.check(TokenType::Identifier) && self.check_next(TokenType::LeftBrace) {
+      return self.record_pattern();
+    }
+
+    if self.is_match(&[TokenType::LeftBracket]) {
+      return self.list_pattern();
+    }
+
+    if self.is_match(&[TokenType::Identifier]) {
+      let name = self.previous().dup();
+      return Ok(if &*name.lexeme() == "_" {
+        Pattern::Wildcard { token: name }
+      } else {
+        Pattern::Binding { name }
+      });
+    }
+
+    if self.is_match(&[TokenType::Number, TokenType::String]) {
+      let token = self.previous().dup();
+      let value = token.literal.clone().unwrap();
+      return Ok(Pattern::Literal { token, value });
+    }
+
+    if self.is_match(&[TokenType::True, TokenType::False, TokenType::Nil]) {
+      let token = self.previous().dup();
+      let value = match token.token_type() {
+        TokenType::True => Object::Bool(true),
+        TokenType::False => Object::Bool(false),
+        _ => Object::Nil,
+      };
+      return Ok(Pattern::Literal { token, value });
+    }
+
+    if self.check(TokenType::Minus) && self.check_next(TokenType::Number) {
+      let token = self.advance().dup();
+      let value = match &self.advance().literal {
+        Some(Object::Num(n)) => Object::Num(-n),
+        _ => unreachable!(),
+      };
+      return Ok(Pattern::Literal { token, value });
+    }
+
+    let peek = self.peek().dup();
+    Err(self.error(&peek, "Expect pattern."))
+  }
+
+  /// Enum.Variant 或 Enum.Variant(p1, p2, ...)
+  fn variant_pattern(&mut self) -> Result<Pattern, SaturdayResult> {
+    let enum_name = Rc::new(Expr::Variable(Rc::new(VariableExpr {
+      name: self.advance().dup(),
+    })));
+    self.advance();
+    let variant = self.consume(TokenType::Identifier, "Expect variant name after '.'.")?;
+
+    let mut fields = Vec::new();
+    if self.is_match(&[TokenType::LeftParen]) {
+      if !self.check(TokenType::RightParen) {
+        loop {
+          fields.push(self.pattern()?);
+          if !self.is_match(&[TokenType::Comma]) {
+            break;
+          }
+        }
+      }
+      self.consume(TokenType::RightParen, "Expect ')' after variant patterns.")?;
+    }
+
+    Ok(Pattern::Variant {
+      enum_name,
+      variant,
+      fields,
+    })
+  }
+
+  /// [p1, p2, ...] 或 [p1, ...rest]，rest只能是名字或者_
+  fn list_pattern(&mut self) -> Result<Pattern, SaturdayResult> {
+    let bracket = self.previous().dup();
+    let mut elements = Vec::new();
+    let mut rest = None;
+    while !self.check(TokenType::RightBracket) && !self.is_at_end() {
+      if self.is_match(&[TokenType::Ellipsis]) {
+        let name = self.consume(TokenType::Identifier, "Expect name after '...'.")?;
+        rest = Some(Box::new(if &*name.lexeme() == "_" {
+          Pattern::Wildcard { token: name }
+        } else {
+          Pattern::Binding { name }
+        }));
+        break;
+      }
+
+      elements.push(self.pattern()?);
+      if !self.is_match(&[TokenType::Comma]) {
+        break;
+      }
+    }
+
+    self.consume(TokenType::RightBracket, "Expect ']' after list patterns.")?;
+    Ok(Pattern::List {
+      bracket,
+      elements,
+      rest,
+    })
+  }
+
+  /// Record { field, field: pattern, ... }，只写名字的字段把值绑定到同名变量上
+  fn record_pattern(&mut self) -> Result<Pattern, SaturdayResult> {
+    let name = Rc::new(Expr::Variable(Rc::new(VariableExpr {
+      name: self.advance().dup(),
+    })));
+    self.advance();
+
+    let mut fields: Vec<(Token, Pattern)> = Vec::new();
+    while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+      let field = self.consume(TokenType::Identifier, "Expect field name.")?;
+      if fields.iter().any(|(f, _)| f.symbol == field.symbol) {
+        self.error(&field, "Duplicate field in record pattern.");
+      }
+      let pattern = if self.is_match(&[TokenType::Colon]) {
+        self.pattern()?
+      } else {
+        Pattern::Binding { name: field.dup() }
+      };
+      fields.push((field, pattern));
+
+      if !self.is_match(&[TokenType::Comma]) {
+        break;
+      }
+    }
+
+    self.consume(TokenType::RightBrace, "Expect '}' after record patterns.")?;
+    Ok(Pattern::Record { name, fields })
+  }
+
+  /// assert condition, message;
+  fn assert_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let from = self.current;
+    let condition = Rc::new(self.expression()?);
+    let source = self.source_text(from, self.current);
+
+    let message = if self.is_match(&[TokenType::Comma]) {
+      Some(Rc::new(self.expression()?))
+    } else {
+      None
+    };
+
+    self.consume(TokenType::SemiColon, "Expect ';' after assert.")?;
+    Ok(Stmt::Assert(Rc::new(AssertStmt {
+      keyword,
+      condition,
+      message,
+      source,
+    })))
+  }
+
+  /// 用token还原一段源码，token之间原来有空白的地方用一个空格代替
+  fn source_text(&self, from: usize, to: usize) -> String {
+    let mut text = String::new();
+    let mut last_end: Option<usize> = None;
+    for token in &self.tokens[from..to] {
+      if last_end.is_some_and(|end| token.start > end) {
+        text.push(' ');
+      }
+      text.push_str(&token.lexeme());
+      last_end = Some(token.end);
+    }
+    text
+  }
+
   fn print_statement(&mut self) -> Result<Stmt, SaturdayResult> {
     let value = Rc::new(self.expression()?);
     self.consume(TokenType::SemiColon, "Expect ';' after value.")?;
@@ -231,6 +589,18 @@ impl<'a> Parser<'a> {
     Ok(Stmt::Return(Rc::new(ReturnStmt { keyword, value })))
   }
 
+  fn yield_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let value = if self.check(TokenType::SemiColon) {
+      None
+    } else {
+      Some(Rc::new(self.expression()?))
+    };
+
+    self.consume(TokenType::SemiColon, "Expect ';' after yield value.")?;
+    Ok(Stmt::Yield(Rc::new(YieldStmt { keyword, value })))
+  }
+
   fn expression_statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
     let expr = Rc::new(self.expression()?);
     self.consume(TokenType::SemiColon, "Expect ';' after value.")?;
@@ -240,6 +610,8 @@ impl<'a> Parser<'a> {
   }
 
   fn function(&mut self, kind: &str) -> Result<Rc<Stmt>, SaturdayResult> {
+    // fun* 声明生成器函数
+    let generator = self.is_match(&[TokenType::Star]);
     let name = self.consume(TokenType::Identifier, &format!("Expect {kind} name"))?;
     self.consume(
       TokenType::LeftParen,
@@ -247,8 +619,10 @@ impl<'a> Parser<'a> {
     )?;
 
     let mut params: Vec<Token> = Vec::new();
+    let mut param_types = Vec::new();
     if !self.check(TokenType::RightParen) {
       params.push(self.consume(TokenType::Identifier, "Expect parameter name")?);
+      param_types.push(self.optional_annotation()?);
       while self.is_match(&[TokenType::Comma]) {
         if params.len() >= 255 && !self.had_error {
           let peek = self.peek().dup();
@@ -256,11 +630,25 @@ impl<'a> Parser<'a> {
         }
 
         params.push(self.consume(TokenType::Identifier, "Expect parameter name")?);
+        param_types.push(self.optional_annotation()?);
       }
     }
 
     self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
 
+    let return_type = if self.is_match(&[TokenType::Arrow]) {
+      let annotation = self.type_annotation()?;
+      if generator {
+        self.error(
+          &annotation.token,
+          "Generator functions can't declare a return type.",
+        );
+      }
+      Some(annotation)
+    } else {
+      None
+    };
+
     self.consume(
       TokenType::LeftBrace,
       &format!("Expect '{{' before {kind} body"),
@@ -270,6 +658,9 @@ impl<'a> Parser<'a> {
       name,
       params: Rc::new(params),
       body,
+      generator,
+      param_types: Rc::new(param_types),
+      return_type,
     }))))
   }
 
@@ -284,7 +675,7 @@ impl<'a> Parser<'a> {
   }
 
   fn assignment(&mut self) -> Result<Expr, SaturdayResult> {
-    let expr = self.or()?;
+    let expr = self.pipe()?;
 
     if self.is_match(&[TokenType::Assign]) {
       let equals = self.previous().dup();
@@ -303,6 +694,58 @@ impl<'a> Parser<'a> {
     Ok(expr)
   }
 
+  /// # 管道运算
+  /// ```
+  /// x |> f(a) 转换为 f(x, a)，x |> f 转换为 f(x)
+  /// 生成的CallExpr以 |> 作为paren，参数个数错误时指向管道符
+  /// ```
+  fn pipe(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut expr = self.nil_coalesce()?;
+
+    while self.is_match(&[TokenType::PipeGreater]) {
+      let pipe = self.previous().dup();
+      let right = self.nil_coalesce()?;
+      let left = Rc::new(expr);
+
+      expr = if let Expr::Call(call) = right {
+        let mut arguments = vec![left];
+        arguments.extend(call.arguments.iter().cloned());
+        Expr::Call(Rc::new(CallExpr {
+          callee: Rc::clone(&call.callee),
+          paren: pipe,
+          arguments,
+          optional: call.optional,
+        }))
+      } else {
+        Expr::Call(Rc::new(CallExpr {
+          callee: Rc::new(right),
+          paren: pipe,
+          arguments: vec![left],
+          optional: false,
+        }))
+      };
+    }
+
+    Ok(expr)
+  }
+
+  /// a ?? b：仅当a为nil时才求值b
+  fn nil_coalesce(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut expr = self.or()?;
+
+    while self.is_match(&[TokenType::QuestionQuestion]) {
+      let operator = self.previous().dup();
+      let right = Rc::new(self.or()?);
+      expr = Expr::Logical(Rc::new(LogicalExpr {
+        left: Rc::new(expr),
+        operator,
+        right,
+      }));
+    }
+
+    Ok(expr)
+  }
+
   fn or(&mut self) -> Result<Expr, SaturdayResult> {
     let mut expr = self.and()?;
 
@@ -352,13 +795,73 @@ impl<'a> Parser<'a> {
   }
 
   fn comparison(&mut self) -> Result<Expr, SaturdayResult> {
-    let mut expr = self.term()?;
+    let mut expr = self.bit_or()?;
     while self.is_match(&[
       TokenType::Greater,
       TokenType::GreaterEqual,
       TokenType::Less,
       TokenType::LessEqual,
     ]) {
+      let operator = self.previous().dup();
+      let right = self.bit_or()?;
+      expr = Expr::Binary(Rc::new(BinaryExpr {
+        left: Rc::new(expr),
+        operator,
+        right: Rc::new(right),
+      }));
+    }
+
+    Ok(expr)
+  }
+
+  fn bit_or(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut expr = self.bit_xor()?;
+    while self.is_match(&[TokenType::Pipe]) {
+      let operator = self.previous().dup();
+      let right = self.bit_xor()?;
+      expr = Expr::Binary(Rc::new(BinaryExpr {
+        left: Rc::new(expr),
+        operator,
+        right: Rc::new(right),
+      }));
+    }
+
+    Ok(expr)
+  }
+
+  fn bit_xor(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut expr = self.bit_and()?;
+    while self.is_match(&[TokenType::Caret]) {
+      let operator = self.previous().dup();
+      let right = self.bit_and()?;
+      expr = Expr::Binary(Rc::new(BinaryExpr {
+        left: Rc::new(expr),
+        operator,
+        right: Rc::new(right),
+      }));
+    }
+
+    Ok(expr)
+  }
+
+  fn bit_and(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut expr = self.shift()?;
+    while self.is_match(&[TokenType::Ampersand]) {
+      let operator = self.previous().dup();
+      let right = self.shift()?;
+      expr = Expr::Binary(Rc::new(BinaryExpr {
+        left: Rc::new(expr),
+        operator,
+        right: Rc::new(right),
+      }));
+    }
+
+    Ok(expr)
+  }
+
+  fn shift(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut expr = self.term()?;
+    while self.is_match(&[TokenType::LessLess, TokenType::GreaterGreater]) {
       let operator = self.previous().dup();
       let right = self.term()?;
       expr = Expr::Binary(Rc::new(BinaryExpr {
@@ -402,7 +905,7 @@ impl<'a> Parser<'a> {
   }
 
   fn unary(&mut self) -> Result<Expr, SaturdayResult> {
-    if self.is_match(&[TokenType::Bang, TokenType::Minus]) {
+    if self.is_match(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
       let operator = self.previous().dup();
       let right = self.unary()?;
       return Ok(Expr::Unary(Rc::new(UnaryExpr {
@@ -418,7 +921,29 @@ impl<'a> Parser<'a> {
     let mut expr = self.primary()?;
     loop {
       if self.is_match(&[TokenType::LeftParen]) {
-        expr = self.finish_call(&Rc::new(expr))?;
+        expr = self.finish_call(&Rc::new(expr), false)?;
+      } else if self.is_match(&[TokenType::Dot]) {
+        let name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+        expr = Expr::Get(Rc::new(GetExpr {
+          object: Rc::new(expr),
+          name,
+          optional: false,
+        }));
+      } else if self.is_match(&[TokenType::QuestionDot]) {
+        // f?.(x) / a?.b：左边为nil时整个表达式的结果为nil
+        if self.is_match(&[TokenType::LeftParen]) {
+          expr = self.finish_call(&Rc::new(expr), true)?;
+        } else {
+          let name = self.consume(
+            TokenType::Identifier,
+            "Expect '(' or property name after '?.'.",
+          )?;
+          expr = Expr::Get(Rc::new(GetExpr {
+            object: Rc::new(expr),
+            name,
+            optional: true,
+          }));
+        }
       } else {
         break;
       }
@@ -428,7 +953,7 @@ impl<'a> Parser<'a> {
   }
 
   /// 解析方法参数
-  fn finish_call(&mut self, callee: &Rc<Expr>) -> Result<Expr, SaturdayResult> {
+  fn finish_call(&mut self, callee: &Rc<Expr>, optional: bool) -> Result<Expr, SaturdayResult> {
     let mut arguments = Vec::new();
     if !self.check(TokenType::RightParen) {
       arguments.push(Rc::new(self.expression()?));
@@ -448,6 +973,7 @@ impl<'a> Parser<'a> {
       callee: Rc::clone(callee),
       paren,
       arguments,
+      optional,
     })))
   }
 
@@ -480,6 +1006,19 @@ impl<'a> Parser<'a> {
       })));
     }
 
+    if self.is_match(&[TokenType::LeftBracket]) {
+      let bracket = self.previous().dup();
+      let mut elements = Vec::new();
+      while !self.check(TokenType::RightBracket) && !self.is_at_end() {
+        elements.push(Rc::new(self.expression()?));
+        if !self.is_match(&[TokenType::Comma]) {
+          break;
+        }
+      }
+      self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+      return Ok(Expr::List(Rc::new(ListExpr { bracket, elements })));
+    }
+
     if self.is_match(&[TokenType::LeftParen]) {
       let expr = self.expression()?;
       self.consume(TokenType::RightParen, "Expect ')' after expression")?;
@@ -524,6 +1063,11 @@ impl<'a> Parser<'a> {
           | TokenType::While
           | TokenType::Print
           | TokenType::Return
+          | TokenType::Yield
+          | TokenType::Match
+          | TokenType::Enum
+          | TokenType::Record
+          | TokenType::Assert
       ) {
         return;
       }
@@ -551,6 +1095,13 @@ impl<'a> Parser<'a> {
     }
   }
 
+  fn check_next(&self, t_type: TokenType) -> bool {
+    match self.tokens.get(self.current + 1) {
+      Some(token) => token.is(t_type),
+      None => false,
+    }
+  }
+
   fn advance(&mut self) -> &Token {
     if !self.is_at_end() {
       self.current += 1;
@@ -571,3 +1122,54 @@ impl<'a> Parser<'a> {
     self.tokens.get(self.current - 1).unwrap()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::scanner::Scanner;
+
+  fn parse_expression(source: &str) -> Expr {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let mut parser = Parser::new(tokens);
+    parser.expression().ok().unwrap()
+  }
+
+  fn callee_name(expr: &Expr) -> String {
+    match expr {
+      Expr::Variable(v) => v.name.as_string(),
+      _ => panic!("expect variable"),
+    }
+  }
+
+  #[test]
+  fn pipe_inserts_left_side_as_first_argument() {
+    let expr = parse_expression("x |> a |> b(2)");
+    let Expr::Call(outer) = expr else {
+      panic!("expect call");
+    };
+    assert_eq!(callee_name(&outer.callee), "b");
+    assert!(outer.paren.is(TokenType::PipeGreater));
+    assert_eq!(outer.arguments.len(), 2);
+    assert!(matches!(&*outer.arguments[1], Expr::Literal(_)));
+
+    let Expr::Call(inner) = &*outer.arguments[0] else {
+      panic!("expect call");
+    };
+    assert_eq!(callee_name(&inner.callee), "a");
+    assert_eq!(inner.arguments.len(), 1);
+    assert_eq!(callee_name(&inner.arguments[0]), "x");
+  }
+
+  #[test]
+  fn assert_keeps_condition_source() {
+    let mut scanner = Scanner::new("assert fib( 10 )  ==\n 56, \"msg\";".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+    let Stmt::Assert(stmt) = &*statements[0] else {
+      panic!("expect assert");
+    };
+    assert_eq!(stmt.source, "fib( 10 ) == 56");
+    assert!(stmt.message.is_some());
+  }
+}
@@ -0,0 +1,215 @@
+use crate::callable::SaturdayCallable;
+use crate::error::SaturdayResult;
+use crate::expr::Expr;
+use crate::interner::Symbol;
+use crate::object::Object;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::rc::Rc;
+
+/// match语句中的模式
+#[derive(Clone)]
+pub enum Pattern {
+  /// 字面量，与被匹配的值相等时匹配
+  Literal { token: Token, value: Object },
+  /// _ 匹配任何值且不绑定
+  Wildcard { token: Token },
+  /// 名字匹配任何值并把值绑定到这个名字上
+  Binding { name: Token },
+  /// 枚举变体，如 Shape.Circle(r)，载荷逐个用子模式匹配。
+  /// enum_name是一个变量表达式，和普通的变量一样由resolver解析，运行时按同一性比较枚举
+  Variant {
+    enum_name: Rc<Expr>,
+    variant: Token,
+    fields: Vec<Pattern>,
+  },
+  /// 列表，如 [first, second, ...rest]，元素逐个用子模式匹配。
+  /// 没有rest时长度必须相同；rest是绑定或者_，匹配剩下的元素组成的列表
+  List {
+    bracket: Token,
+    elements: Vec<Pattern>,
+    rest: Option<Box<Pattern>>,
+  },
+  /// record，如 Point { x, y: 0 }，只匹配列出的字段。
+  /// 和枚举一样，name是一个变量表达式，运行时按同一性比较record类型
+  Record {
+    name: Rc<Expr>,
+    fields: Vec<(Token, Pattern)>,
+  },
+}
+
+pub struct MatchArm {
+  pub pattern: Pattern,
+  pub guard: Option<Rc<Expr>>,
+  pub body: Rc<Stmt>,
+}
+
+impl Pattern {
+  /// 模式所在的位置，用于报告错误和警告
+  pub fn token(&self) -> &Token {
+    match self {
+      Pattern::Literal { token, .. } | Pattern::Wildcard { token } => token,
+      Pattern::Binding { name } => name,
+      Pattern::List { bracket, .. } => bracket,
+      Pattern::Variant {
+        enum_name: name, ..
+      }
+      | Pattern::Record { name, .. } => match &**name {
+        Expr::Variable(variable) => &variable.name,
+        _ => unreachable!(),
+      },
+    }
+  }
+
+  /// 不论值是什么都能匹配
+  pub fn is_irrefutable(&self) -> bool {
+    matches!(self, Pattern::Wildcard { .. } | Pattern::Binding { .. })
+  }
+
+  /// 模式中绑定的名字
+  pub fn bindings(&self) -> Vec<&Token> {
+    match self {
+      Pattern::Binding { name } => vec![name],
+      Pattern::Variant { fields, .. } => fields.iter().flat_map(|f| f.bindings()).collect(),
+      Pattern::List { elements, rest, .. } => elements
+        .iter()
+        .chain(rest.as_deref())
+        .flat_map(|e| e.bindings())
+        .collect(),
+      Pattern::Record { fields, .. } => fields.iter().flat_map(|(_, f)| f.bindings()).collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  /// 模式中引用的枚举和record类型，按先序排列，匹配前由调用方求值
+  pub fn types(&self) -> Vec<&Rc<Expr>> {
+    match self {
+      Pattern::Variant {
+        enum_name, fields, ..
+      } => {
+        let mut types = vec![enum_name];
+        types.extend(fields.iter().flat_map(|f| f.types()));
+        types
+      }
+      Pattern::List { elements, .. } => elements.iter().flat_map(|e| e.types()).collect(),
+      Pattern::Record { name, fields } => {
+        let mut types = vec![name];
+        types.extend(fields.iter().flat_map(|(_, f)| f.types()));
+        types
+      }
+      _ => Vec::new(),
+    }
+  }
+
+  /// # 尝试匹配
+  /// ```
+  /// types是types()中各个名字求出的值。成功时把绑定的名字和值追加到bindings中；
+  /// 模式引用的不是枚举或record、枚举没有这个变体、子模式个数和变体的载荷个数不同、
+  /// 或者record没有这个字段时报错
+  /// ```
+  pub fn matches(
+    &self,
+    value: &Object,
+    types: &[Object],
+    bindings: &mut Vec<(Symbol, Object)>,
+  ) -> Result<bool, SaturdayResult> {
+    match self {
+      Pattern::Literal { value: literal, .. } => Ok(literal == value),
+      Pattern::Wildcard { .. } => Ok(true),
+      Pattern::Binding { name } => {
+        bindings.push((name.symbol, value.clone()));
+        Ok(true)
+      }
+      Pattern::Variant {
+        variant, fields, ..
+      } => {
+        let Some(Object::Enum(e)) = types.first() else {
+          return Err(SaturdayResult::runtime_error(
+            self.token(),
+            &format!("'{}' is not an enum.", self.token().as_string()),
+          ));
+        };
+        let arity = e.arity(variant)?;
+        if arity != fields.len() {
+          return Err(SaturdayResult::runtime_error(
+            variant,
+            &format!("Expected {} fields but got {}.", arity, fields.len()),
+          ));
+        }
+
+        let Object::Variant(v) = value else {
+          return Ok(false);
+        };
+        if !e.owns(v) || v.tag != variant.as_string() {
+          return Ok(false);
+        }
+
+        Self::matches_all(fields.iter().zip(v.payload.iter()), &types[1..], bindings)
+      }
+      Pattern::List { elements, rest, .. } => {
+        let Object::List(list) = value else {
+          return Ok(false);
+        };
+        let length_matches = match rest {
+          Some(_) => list.len() >= elements.len(),
+          None => list.len() == elements.len(),
+        };
+        if !length_matches || !Self::matches_all(elements.iter().zip(list.iter()), types, bindings)?
+        {
+          return Ok(false);
+        }
+        if let Some(rest) = rest {
+          let remaining = Object::List(Rc::new(list[elements.len()..].to_vec()));
+          rest.matches(&remaining, &[], bindings)?;
+        }
+        Ok(true)
+      }
+      Pattern::Record { fields, .. } => {
+        let record_type = match types.first() {
+          Some(Object::Func(constructor)) => constructor.as_record(),
+          _ => None,
+        };
+        let Some(record_type) = record_type else {
+          return Err(SaturdayResult::runtime_error(
+            self.token(),
+            &format!("'{}' is not a record.", self.token().as_string()),
+          ));
+        };
+        let indices = fields
+          .iter()
+          .map(|(name, _)| record_type.field(name))
+          .collect::<Result<Vec<_>, _>>()?;
+
+        let Object::Record(r) = value else {
+          return Ok(false);
+        };
+        if !r.is_a(record_type) {
+          return Ok(false);
+        }
+
+        let values = indices.iter().map(|&i| &r.values[i]);
+        Self::matches_all(
+          fields.iter().map(|(_, f)| f).zip(values),
+          &types[1..],
+          bindings,
+        )
+      }
+    }
+  }
+
+  /// 逐个匹配子模式，types按各个子模式引用的个数依次分给它们
+  fn matches_all<'a>(
+    patterns: impl Iterator<Item = (&'a Pattern, &'a Object)>,
+    mut types: &[Object],
+    bindings: &mut Vec<(Symbol, Object)>,
+  ) -> Result<bool, SaturdayResult> {
+    for (pattern, value) in patterns {
+      let (own, others) = types.split_at(pattern.types().len());
+      if !pattern.matches(value, own, bindings)? {
+        return Ok(false);
+      }
+      types = others;
+    }
+    Ok(true)
+  }
+}
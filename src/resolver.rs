@@ -1,12 +1,15 @@
 use crate::error::SaturdayResult;
 use crate::expr::{
-  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GroupingExpr, LiteralExpr, LogicalExpr,
-  UnaryExpr, VariableExpr,
+  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, ListExpr,
+  LiteralExpr, LogicalExpr, UnaryExpr, VariableExpr,
 };
+use crate::interner::Symbol;
 use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::pattern::Pattern;
 use crate::stmt::{
-  BlockStmt, BreakStmt, DefStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt,
-  StmtVisitor, WhileStmt,
+  AssertStmt, BlockStmt, BreakStmt, DefStmt, EnumStmt, ExpressionStmt, ForInStmt, FunctionStmt,
+  IfStmt, MatchStmt, PrintStmt, RecordStmt, ReturnStmt, Stmt, StmtVisitor, WhileStmt, YieldStmt,
 };
 use crate::token::Token;
 use std::cell::RefCell;
@@ -16,16 +19,23 @@ use std::rc::Rc;
 
 pub struct Resolver<'a> {
   interpreter: &'a Interpreter,
-  scopes: RefCell<Vec<RefCell<HashMap<String, bool>>>>,
+  scopes: RefCell<Vec<RefCell<HashMap<Symbol, Local>>>>,
   had_error: RefCell<bool>,
   current_function: RefCell<FunctionType>,
   in_while: RefCell<bool>,
 }
 
+/// 作用域中的局部变量：运行时环境中的槽，以及初始化是否已经完成
+struct Local {
+  slot: usize,
+  defined: bool,
+}
+
 #[derive(PartialEq)]
 enum FunctionType {
   None,
   Function,
+  Generator,
 }
 
 impl<'a> Resolver<'a> {
@@ -69,24 +79,35 @@ impl<'a> Resolver<'a> {
 
   fn declare(&self, name: &Token) {
     if let Some(scope) = self.scopes.borrow().last() {
-      if scope.borrow().contains_key(&name.as_string()) {
+      if scope.borrow().contains_key(&name.symbol) {
         self.error(name, "Already a variable with this name in this scope.");
+        return;
       }
 
-      scope.borrow_mut().insert(name.as_string(), false);
+      // 运行时按定义的顺序把变量放进环境，所以槽就是作用域中已有变量的个数
+      let slot = scope.borrow().len();
+      scope.borrow_mut().insert(
+        name.symbol,
+        Local {
+          slot,
+          defined: false,
+        },
+      );
     }
   }
 
   fn define(&self, name: &Token) {
     if let Some(scope) = self.scopes.borrow().last() {
-      scope.borrow_mut().insert(name.as_string(), true);
+      if let Some(local) = scope.borrow_mut().get_mut(&name.symbol) {
+        local.defined = true;
+      }
     }
   }
 
   fn resolve_local(&self, expr: Rc<Expr>, name: &Token) {
     for (scope, map) in self.scopes.borrow().iter().rev().enumerate() {
-      if map.borrow().contains_key(&name.as_string()) {
-        self.interpreter.resolve(expr, scope);
+      if let Some(local) = map.borrow().get(&name.symbol) {
+        self.interpreter.resolve(expr, scope, local.slot);
         return;
       }
     }
@@ -119,6 +140,15 @@ impl<'a> Resolver<'a> {
 }
 
 impl<'a> StmtVisitor<()> for Resolver<'a> {
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.condition.clone())?;
+    if let Some(message) = stmt.message.clone() {
+      self.resolve_expr(message)?;
+    }
+
+    Ok(())
+  }
+
   fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<(), SaturdayResult> {
     self.begin_scope();
     self.resolve(&stmt.statements)?;
@@ -134,6 +164,18 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_enum_stmt(&self, _: Rc<Stmt>, stmt: &EnumStmt) -> Result<(), SaturdayResult> {
+    self.declare(&stmt.name);
+    self.define(&stmt.name);
+    Ok(())
+  }
+
+  fn visit_record_stmt(&self, _: Rc<Stmt>, stmt: &RecordStmt) -> Result<(), SaturdayResult> {
+    self.declare(&stmt.name);
+    self.define(&stmt.name);
+    Ok(())
+  }
+
   fn visit_expression_stmt(
     &self,
     _: Rc<Stmt>,
@@ -143,11 +185,30 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_forin_stmt(&self, _: Rc<Stmt>, stmt: &ForInStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.iterable.clone())?;
+
+    self.begin_scope();
+    self.declare(&stmt.name);
+    self.define(&stmt.name);
+    let enclosing_while = self.in_while.replace(true);
+    self.resolve_stmt(stmt.body.clone())?;
+    self.in_while.replace(enclosing_while);
+    self.end_scope();
+
+    Ok(())
+  }
+
   fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
     self.declare(&stmt.name);
     self.define(&stmt.name);
 
-    self.resolve_function(stmt, FunctionType::Function)?;
+    let f_type = if stmt.generator {
+      FunctionType::Generator
+    } else {
+      FunctionType::Function
+    };
+    self.resolve_function(stmt, f_type)?;
     Ok(())
   }
 
@@ -161,6 +222,68 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_match_stmt(&self, _: Rc<Stmt>, stmt: &MatchStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.subject.clone())?;
+    if stmt.arms.is_empty() {
+      SaturdayResult::warning(&stmt.keyword, "match has no arms.");
+    }
+
+    let literal_subject = match &*stmt.subject {
+      Expr::Literal(literal) => literal.value.clone(),
+      _ => None,
+    };
+    // 之前已经出现过的、没有guard的字面量模式
+    let mut covered: Vec<Object> = Vec::new();
+    let mut exhausted = false;
+
+    for arm in stmt.arms.iter() {
+      let token = arm.pattern.token();
+      if exhausted {
+        SaturdayResult::warning(token, "Unreachable match arm.");
+      } else if let Pattern::Literal { value, .. } = &arm.pattern {
+        if covered.contains(value) {
+          SaturdayResult::warning(token, "Unreachable match arm.");
+        } else if literal_subject
+          .as_ref()
+          .is_some_and(|subject| subject != value)
+        {
+          SaturdayResult::warning(token, "Pattern can never match the literal scrutinee.");
+        }
+      } else if literal_subject.is_some() && !arm.pattern.is_irrefutable() {
+        // 字面量不会是列表、record或者枚举变体
+        SaturdayResult::warning(token, "Pattern can never match the literal scrutinee.");
+      }
+
+      if arm.guard.is_none() {
+        match &arm.pattern {
+          Pattern::Literal { value, .. } => {
+            exhausted |= literal_subject.as_ref() == Some(value);
+            covered.push(value.clone());
+          }
+          pattern => exhausted |= pattern.is_irrefutable(),
+        }
+      }
+
+      // 模式中的枚举和record类型在分支的作用域之外，和subject一样解析
+      for type_name in arm.pattern.types() {
+        self.resolve_expr(type_name.clone())?;
+      }
+
+      self.begin_scope();
+      for name in arm.pattern.bindings() {
+        self.declare(name);
+        self.define(name);
+      }
+      if let Some(guard) = &arm.guard {
+        self.resolve_expr(guard.clone())?;
+      }
+      self.resolve_stmt(arm.body.clone())?;
+      self.end_scope();
+    }
+
+    Ok(())
+  }
+
   fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), SaturdayResult> {
     self.resolve_expr(stmt.expression.clone())?;
     Ok(())
@@ -171,7 +294,17 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
       self.error(&stmt.keyword, "Can't return from top-level code.");
     }
 
+    if *self.current_function.borrow() == FunctionType::Generator && stmt.value.is_some() {
+      self.error(&stmt.keyword, "Can't return a value from a generator.");
+    }
+
     if let Some(value) = stmt.value.clone() {
+      if *self.current_function.borrow() == FunctionType::Function
+        && matches!(&*value, Expr::Call(_))
+      {
+        self.interpreter.mark_tail_call(value.clone());
+      }
+
       self.resolve_expr(value)?;
     }
 
@@ -194,6 +327,20 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     self.resolve_stmt(stmt.body.clone())?;
     self.in_while.replace(false);
 
+    Ok(())
+  }
+  fn visit_yield_stmt(&self, _: Rc<Stmt>, stmt: &YieldStmt) -> Result<(), SaturdayResult> {
+    if *self.current_function.borrow() != FunctionType::Generator {
+      self.error(
+        &stmt.keyword,
+        "Can't yield outside of a generator function.",
+      );
+    }
+
+    if let Some(value) = stmt.value.clone() {
+      self.resolve_expr(value)?;
+    }
+
     Ok(())
   }
 }
@@ -220,11 +367,23 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<(), SaturdayResult> {
+    self.resolve_expr(expr.object.clone())?;
+    Ok(())
+  }
+
   fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<(), SaturdayResult> {
     self.resolve_expr(expr.expression.clone())?;
     Ok(())
   }
 
+  fn visit_list_expr(&self, _: Rc<Expr>, expr: &ListExpr) -> Result<(), SaturdayResult> {
+    for element in &expr.elements {
+      self.resolve_expr(element.clone())?;
+    }
+    Ok(())
+  }
+
   fn visit_literal_expr(&self, _: Rc<Expr>, _expr: &LiteralExpr) -> Result<(), SaturdayResult> {
     Ok(())
   }
@@ -252,8 +411,8 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
         .last()
         .unwrap()
         .borrow()
-        .get(&expr.name.as_string())
-        .copied()
+        .get(&expr.name.symbol)
+        .map(|local| local.defined)
         == Some(false)
     {
       self.error(
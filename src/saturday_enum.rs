@@ -0,0 +1,169 @@
+use crate::callable::{Callable, SaturdayCallable};
+use crate::error::SaturdayResult;
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::token::Token;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// enum声明中的一个变体，fields为载荷的名字
+pub struct VariantDecl {
+  pub name: Token,
+  pub fields: Vec<Token>,
+}
+
+/// 枚举类型的标识，变体值和构造函数共享同一个Rc，用它判断是否属于同一个枚举
+pub struct EnumType {
+  pub name: String,
+}
+
+pub struct SaturdayEnum {
+  enum_type: Rc<EnumType>,
+  /// 无载荷的变体直接是值，有载荷的变体是构造函数
+  variants: HashMap<String, Object>,
+}
+
+impl SaturdayEnum {
+  pub fn new(name: &Token, variants: &[VariantDecl]) -> Self {
+    let enum_type = Rc::new(EnumType {
+      name: name.as_string(),
+    });
+
+    let variants = variants
+      .iter()
+      .map(|variant| {
+        let tag = variant.name.as_string();
+        let value = if variant.fields.is_empty() {
+          Object::Variant(Rc::new(EnumVariant {
+            enum_type: Rc::clone(&enum_type),
+            tag: tag.clone(),
+            payload: Vec::new(),
+          }))
+        } else {
+          Object::Func(Callable {
+            func: Rc::new(VariantConstructor {
+              enum_type: Rc::clone(&enum_type),
+              tag: tag.clone(),
+              arity: variant.fields.len(),
+            }),
+          })
+        };
+        (tag, value)
+      })
+      .collect();
+
+    Self {
+      enum_type,
+      variants,
+    }
+  }
+
+  pub fn get(&self, name: &Token) -> Result<Object, SaturdayResult> {
+    match self.variants.get(&name.as_string()) {
+      Some(value) => Ok(value.clone()),
+      None => Err(SaturdayResult::runtime_error(
+        name,
+        &format!(
+          "Undefined variant '{}' of enum {}.",
+          name.as_string(),
+          self.enum_type.name
+        ),
+      )),
+    }
+  }
+
+  /// 变体载荷的个数，没有这个变体时报错
+  pub fn arity(&self, name: &Token) -> Result<usize, SaturdayResult> {
+    Ok(match self.get(name)? {
+      Object::Func(constructor) => constructor.func.arity(),
+      _ => 0,
+    })
+  }
+
+  /// 变体值是否属于这个枚举
+  pub fn owns(&self, variant: &EnumVariant) -> bool {
+    Rc::ptr_eq(&self.enum_type, &variant.enum_type)
+  }
+}
+
+impl Debug for SaturdayEnum {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{self}")
+  }
+}
+
+impl Display for SaturdayEnum {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "<enum {}>", self.enum_type.name)
+  }
+}
+
+impl PartialEq for SaturdayEnum {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.enum_type, &other.enum_type)
+  }
+}
+
+/// 枚举的变体值，比如 Shape.Circle(2)
+pub struct EnumVariant {
+  enum_type: Rc<EnumType>,
+  pub tag: String,
+  pub payload: Vec<Object>,
+}
+
+impl Debug for EnumVariant {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{self}")
+  }
+}
+
+impl Display for EnumVariant {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.enum_type.name, self.tag)?;
+    if !self.payload.is_empty() {
+      let payload: Vec<String> = self.payload.iter().map(|p| p.to_string()).collect();
+      write!(f, "({})", payload.join(", "))?;
+    }
+    Ok(())
+  }
+}
+
+/// 同一个枚举的同一个变体，且载荷逐个相等
+impl PartialEq for EnumVariant {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.enum_type, &other.enum_type)
+      && self.tag == other.tag
+      && self.payload == other.payload
+  }
+}
+
+pub struct VariantConstructor {
+  enum_type: Rc<EnumType>,
+  tag: String,
+  arity: usize,
+}
+
+impl SaturdayCallable for VariantConstructor {
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    _paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(Object::Variant(Rc::new(EnumVariant {
+      enum_type: Rc::clone(&self.enum_type),
+      tag: self.tag.clone(),
+      payload: arguments,
+    })))
+  }
+
+  fn arity(&self) -> usize {
+    self.arity
+  }
+
+  fn to_string(&self) -> String {
+    format!("{}.{}", self.enum_type.name, self.tag)
+  }
+}
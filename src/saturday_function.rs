@@ -1,6 +1,7 @@
-use crate::callable::SaturdayCallable;
+use crate::callable::{Callable, SaturdayCallable};
 use crate::environment::Environment;
 use crate::error::SaturdayResult;
+use crate::generator::Generator;
 use crate::interpreter::Interpreter;
 use crate::object::Object;
 use crate::stmt::{FunctionStmt, Stmt};
@@ -13,6 +14,7 @@ pub struct SaturdayFunction {
   params: Rc<Vec<Token>>,
   body: Rc<Vec<Rc<Stmt>>>,
   closure: Rc<RefCell<Environment>>,
+  generator: bool,
 }
 
 impl SaturdayFunction {
@@ -22,25 +24,78 @@ impl SaturdayFunction {
       params: Rc::clone(&declaration.params),
       body: Rc::clone(&declaration.body),
       closure: Rc::clone(closure),
+      generator: declaration.generator,
     }
   }
 }
 
+impl SaturdayFunction {
+  /// 以可恢复的方式执行函数体，供生成器和协程使用
+  pub fn resumable(&self, arguments: Vec<Object>) -> Generator {
+    let e = self.bind_arguments(arguments);
+    Generator::new(&self.name, &self.body, Rc::new(RefCell::new(e)))
+  }
+
+  /// 定义函数时的环境
+  pub fn closure(&self) -> &Rc<RefCell<Environment>> {
+    &self.closure
+  }
+
+  fn bind_arguments(&self, arguments: Vec<Object>) -> Environment {
+    let mut e = Environment::new_with_enclosing(Rc::clone(&self.closure));
+    for (param, arg) in self.params.iter().zip(arguments) {
+      e.define(param.symbol, arg);
+    }
+
+    e
+  }
+}
+
 impl SaturdayCallable for SaturdayFunction {
   fn call(
     &self,
     interpreter: &Interpreter,
+    _paren: &Token,
     arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult> {
-    let mut e = Environment::new_with_enclosing(Rc::clone(&self.closure));
-    for (param, arg) in self.params.iter().zip(arguments.iter()) {
-      e.define(&param.as_string(), arg.clone());
+    if self.generator {
+      return Ok(Object::Generator(Rc::new(self.resumable(arguments))));
     }
 
-    match interpreter.execute_block(&self.body, e) {
-      Err(SaturdayResult::ReturnValue { value }) => Ok(value),
-      Err(e) => Err(e),
-      Ok(_) => Ok(Object::Nil),
+    // 尾调用不递归执行，而是在这里循环复用当前的Rust栈帧
+    let mut tail_callee: Option<(Callable, Token)> = None;
+    let mut arguments = arguments;
+    loop {
+      let function = match &tail_callee {
+        None => self,
+        Some((callee, paren)) => {
+          // 尾调用不经过call_at，在这里按一次调用计步并检查中断
+          interpreter.consume_fuel()?;
+          interpreter.poll_interrupt()?;
+          match callee.as_function() {
+            Some(function) if !function.generator => function,
+            _ => return callee.call(interpreter, paren, arguments),
+          }
+        }
+      };
+
+      let e = function.bind_arguments(arguments);
+      let result = interpreter.in_frame(&function.name, || {
+        interpreter.execute_block(&function.body, e)
+      });
+      match result {
+        Err(SaturdayResult::ReturnValue { value }) => return Ok(value),
+        Err(SaturdayResult::TailCall {
+          callee,
+          paren,
+          arguments: next_arguments,
+        }) => {
+          tail_callee = Some((callee, paren));
+          arguments = next_arguments;
+        }
+        Err(e) => return Err(e),
+        Ok(_) => return Ok(Object::Nil),
+      }
     }
   }
 
@@ -51,4 +106,8 @@ impl SaturdayCallable for SaturdayFunction {
   fn to_string(&self) -> String {
     self.name.as_string()
   }
+
+  fn as_function(&self) -> Option<&SaturdayFunction> {
+    Some(self)
+  }
 }
@@ -0,0 +1,112 @@
+use crate::callable::SaturdayCallable;
+use crate::error::SaturdayResult;
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::token::Token;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// record类型的标识，构造函数和它创建的值共享同一个Rc，用它判断是否属于同一个record
+pub struct RecordType {
+  pub name: String,
+  pub fields: Vec<String>,
+}
+
+impl RecordType {
+  /// 字段在值中的下标，没有这个字段时报错
+  pub fn field(&self, name: &Token) -> Result<usize, SaturdayResult> {
+    let field = name.as_string();
+    self.fields.iter().position(|f| *f == field).ok_or_else(|| {
+      SaturdayResult::runtime_error(
+        name,
+        &format!("Undefined field '{}' of record {}.", field, self.name),
+      )
+    })
+  }
+}
+
+/// record的构造函数，按声明的顺序接收字段的值，比如 Point(1, 2)
+pub struct RecordConstructor {
+  pub record_type: Rc<RecordType>,
+}
+
+impl RecordConstructor {
+  pub fn new(name: &Token, fields: &[Token]) -> Self {
+    Self {
+      record_type: Rc::new(RecordType {
+        name: name.as_string(),
+        fields: fields.iter().map(|field| field.as_string()).collect(),
+      }),
+    }
+  }
+}
+
+impl SaturdayCallable for RecordConstructor {
+  fn call(
+    &self,
+    _interpreter: &Interpreter,
+    _paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(Object::Record(Rc::new(Record {
+      record_type: Rc::clone(&self.record_type),
+      values: arguments,
+    })))
+  }
+
+  fn arity(&self) -> usize {
+    self.record_type.fields.len()
+  }
+
+  fn to_string(&self) -> String {
+    format!("<record {}>", self.record_type.name)
+  }
+
+  fn as_record(&self) -> Option<&Rc<RecordType>> {
+    Some(&self.record_type)
+  }
+}
+
+/// record的值，字段按声明的顺序保存
+pub struct Record {
+  record_type: Rc<RecordType>,
+  pub values: Vec<Object>,
+}
+
+impl Record {
+  pub fn get(&self, name: &Token) -> Result<Object, SaturdayResult> {
+    Ok(self.values[self.record_type.field(name)?].clone())
+  }
+
+  /// 值是否由这个record类型创建
+  pub fn is_a(&self, record_type: &Rc<RecordType>) -> bool {
+    Rc::ptr_eq(&self.record_type, record_type)
+  }
+}
+
+impl Debug for Record {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{self}")
+  }
+}
+
+impl Display for Record {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let fields: Vec<String> = self
+      .record_type
+      .fields
+      .iter()
+      .zip(&self.values)
+      .map(|(name, value)| format!("{name}: {value}"))
+      .collect();
+    write!(f, "{} {{ {} }}", self.record_type.name, fields.join(", "))
+  }
+}
+
+/// 同一个record类型，且字段逐个相等
+impl PartialEq for Record {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.record_type, &other.record_type) && self.values == other.values
+  }
+}
@@ -1,5 +1,6 @@
 use crate::object::Object;
 use crate::token::Token;
+use crate::unicode::{is_xid_continue, is_xid_start, nfc};
 use crate::SaturdayResult;
 
 use super::token_type::*;
@@ -10,6 +11,7 @@ pub struct Scanner {
   start: usize,
   current: usize,
   line: usize,
+  nfc: bool,
 }
 
 impl Scanner {
@@ -20,9 +22,15 @@ impl Scanner {
       start: 0,
       current: 0,
       line: 1,
+      nfc: false,
     }
   }
 
+  /// 开启后标识符会做NFC规范化，视觉上相同的名字视为同一个变量
+  pub fn set_nfc(&mut self, nfc: bool) {
+    self.nfc = nfc;
+  }
+
   /// # 开始解析token
   /// ```
   /// 通过scan_token逐个解析
@@ -31,12 +39,18 @@ impl Scanner {
     let mut had_error: Option<SaturdayResult> = None;
     while !self.is_at_end() {
       self.start = self.current;
+      let scanned = self.tokens.len();
       match self.scan_token() {
         Ok(()) => {}
         Err(e) => {
           had_error = Some(e);
         }
       }
+
+      for token in &mut self.tokens[scanned..] {
+        token.start = self.start;
+        token.end = self.current;
+      }
     }
 
     self.tokens.push(Token::eof(self.line));
@@ -61,11 +75,53 @@ impl Scanner {
       '{' => self.add_token(TokenType::LeftBrace),
       '}' => self.add_token(TokenType::RightBrace),
       ',' => self.add_token(TokenType::Comma),
-      '.' => self.add_token(TokenType::Dot),
-      '-' => self.add_token(TokenType::Minus),
+      '[' => self.add_token(TokenType::LeftBracket),
+      ']' => self.add_token(TokenType::RightBracket),
+      '.' => {
+        let tok = if self.peek() == Some('.') && self.peek_next() == Some('.') {
+          self.advance();
+          self.advance();
+          TokenType::Ellipsis
+        } else {
+          TokenType::Dot
+        };
+
+        self.add_token(tok);
+      }
+      '-' => {
+        let tok = if self.r#match('>') {
+          TokenType::Arrow
+        } else {
+          TokenType::Minus
+        };
+
+        self.add_token(tok);
+      }
+      ':' => self.add_token(TokenType::Colon),
       '+' => self.add_token(TokenType::Plus),
       ';' => self.add_token(TokenType::SemiColon),
       '*' => self.add_token(TokenType::Star),
+      '&' => self.add_token(TokenType::Ampersand),
+      '|' => {
+        let tok = if self.r#match('>') {
+          TokenType::PipeGreater
+        } else {
+          TokenType::Pipe
+        };
+
+        self.add_token(tok);
+      }
+      '^' => self.add_token(TokenType::Caret),
+      '~' => self.add_token(TokenType::Tilde),
+      '?' => {
+        if self.r#match('?') {
+          self.add_token(TokenType::QuestionQuestion);
+        } else if self.r#match('.') {
+          self.add_token(TokenType::QuestionDot);
+        } else {
+          SaturdayResult::error(self.line, "Unexpected character");
+        }
+      }
       '!' => {
         let tok = if self.r#match('=') {
           TokenType::BangEqual
@@ -78,6 +134,8 @@ impl Scanner {
       '=' => {
         let tok = if self.r#match('=') {
           TokenType::Equal
+        } else if self.r#match('>') {
+          TokenType::FatArrow
         } else {
           TokenType::Assign
         };
@@ -87,6 +145,8 @@ impl Scanner {
       '<' => {
         let tok = if self.r#match('=') {
           TokenType::LessEqual
+        } else if self.r#match('<') {
+          TokenType::LessLess
         } else {
           TokenType::Less
         };
@@ -96,6 +156,8 @@ impl Scanner {
       '>' => {
         let tok = if self.r#match('=') {
           TokenType::GreaterEqual
+        } else if self.r#match('>') {
+          TokenType::GreaterGreater
         } else {
           TokenType::Greater
         };
@@ -124,12 +186,24 @@ impl Scanner {
         self.line += 1;
       }
       '"' => {
-        self.string()?;
+        if self.peek() == Some('"') && self.peek_next() == Some('"') {
+          self.multi_line_string(false)?;
+        } else {
+          self.string()?;
+        }
+      }
+      'r' if self.peek() == Some('"') => {
+        self.advance();
+        if self.peek() == Some('"') && self.peek_next() == Some('"') {
+          self.multi_line_string(true)?;
+        } else {
+          self.raw_string()?;
+        }
       }
       '0'..='9' => {
         self.number();
       }
-      _ if c.is_alphabetic() || c == '_' => {
+      _ if is_xid_start(c) || c == '_' => {
         self.identifier();
       }
       _ => {
@@ -171,7 +245,7 @@ impl Scanner {
 
   fn is_alpha_numeric(ch: Option<char>) -> bool {
     if let Some(ch) = ch {
-      ch.is_ascii_alphanumeric() || ch == '_'
+      is_xid_continue(ch)
     } else {
       false
     }
@@ -182,12 +256,13 @@ impl Scanner {
       self.advance();
     }
 
-    let text: String = self.source[self.start..self.current].iter().collect();
-    if let Some(t_type) = Scanner::keywords(&text) {
-      self.add_token(t_type);
-    } else {
-      self.add_token(TokenType::Identifier);
+    let mut text: String = self.source[self.start..self.current].iter().collect();
+    if self.nfc {
+      text = nfc(&text);
     }
+
+    let t_type = Scanner::keywords(&text).unwrap_or(TokenType::Identifier);
+    self.tokens.push(Token::new(t_type, text, None, self.line));
   }
 
   fn number(&mut self) {
@@ -210,7 +285,11 @@ impl Scanner {
   }
 
   fn peek_next(&self) -> Option<char> {
-    self.source.get(self.current + 1).copied()
+    self.peek_at(1)
+  }
+
+  fn peek_at(&self, offset: usize) -> Option<char> {
+    self.source.get(self.current + offset).copied()
   }
 
   fn is_digit(ch: Option<char>) -> bool {
@@ -227,12 +306,21 @@ impl Scanner {
         '"' => {
           break;
         }
+        '\\' => {
+          // 跳过被转义的字符，避免 \" 提前结束字符串
+          self.advance();
+          if self.peek() == Some('\n') {
+            self.line += 1;
+          }
+        }
         '\n' => {
           self.line += 1;
         }
         _ => {}
       }
-      self.advance();
+      if !self.is_at_end() {
+        self.advance();
+      }
     }
 
     if self.is_at_end() {
@@ -240,14 +328,160 @@ impl Scanner {
     }
 
     self.advance();
-    // TODO: handle escape sequence
     let value: String = self.source[self.start + 1..self.current - 1]
       .iter()
       .collect();
+    let value = Scanner::unescape(&value);
+    self.add_token_object(TokenType::String, Some(Object::Str(value)));
+    Ok(())
+  }
+
+  /// 原始字符串 r"..."，不处理转义序列
+  fn raw_string(&mut self) -> Result<(), SaturdayResult> {
+    // 跳过开头的 "
+    self.advance();
+    while let Some(ch) = self.peek() {
+      if ch == '"' {
+        break;
+      }
+      if ch == '\n' {
+        self.line += 1;
+      }
+      self.advance();
+    }
+
+    if self.is_at_end() {
+      return Err(SaturdayResult::error(self.line, "Unterminated string."));
+    }
+
+    self.advance();
+    let value: String = self.source[self.start + 2..self.current - 1]
+      .iter()
+      .collect();
+    self.add_token_object(TokenType::String, Some(Object::Str(value)));
+    Ok(())
+  }
+
+  /// 多行字符串 """..."""（raw为true时对应 r"""..."""）
+  /// ```
+  /// 去除所有非空行的公共缩进，首尾的空白行会被忽略
+  /// ```
+  fn multi_line_string(&mut self, raw: bool) -> Result<(), SaturdayResult> {
+    // 跳过开头剩余的 ""（raw时为 """）
+    let prefix = if raw { 4 } else { 3 };
+    while self.current < self.start + prefix {
+      self.advance();
+    }
+
+    loop {
+      match self.peek() {
+        Some('"') if self.peek_next() == Some('"') && self.peek_at(2) == Some('"') => {
+          break;
+        }
+        Some('\\') if !raw => {
+          self.advance();
+          if self.peek() == Some('\n') {
+            self.line += 1;
+          }
+          if !self.is_at_end() {
+            self.advance();
+          }
+        }
+        Some('\n') => {
+          self.line += 1;
+          self.advance();
+        }
+        Some(_) => {
+          self.advance();
+        }
+        None => {
+          return Err(SaturdayResult::error(self.line, "Unterminated string."));
+        }
+      }
+    }
+
+    let value: String = self.source[self.start + prefix..self.current]
+      .iter()
+      .collect();
+    for _ in 0..3 {
+      self.advance();
+    }
+
+    let value = Scanner::trim_indent(&value);
+    let value = if raw {
+      value
+    } else {
+      Scanner::unescape(&value)
+    };
     self.add_token_object(TokenType::String, Some(Object::Str(value)));
     Ok(())
   }
 
+  fn trim_indent(text: &str) -> String {
+    let is_blank = |line: &str| line.chars().all(|ch| matches!(ch, ' ' | '\t' | '\r'));
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() > 1 && is_blank(lines[0]) {
+      lines.remove(0);
+    }
+    if lines.len() > 1 && is_blank(lines[lines.len() - 1]) {
+      lines.pop();
+    }
+
+    let indent = lines
+      .iter()
+      .filter(|line| !is_blank(line))
+      .map(|line| {
+        line
+          .chars()
+          .take_while(|ch| matches!(ch, ' ' | '\t'))
+          .count()
+      })
+      .min()
+      .unwrap_or(0);
+
+    lines
+      .iter()
+      .map(|line| {
+        if is_blank(line) {
+          String::new()
+        } else {
+          line.chars().skip(indent).collect()
+        }
+      })
+      .collect::<Vec<String>>()
+      .join("\n")
+  }
+
+  /// 处理转义序列，不认识的转义原样保留反斜杠，和加入转义之前的字符串保持一致
+  fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+      if ch != '\\' {
+        result.push(ch);
+        continue;
+      }
+
+      match chars.next() {
+        Some('n') => result.push('\n'),
+        Some('t') => result.push('\t'),
+        Some('r') => result.push('\r'),
+        Some('0') => result.push('\0'),
+        Some('\\') => result.push('\\'),
+        Some('"') => result.push('"'),
+        // 行尾的反斜杠表示续行
+        Some('\n') => {}
+        Some(other) => {
+          result.push('\\');
+          result.push(other);
+        }
+        None => result.push('\\'),
+      }
+    }
+
+    result
+  }
+
   fn advance(&mut self) -> char {
     let result = *self.source.get(self.current).unwrap();
     self.current += 1;
@@ -299,7 +533,100 @@ impl Scanner {
       "while" => Some(TokenType::While),
       "def" => Some(TokenType::Def),
       "break" => Some(TokenType::Break),
+      "in" => Some(TokenType::In),
+      "yield" => Some(TokenType::Yield),
+      "match" => Some(TokenType::Match),
+      "enum" => Some(TokenType::Enum),
+      "assert" => Some(TokenType::Assert),
+      "record" => Some(TokenType::Record),
       _ => None,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn scan(source: &str) -> Vec<Token> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens().ok().unwrap().clone()
+  }
+
+  fn string_literal(token: &Token) -> String {
+    match &token.literal {
+      Some(Object::Str(s)) => s.clone(),
+      _ => panic!("expect string literal"),
+    }
+  }
+
+  #[test]
+  fn string_handles_escape_sequences() {
+    let tokens = scan(r#""a\tb\n\"c\"\\""#);
+    assert_eq!(string_literal(&tokens[0]), "a\tb\n\"c\"\\");
+  }
+
+  #[test]
+  fn string_keeps_unknown_escapes() {
+    let tokens = scan(r#""\d+\.\n""#);
+    assert_eq!(string_literal(&tokens[0]), "\\d+\\.\n");
+  }
+
+  #[test]
+  fn raw_string_skips_escape_sequences() {
+    let tokens = scan(r#"r"\d+\.\n""#);
+    assert!(tokens[0].is(TokenType::String));
+    assert_eq!(string_literal(&tokens[0]), r"\d+\.\n");
+  }
+
+  #[test]
+  fn multi_line_string_strips_common_indentation() {
+    let tokens = scan(
+      "def s = \"\"\"\n    select *\n      from t\n\n    where a = \"x\"\n    \"\"\";\nprint s;",
+    );
+    assert_eq!(
+      string_literal(&tokens[3]),
+      "select *\n  from t\n\nwhere a = \"x\""
+    );
+    assert!(tokens[4].is(TokenType::SemiColon));
+    assert_eq!(tokens[4].line, 6);
+    assert_eq!(tokens[6].line, 7);
+  }
+
+  #[test]
+  fn raw_multi_line_string_keeps_backslashes() {
+    let tokens = scan("r\"\"\"\n  a\\nb\n  \"\"\"");
+    assert_eq!(string_literal(&tokens[0]), "a\\nb");
+  }
+
+  #[test]
+  fn identifier_accepts_unicode() {
+    let tokens = scan("def 结果_1 = 1;");
+    assert!(tokens[1].is(TokenType::Identifier));
+    assert_eq!(&*tokens[1].lexeme(), "结果_1");
+    assert!(tokens[2].is(TokenType::Assign));
+  }
+
+  #[test]
+  fn identifier_continues_with_combining_mark() {
+    let tokens = scan("cafe\u{301}");
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(&*tokens[0].lexeme(), "cafe\u{301}");
+  }
+
+  #[test]
+  fn identifier_is_normalized_with_nfc() {
+    let mut scanner = Scanner::new("cafe\u{301} caf\u{e9} \u{1100}\u{1161}\u{11a8}".to_string());
+    scanner.set_nfc(true);
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    assert_eq!(&*tokens[0].lexeme(), "caf\u{e9}");
+    assert_eq!(&*tokens[1].lexeme(), "caf\u{e9}");
+    assert_eq!(&*tokens[2].lexeme(), "\u{ac01}");
+  }
+
+  #[test]
+  fn unterminated_multi_line_string_is_error() {
+    let mut scanner = Scanner::new("\"\"\"abc\n".to_string());
+    assert!(scanner.scan_tokens().is_err());
+  }
+}
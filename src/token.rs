@@ -1,23 +1,31 @@
 use super::token_type::*;
+use crate::interner::{intern, Symbol};
 use crate::object::Object;
 use std::fmt;
 use std::fmt::Formatter;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Token {
   pub t_type: TokenType,
-  pub lexeme: String,
   pub literal: Option<Object>,
   pub line: usize,
+  /// 驻留后的lexeme，变量按它查找；token不另外保存lexeme，需要文本时从符号取回
+  pub symbol: Symbol,
+  /// 在源码中的字符位置 [start, end)，用于取回表达式的源码文本
+  pub start: usize,
+  pub end: usize,
 }
 
 impl Token {
   pub fn new(t_type: TokenType, lexeme: String, literal: Option<Object>, line: usize) -> Token {
     Token {
       t_type,
-      lexeme,
+      symbol: intern(&lexeme),
       literal,
       line,
+      start: 0,
+      end: 0,
     }
   }
 
@@ -29,16 +37,22 @@ impl Token {
     self.t_type
   }
 
+  pub fn lexeme(&self) -> Rc<str> {
+    self.symbol.as_str()
+  }
+
   pub fn as_string(&self) -> String {
-    self.lexeme.clone()
+    self.lexeme().to_string()
   }
 
   pub fn dup(&self) -> Self {
     Token {
       t_type: self.t_type,
-      lexeme: self.lexeme.to_string(),
       literal: self.literal.clone(),
       line: self.line,
+      symbol: self.symbol,
+      start: self.start,
+      end: self.end,
     }
   }
 
@@ -47,9 +61,11 @@ impl Token {
   pub fn eof(line: usize) -> Token {
     Token {
       t_type: TokenType::Eof,
-      lexeme: "".to_string(),
       literal: None,
       line,
+      symbol: intern(""),
+      start: 0,
+      end: 0,
     }
   }
 }
@@ -60,7 +76,7 @@ impl fmt::Display for Token {
       f,
       "{:?} {} {}",
       self.t_type,
-      self.lexeme,
+      self.symbol,
       if let Some(literal) = &self.literal {
         literal.to_string()
       } else {
@@ -41,4 +41,25 @@ pub enum TokenType {
   While,
   Eof,
   Break,
+  QuestionQuestion, // ??
+  QuestionDot,      // ?.
+  Ampersand,        // &
+  Pipe,             // |
+  Caret,            // ^
+  Tilde,            // ~
+  LessLess,         // <<
+  GreaterGreater,   // >>
+  PipeGreater,      // |>
+  In,
+  Yield,
+  Match,
+  FatArrow, // =>
+  Enum,
+  Colon, // :
+  Arrow, // ->
+  Assert,
+  LeftBracket,  // [
+  RightBracket, // ]
+  Ellipsis,     // ...
+  Record,
 }
@@ -0,0 +1,66 @@
+use crate::token::Token;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// 静态检查使用的类型，Any表示没有标注、按动态类型处理
+#[derive(Clone, PartialEq)]
+pub enum Type {
+  Any,
+  Num,
+  Str,
+  Bool,
+  Nil,
+  /// 已知签名的函数，None表示只知道它是函数
+  Fun(Option<Rc<Signature>>),
+}
+
+#[derive(PartialEq)]
+pub struct Signature {
+  pub params: Vec<Type>,
+  pub ret: Type,
+}
+
+impl Type {
+  /// 标注中使用的类型名
+  pub fn from_name(name: &str) -> Option<Type> {
+    match name {
+      "any" => Some(Type::Any),
+      "num" => Some(Type::Num),
+      "str" => Some(Type::Str),
+      "bool" => Some(Type::Bool),
+      "nil" => Some(Type::Nil),
+      "fun" => Some(Type::Fun(None)),
+      _ => None,
+    }
+  }
+
+  /// 类型为self的位置能否接受一个actual类型的值
+  pub fn accepts(&self, actual: &Type) -> bool {
+    match (self, actual) {
+      (Type::Any, _) | (_, Type::Any) => true,
+      (Type::Fun(_), Type::Fun(_)) => true,
+      (expected, actual) => expected == actual,
+    }
+  }
+}
+
+impl Display for Type {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Type::Any => write!(f, "any"),
+      Type::Num => write!(f, "num"),
+      Type::Str => write!(f, "str"),
+      Type::Bool => write!(f, "bool"),
+      Type::Nil => write!(f, "nil"),
+      Type::Fun(_) => write!(f, "fun"),
+    }
+  }
+}
+
+/// 源码中的类型标注，token用于报告位置
+#[derive(Clone)]
+pub struct TypeAnnotation {
+  pub token: Token,
+  pub ty: Type,
+}
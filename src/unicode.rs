@@ -0,0 +1,162 @@
+use crate::unicode_tables::*;
+use std::cmp::Ordering;
+
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+fn in_ranges(ch: char, ranges: &[(char, char)]) -> bool {
+  ranges
+    .binary_search_by(|&(lo, hi)| {
+      if hi < ch {
+        Ordering::Less
+      } else if lo > ch {
+        Ordering::Greater
+      } else {
+        Ordering::Equal
+      }
+    })
+    .is_ok()
+}
+
+/// 标识符首字符（XID_Start）
+pub fn is_xid_start(ch: char) -> bool {
+  if ch.is_ascii() {
+    ch.is_ascii_alphabetic()
+  } else {
+    in_ranges(ch, XID_START)
+  }
+}
+
+/// 标识符后续字符（XID_Continue）
+pub fn is_xid_continue(ch: char) -> bool {
+  if ch.is_ascii() {
+    ch.is_ascii_alphanumeric() || ch == '_'
+  } else {
+    in_ranges(ch, XID_CONTINUE)
+  }
+}
+
+fn combining_class(ch: char) -> u8 {
+  if ch.is_ascii() {
+    return 0;
+  }
+
+  CANONICAL_COMBINING_CLASS
+    .binary_search_by(|&(lo, hi, _)| {
+      if hi < ch {
+        Ordering::Less
+      } else if lo > ch {
+        Ordering::Greater
+      } else {
+        Ordering::Equal
+      }
+    })
+    .map(|i| CANONICAL_COMBINING_CLASS[i].2)
+    .unwrap_or(0)
+}
+
+fn decompose(ch: char, out: &mut Vec<char>) {
+  let code = ch as u32;
+  if (S_BASE..S_BASE + S_COUNT).contains(&code) {
+    let s_index = code - S_BASE;
+    out.push(char::from_u32(L_BASE + s_index / N_COUNT).unwrap());
+    out.push(char::from_u32(V_BASE + (s_index % N_COUNT) / T_COUNT).unwrap());
+    if !s_index.is_multiple_of(T_COUNT) {
+      out.push(char::from_u32(T_BASE + s_index % T_COUNT).unwrap());
+    }
+    return;
+  }
+
+  match CANONICAL_DECOMPOSITION.binary_search_by(|&(c, _, _)| c.cmp(&ch)) {
+    Ok(i) => {
+      let (_, first, second) = CANONICAL_DECOMPOSITION[i];
+      decompose(first, out);
+      if let Some(second) = second {
+        decompose(second, out);
+      }
+    }
+    Err(_) => out.push(ch),
+  }
+}
+
+fn compose_pair(first: char, second: char) -> Option<char> {
+  let (a, b) = (first as u32, second as u32);
+  if (L_BASE..L_BASE + L_COUNT).contains(&a) && (V_BASE..V_BASE + V_COUNT).contains(&b) {
+    let l_index = a - L_BASE;
+    let v_index = b - V_BASE;
+    return char::from_u32(S_BASE + (l_index * V_COUNT + v_index) * T_COUNT);
+  }
+
+  if (S_BASE..S_BASE + S_COUNT).contains(&a)
+    && (a - S_BASE).is_multiple_of(T_COUNT)
+    && (T_BASE + 1..T_BASE + T_COUNT).contains(&b)
+  {
+    return char::from_u32(a + b - T_BASE);
+  }
+
+  CANONICAL_COMPOSITION
+    .binary_search_by(|&(x, y, _)| (x, y).cmp(&(first, second)))
+    .ok()
+    .map(|i| CANONICAL_COMPOSITION[i].2)
+}
+
+/// # NFC规范化
+/// ```
+/// 先完全分解，按组合类重排组合标记，再进行规范组合，
+/// 使得视觉上相同的标识符（如 é 与 e + ◌́）得到同一个名字
+/// ```
+pub fn nfc(text: &str) -> String {
+  if text.is_ascii() {
+    return text.to_string();
+  }
+
+  let mut chars = Vec::with_capacity(text.len());
+  for ch in text.chars() {
+    decompose(ch, &mut chars);
+  }
+
+  // 规范排序：相邻的非零组合类字符按组合类稳定排序
+  let mut i = 0;
+  while i < chars.len() {
+    if combining_class(chars[i]) == 0 {
+      i += 1;
+      continue;
+    }
+    let start = i;
+    while i < chars.len() && combining_class(chars[i]) != 0 {
+      i += 1;
+    }
+    chars[start..i].sort_by_key(|&ch| combining_class(ch));
+  }
+
+  let mut result: Vec<char> = Vec::with_capacity(chars.len());
+  let mut starter: Option<usize> = None;
+  let mut last_class = 0;
+  for ch in chars {
+    let class = combining_class(ch);
+    if let Some(s) = starter {
+      let blocked = result.len() > s + 1 && (last_class == 0 || last_class >= class);
+      if !blocked {
+        if let Some(composed) = compose_pair(result[s], ch) {
+          result[s] = composed;
+          continue;
+        }
+      }
+    }
+
+    if class == 0 {
+      starter = Some(result.len());
+    }
+    last_class = class;
+    result.push(ch);
+  }
+
+  result.into_iter().collect()
+}
@@ -0,0 +1,658 @@
+use crate::callable::{Callable, SaturdayCallable};
+use crate::chunk::{Function, Op};
+use crate::error::SaturdayResult;
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::saturday_enum::SaturdayEnum;
+use crate::saturday_record::RecordConstructor;
+use crate::token::Token;
+use crate::token_type::TokenType;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// 闭包捕获的变量：还在栈上时记录栈槽，所在的函数返回后保存值本身
+pub enum Upvalue {
+  Open(usize),
+  Closed(Object),
+}
+
+/// 编译后的函数和它捕获的变量，作为Object::Func在VM中传递
+pub struct Closure {
+  pub function: Rc<Function>,
+  pub upvalues: Rc<Vec<Rc<RefCell<Upvalue>>>>,
+}
+
+impl SaturdayCallable for Closure {
+  /// 解释器中调用闭包（比如REPL中退回解释器的行调用了VM定义的函数）时，在新的VM栈上执行它
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    _paren: &Token,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let mut stack = vec![Object::Nil];
+    stack.extend(arguments);
+    execute(
+      interpreter,
+      self.function.clone(),
+      self.upvalues.clone(),
+      stack,
+      true,
+    )
+  }
+
+  fn arity(&self) -> usize {
+    self.function.arity
+  }
+
+  fn to_string(&self) -> String {
+    self.function.name.clone()
+  }
+
+  fn as_closure(&self) -> Option<&Closure> {
+    Some(self)
+  }
+}
+
+struct CallFrame {
+  function: Rc<Function>,
+  upvalues: Rc<Vec<Rc<RefCell<Upvalue>>>>,
+  ip: usize,
+  /// 0号槽在栈上的位置
+  base: usize,
+}
+
+/// # 栈式虚拟机
+/// ```
+/// 执行Compiler生成的字节码。全局变量按名字存放在Interpreter的全局环境中，
+/// 这样REPL中前面定义的变量后面还能使用，退回解释器执行的行和VM执行的行也能互相看到对方的定义。
+/// 字节码没有语句的边界，执行步数的预算在每次调用和每轮循环时消耗，中断也在这两处检查。
+/// 运算、属性访问和内置函数复用Interpreter的实现，保证两者的行为和报错一致
+/// ```
+pub fn run(interpreter: &Interpreter, script: Rc<Function>) -> Result<(), SaturdayResult> {
+  execute(
+    interpreter,
+    script,
+    Rc::new(Vec::new()),
+    vec![Object::Nil],
+    false,
+  )
+  .map(|_| ())
+}
+
+/// 从function开始执行，stack中是0号槽和参数，named表示trace中是否列出最外层的函数名
+fn execute(
+  interpreter: &Interpreter,
+  function: Rc<Function>,
+  upvalues: Rc<Vec<Rc<RefCell<Upvalue>>>>,
+  stack: Vec<Object>,
+  named: bool,
+) -> Result<Object, SaturdayResult> {
+  let mut stack = stack;
+  let mut open_upvalues: Vec<Rc<RefCell<Upvalue>>> = Vec::new();
+  let mut frames: Vec<CallFrame> = Vec::new();
+
+  let mut function = function;
+  let mut upvalues = upvalues;
+  let mut ip = 0;
+  let mut base = 0;
+
+  let mut dispatch = || -> Result<Object, SaturdayResult> {
+    loop {
+      let op = function.chunk.code[ip];
+      ip += 1;
+
+      match op {
+        Op::Constant(index) => stack.push(function.chunk.constants[index as usize].clone()),
+        Op::Nil => stack.push(Object::Nil),
+        Op::True => stack.push(Object::Bool(true)),
+        Op::False => stack.push(Object::Bool(false)),
+        Op::Pop => {
+          stack.pop();
+        }
+        Op::GetLocal(slot) => stack.push(stack[base + slot as usize].clone()),
+        Op::SetLocal(slot) => stack[base + slot as usize] = stack.last().unwrap().clone(),
+        Op::GetGlobal(name) => {
+          let name = &function.chunk.tokens[name as usize];
+          stack.push(interpreter.globals.borrow().get(name)?);
+        }
+        Op::DefineGlobal(name) => {
+          let name = &function.chunk.tokens[name as usize];
+          let value = stack.pop().unwrap();
+          interpreter.globals.borrow_mut().define(name.symbol, value);
+        }
+        Op::SetGlobal(name) => {
+          let name = &function.chunk.tokens[name as usize];
+          let value = stack.last().unwrap().clone();
+          interpreter.globals.borrow_mut().assign(name, value)?;
+        }
+        Op::GetUpvalue(index) => {
+          let value = match &*upvalues[index as usize].borrow() {
+            Upvalue::Open(slot) => stack[*slot].clone(),
+            Upvalue::Closed(value) => value.clone(),
+          };
+          stack.push(value);
+        }
+        Op::SetUpvalue(index) => {
+          let value = stack.last().unwrap().clone();
+          match &mut *upvalues[index as usize].borrow_mut() {
+            Upvalue::Open(slot) => stack[*slot] = value,
+            Upvalue::Closed(closed) => *closed = value,
+          }
+        }
+        Op::Get(name) => {
+          let object = stack.pop().unwrap();
+          let name = &function.chunk.tokens[name as usize];
+          stack.push(interpreter.get_property(object, name)?);
+        }
+        Op::Binary(operator) => {
+          let right = stack.pop().unwrap();
+          let left = stack.pop().unwrap();
+          let operator = &function.chunk.tokens[operator as usize];
+          let result = match (&left, &right) {
+            (Object::Num(l), Object::Num(r)) => match operator.token_type() {
+              TokenType::Plus => Some(Object::Num(l + r)),
+              TokenType::Minus => Some(Object::Num(l - r)),
+              TokenType::Star => Some(Object::Num(l * r)),
+              TokenType::Slash => Some(Object::Num(l / r)),
+              TokenType::Less => Some(Object::Bool(l < r)),
+              TokenType::LessEqual => Some(Object::Bool(l <= r)),
+              TokenType::Greater => Some(Object::Bool(l > r)),
+              TokenType::GreaterEqual => Some(Object::Bool(l >= r)),
+              _ => None,
+            },
+            _ => None,
+          };
+          let result = match result {
+            Some(result) => result,
+            None => interpreter.binary(operator, left, right)?,
+          };
+          stack.push(result);
+        }
+        Op::Unary(operator) => {
+          let right = stack.pop().unwrap();
+          let operator = &function.chunk.tokens[operator as usize];
+          stack.push(interpreter.unary(operator, right)?);
+        }
+        Op::Jump(offset) => ip += offset as usize,
+        Op::JumpIfFalse(offset) => {
+          if !interpreter.is_truthy(stack.last().unwrap()) {
+            ip += offset as usize;
+          }
+        }
+        Op::JumpIfNotNil(offset) => {
+          if *stack.last().unwrap() != Object::Nil {
+            ip += offset as usize;
+          }
+        }
+        Op::JumpIfNil(offset) => {
+          if *stack.last().unwrap() == Object::Nil {
+            ip += offset as usize;
+          }
+        }
+        Op::Loop(offset) => {
+          interpreter.poll_interrupt()?;
+          ip -= offset as usize;
+        }
+        Op::Call(count, paren) | Op::TailCall(count, paren) => {
+          interpreter.consume_fuel()?;
+          interpreter.poll_interrupt()?;
+          let count = count as usize;
+          let callee_slot = stack.len() - count - 1;
+          let paren = &function.chunk.tokens[paren as usize];
+          interpreter.reserve_memory(paren, 0)?;
+          let callable = interpreter.check_call(paren, stack[callee_slot].clone(), count)?;
+
+          if let Some(closure) = callable.func.as_closure() {
+            if matches!(op, Op::TailCall(..)) {
+              // 当前帧的局部变量不再需要：关闭它们的upvalue，把callee和参数移到帧底
+              close_upvalues(&mut open_upvalues, &stack, base);
+              stack.drain(base..callee_slot);
+              function = closure.function.clone();
+              upvalues = closure.upvalues.clone();
+              ip = 0;
+              continue;
+            }
+            if frames.len() >= interpreter.max_call_depth() {
+              return Err(SaturdayResult::runtime_error(paren, "Stack overflow."));
+            }
+
+            let caller = CallFrame {
+              function: std::mem::replace(&mut function, closure.function.clone()),
+              upvalues: std::mem::replace(&mut upvalues, closure.upvalues.clone()),
+              ip,
+              base,
+            };
+            frames.push(caller);
+            ip = 0;
+            base = callee_slot;
+          } else {
+            let arguments = stack.split_off(callee_slot + 1);
+            stack.pop();
+            stack.push(callable.func.call(interpreter, paren, arguments)?);
+          }
+        }
+        Op::Closure(index) => {
+          let target = function.chunk.functions[index as usize].clone();
+          let captured = target
+            .upvalues
+            .iter()
+            .map(|desc| {
+              if desc.is_local {
+                capture_upvalue(&mut open_upvalues, base + desc.index as usize)
+              } else {
+                upvalues[desc.index as usize].clone()
+              }
+            })
+            .collect();
+          let closure = Closure {
+            function: target,
+            upvalues: Rc::new(captured),
+          };
+          let func: Rc<dyn SaturdayCallable> = Rc::new(closure);
+          interpreter.gc.track(&func);
+          stack.push(Object::Func(Callable { func }));
+        }
+        Op::CloseUpvalue => {
+          close_upvalues(&mut open_upvalues, &stack, stack.len() - 1);
+          stack.pop();
+        }
+        Op::Return => {
+          let result = stack.pop().unwrap();
+          close_upvalues(&mut open_upvalues, &stack, base);
+          stack.truncate(base);
+
+          let Some(caller) = frames.pop() else {
+            return Ok(result);
+          };
+          function = caller.function;
+          upvalues = caller.upvalues;
+          ip = caller.ip;
+          base = caller.base;
+          stack.push(result);
+        }
+        Op::Print => println!("{}", stack.pop().unwrap()),
+        Op::Fuel => interpreter.consume_fuel()?,
+        Op::Enum(index) => {
+          let stmt = &function.chunk.enums[index as usize];
+          let value = SaturdayEnum::new(&stmt.name, &stmt.variants);
+          stack.push(Object::Enum(Rc::new(value)));
+        }
+        Op::Record(index) => {
+          let stmt = &function.chunk.records[index as usize];
+          let constructor = RecordConstructor::new(&stmt.name, &stmt.fields);
+          stack.push(Object::Func(Callable {
+            func: Rc::new(constructor),
+          }));
+        }
+        Op::List(count) => {
+          let elements = stack.split_off(stack.len() - count as usize);
+          stack.push(Object::List(Rc::new(elements)));
+        }
+        Op::MatchArm(index, offset) => {
+          let (stmt, arm) = &function.chunk.arms[index as usize];
+          let pattern = &stmt.arms[*arm].pattern;
+          let types = stack.split_off(stack.len() - pattern.types().len());
+          let mut bindings = Vec::new();
+          if pattern.matches(stack.last().unwrap(), &types, &mut bindings)? {
+            stack.extend(bindings.into_iter().map(|(_, value)| value));
+          } else {
+            ip += offset as usize;
+          }
+        }
+        Op::AssertFail(index) => {
+          let (stmt, binary) = &function.chunk.asserts[index as usize];
+          let extra = stmt.message.as_ref().map(|_| stack.pop().unwrap());
+          let operands = binary.then(|| {
+            let right = stack.pop().unwrap();
+            let left = stack.pop().unwrap();
+            (left, right)
+          });
+          return Err(interpreter.assert_failure(stmt, operands, extra));
+        }
+      }
+    }
+  };
+
+  // 出错时按VM的调用帧生成trace，0号帧是顶层脚本或者从解释器调用的闭包
+  dispatch().map_err(|err| {
+    let name = (named || !frames.is_empty()).then(|| function.name.clone());
+    let err = err.unwind(name, 0);
+    frames
+      .iter()
+      .enumerate()
+      .rev()
+      .fold(err, |err, (i, frame)| {
+        let name = (named || i > 0).then(|| frame.function.name.clone());
+        err.unwind(name, frame.function.chunk.lines[frame.ip - 1])
+      })
+  })
+}
+
+/// 捕获栈槽slot，同一个槽的多个闭包共享同一个upvalue
+fn capture_upvalue(open: &mut Vec<Rc<RefCell<Upvalue>>>, slot: usize) -> Rc<RefCell<Upvalue>> {
+  if let Some(upvalue) = open
+    .iter()
+    .find(|upvalue| matches!(&*upvalue.borrow(), Upvalue::Open(s) if *s == slot))
+  {
+    return upvalue.clone();
+  }
+
+  let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+  open.push(upvalue.clone());
+  upvalue
+}
+
+/// 栈槽from及以上的变量即将出栈，把捕获它们的upvalue改为保存值本身
+fn close_upvalues(open: &mut Vec<Rc<RefCell<Upvalue>>>, stack: &[Object], from: usize) {
+  open.retain(|upvalue| {
+    let slot = match &*upvalue.borrow() {
+      Upvalue::Open(slot) => *slot,
+      Upvalue::Closed(_) => return false,
+    };
+    if slot < from {
+      return true;
+    }
+
+    *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+    false
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::compiler::Compiler;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::scanner::Scanner;
+
+  fn run(source: &str) -> (Interpreter, Result<(), SaturdayResult>) {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+    let interpreter = Interpreter::new();
+    Resolver::new(&interpreter)
+      .resolve(&Rc::new(statements.clone()))
+      .ok();
+
+    let compiler = Compiler::new(false);
+    let script = compiler.compile(&statements).ok().unwrap();
+    let result = super::run(&interpreter, script);
+    (interpreter, result)
+  }
+
+  fn global(interpreter: &Interpreter, name: &str) -> Option<Object> {
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+    interpreter.globals.borrow().get(&token).ok()
+  }
+
+  #[test]
+  fn closures_share_captured_variables() {
+    let (terp, result) = run(
+      "fun counter() {
+         def n = 0;
+         fun inc() { n = n + 1; return n; }
+         fun get() { return n; }
+         inc();
+         inc();
+         return get;
+       }
+       def get = counter();
+       def n = get();",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "n"), Some(Object::Num(2.0)));
+  }
+
+  #[test]
+  fn loops_break_and_recursion() {
+    let (terp, result) = run(
+      "fun fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+       def total = 0;
+       def i = 0;
+       while true {
+         def step = i;
+         if step == 5 { break; }
+         total = total + fib(step);
+         i = i + 1;
+       }",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "total"), Some(Object::Num(7.0)));
+    assert_eq!(global(&terp, "i"), Some(Object::Num(5.0)));
+  }
+
+  #[test]
+  fn match_arms_bind_and_guard() {
+    let (terp, result) = run(
+      "enum Shape { Circle(r), Rect(w, h) }
+       fun area(s) {
+         def result = 0;
+         match s {
+           Shape.Rect(w, h) if w == h => { result = w * w; }
+           Shape.Rect(w, h) => { result = w * h; }
+           Shape.Circle(r) => { result = 3 * r * r; }
+         }
+         return result;
+       }
+       def square = area(Shape.Rect(3, 3));
+       def rect = area(Shape.Rect(2, 5));
+       def circle = area(Shape.Circle(2));
+       def label = nil ?? \"none\";",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "square"), Some(Object::Num(9.0)));
+    assert_eq!(global(&terp, "rect"), Some(Object::Num(10.0)));
+    assert_eq!(global(&terp, "circle"), Some(Object::Num(12.0)));
+    assert_eq!(
+      global(&terp, "label"),
+      Some(Object::Str("none".to_string()))
+    );
+  }
+
+  #[test]
+  fn variant_patterns_compare_enums_by_identity() {
+    let (terp, result) = run(
+      "enum Shape { Circle(r) }
+       def outer = Shape.Circle(1);
+       def matched;
+       {
+         enum Shape { Circle(r) }
+         match outer {
+           Shape.Circle(r) => { matched = \"inner\"; }
+           _ => { matched = \"other\"; }
+         }
+         match Shape.Circle(2) {
+           Shape.Circle(r) => { matched = matched + r; }
+         }
+       }",
+    );
+    assert!(result.is_ok());
+    assert_eq!(
+      global(&terp, "matched"),
+      Some(Object::Str("other2".to_string()))
+    );
+
+    let (_, result) = run("enum Shape { Circle(r) } match Shape.Circle(1) { Shape.Circle => {} }");
+    assert!(
+      matches!(result, Err(SaturdayResult::RuntimeError { message, .. })
+      if message == "Expected 1 fields but got 0.")
+    );
+  }
+
+  #[test]
+  fn list_and_record_patterns() {
+    let (terp, result) = run(
+      "record Point { x, y }
+       def matched = 0;
+       match [Point(1, 2), 3, 4] {
+         [Point { x: 0 }, ...rest] => { matched = -1; }
+         [Point { x, y: b }, ...rest] => { matched = x + b + rest.len(); }
+       }
+       def list = [matched, [nil]];",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "matched"), Some(Object::Num(5.0)));
+    assert_eq!(
+      global(&terp, "list").map(|list| list.to_string()),
+      Some("[5, [nil]]".to_string())
+    );
+  }
+
+  #[test]
+  fn tail_calls_reuse_frames() {
+    let (terp, result) = run(
+      "fun count(n) { if n == 0 { return 0; } return count(n - 1); }
+       fun is_even(n) { if n == 0 { return true; } return is_odd(n - 1); }
+       fun is_odd(n) { if n == 0 { return false; } return is_even(n - 1); }
+       fun keep(n) { def x = n; fun get() { return x; } if n == 0 { return get; } return keep(n - 1); }
+       def zero = count(100000);
+       def even = is_even(100001);
+       def kept = keep(3)();
+       def len = \"abc\".len();",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "zero"), Some(Object::Num(0.0)));
+    assert_eq!(global(&terp, "even"), Some(Object::Bool(false)));
+    assert_eq!(global(&terp, "kept"), Some(Object::Num(0.0)));
+    assert_eq!(global(&terp, "len"), Some(Object::Num(3.0)));
+  }
+
+  #[test]
+  fn optional_chains_short_circuit() {
+    let (terp, result) = run(
+      "def s = nil;
+       def calls = 0;
+       fun count() { calls = calls + 1; return calls; }
+       def len = s?.len();
+       def nested = s?.repeat(count()).len();
+       def some = \"ab\"?.repeat(count()).len();
+       fun wrap(x) { return x?.len(); }
+       def tail = wrap(nil);",
+    );
+    assert!(result.is_ok());
+    assert_eq!(global(&terp, "len"), Some(Object::Nil));
+    assert_eq!(global(&terp, "nested"), Some(Object::Nil));
+    assert_eq!(global(&terp, "calls"), Some(Object::Num(1.0)));
+    assert_eq!(global(&terp, "some"), Some(Object::Num(2.0)));
+    assert_eq!(global(&terp, "tail"), Some(Object::Nil));
+  }
+
+  #[test]
+  fn globals_are_shared_with_the_interpreter() {
+    let (terp, result) = run("def x = 41; fun inc(n) { return n + 1; }");
+    assert!(result.is_ok());
+
+    // REPL中不支持的行退回解释器执行，要能看到VM定义的变量、调用VM编译的函数
+    let mut scanner = Scanner::new(
+      "fun* gen() { yield inc(x); }
+       def y = 0;
+       for v in gen() { y = v; }"
+        .to_string(),
+    );
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    Resolver::new(&terp).resolve(&statements).ok();
+    assert!(terp.interpreter(&statements).is_ok());
+    assert_eq!(global(&terp, "y"), Some(Object::Num(42.0)));
+
+    // 之后VM执行的行也能看到解释器定义的变量
+    let mut scanner = Scanner::new("def z = inc(y);".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+    let script = Compiler::new(false).compile(&statements).ok().unwrap();
+    assert!(super::run(&terp, script).is_ok());
+    assert_eq!(global(&terp, "z"), Some(Object::Num(43.0)));
+  }
+
+  #[test]
+  fn fuel_is_spent_like_the_interpreter() {
+    // 每条语句和每次调用各计一步，两种后端在同一个位置用完预算
+    let sources = [
+      "def i = 0; while true { i = i + 1; }",
+      "def i = 0; fun f(n) { i = i + 1; if n > 0 { return f(n - 1); } return n; } f(100);",
+      "def i = 0; fun g(n) { i = i + 1; if n == 0 { return 0; } return 1 + g(n - 1); } g(100);",
+      "def i = 0; while i < 100 { match i { 0 => { i = i + 2; } _ => { i = i + 1; } } }",
+    ];
+    for source in sources {
+      for fuel in (1..300).step_by(7) {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().ok().unwrap();
+        let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+
+        let terp = Interpreter::new();
+        Resolver::new(&terp).resolve(&statements).ok();
+        terp.set_fuel(Some(fuel));
+        let expected = terp.interpreter(&statements);
+
+        let vm = Interpreter::new();
+        vm.set_fuel(Some(fuel));
+        let script = Compiler::new(false).compile(&statements).ok().unwrap();
+        let result = super::run(&vm, script);
+
+        assert_eq!(
+          matches!(expected, Err(SaturdayResult::OutOfFuel)),
+          matches!(result, Err(SaturdayResult::OutOfFuel)),
+          "{source} with fuel {fuel}"
+        );
+        assert_eq!(
+          global(&terp, "i"),
+          global(&vm, "i"),
+          "{source} with fuel {fuel}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn runtime_errors_match_the_interpreter() {
+    let (_, result) = run("print missing;");
+    assert!(matches!(result, Err(SaturdayResult::RuntimeError { .. })));
+    let (_, result) = run("fun f(a) { return a; } f(1, 2);");
+    assert!(
+      matches!(result, Err(SaturdayResult::RuntimeError { message, .. })
+      if message == "Expected 1 arguments but got 2.")
+    );
+    let (_, result) = run("fun down(n) { return 1 + down(n - 1); }\ndown(1);");
+    assert!(
+      matches!(result, Err(SaturdayResult::RuntimeError { token, message, trace })
+      if message == "Stack overflow." && token.line == 1 && trace.frames.len() == 10_001)
+    );
+    let (_, result) = run("def x = 1; assert x + 1 == 3;");
+    assert!(
+      matches!(result, Err(SaturdayResult::RuntimeError { message, .. })
+      if message == "assert failed: x + 1 == 3 (left: 2, right: 3) at line 1")
+    );
+  }
+
+  #[test]
+  fn unsupported_constructs_fail_instead_of_falling_back() {
+    for (source, expected) in [
+      (
+        "fun* gen() { yield 1; }",
+        "Generator functions are not supported by the VM yet.",
+      ),
+      (
+        "for x in [1, 2] { print x; }",
+        "For-in loops are not supported by the VM yet.",
+      ),
+    ] {
+      let mut scanner = Scanner::new(source.to_string());
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      let statements = Parser::new(tokens).parse().ok().unwrap();
+      let result = Compiler::new(false).compile(&statements);
+      assert!(
+        matches!(&result, Err(SaturdayResult::ParseError { message, .. }) if message == expected),
+        "{source}"
+      );
+    }
+
+    // 协程按解析到的内置函数判断，换个名字调用也一样报错
+    let (_, result) = run("def go = spawn; fun f() {} go(f);");
+    assert!(
+      matches!(result, Err(SaturdayResult::RuntimeError { message, .. })
+      if message == "spawn() is not supported by the VM yet.")
+    );
+  }
+}
@@ -0,0 +1,128 @@
+#!/usr/bin/env python3
+"""生成 src/unicode_tables.rs
+
+用法：在仓库根目录运行 python3 tools/gen_unicode_tables.py
+
+字符属性取自Python自带的unicodedata模块，它的Unicode版本随Python版本变化，
+所以这里固定版本号，版本不一致时拒绝生成。Python 3.11 对应 Unicode 14.0.0。
+"""
+
+import os
+import sys
+import unicodedata
+
+UNICODE_VERSION = "14.0.0"
+
+OUTPUT = os.path.join(os.path.dirname(__file__), "..", "src", "unicode_tables.rs")
+
+
+def code_points():
+    """所有标量值，代理区不是合法的char"""
+    for cp in range(0x110000):
+        if not 0xD800 <= cp <= 0xDFFF:
+            yield cp
+
+
+def ranges(pred):
+    """满足pred的字符合并成闭区间"""
+    out = []
+    start = None
+    for cp in range(0x110000):
+        ok = not 0xD800 <= cp <= 0xDFFF and pred(chr(cp))
+        if ok and start is None:
+            start = cp
+        if not ok and start is not None:
+            out.append((start, cp - 1))
+            start = None
+    if start is not None:
+        out.append((start, 0x10FFFF))
+    return out
+
+
+def combining_classes():
+    """非零的规范组合类，相邻且类别相同的字符合并成一个区间"""
+    out = []
+    cur = None
+    for cp in code_points():
+        ccc = unicodedata.combining(chr(cp))
+        if cur and ccc == cur[2] and cp == cur[1] + 1:
+            cur[1] = cp
+            continue
+        if cur:
+            out.append(tuple(cur))
+            cur = None
+        if ccc:
+            cur = [cp, cp, ccc]
+    if cur:
+        out.append(tuple(cur))
+    return out
+
+
+def decompositions():
+    """规范分解（不含<compat>等兼容分解）和可以组合回去的字符对"""
+    dec = []
+    comp = []
+    for cp in code_points():
+        d = unicodedata.decomposition(chr(cp))
+        if not d or d.startswith("<"):
+            continue
+        parts = [int(x, 16) for x in d.split()]
+        dec.append((cp, parts))
+        # 单字符分解、起始字符不是starter的分解和组合排除表中的字符都不参与组合，
+        # NFC保持不变说明它是组合的结果
+        if (
+            len(parts) == 2
+            and unicodedata.normalize("NFC", chr(cp)) == chr(cp)
+            and unicodedata.combining(chr(parts[0])) == 0
+        ):
+            comp.append((parts[0], parts[1], cp))
+    comp.sort()
+    return dec, comp
+
+
+def ch(cp):
+    return "'\\u{%X}'" % cp
+
+
+def main():
+    if unicodedata.unidata_version != UNICODE_VERSION:
+        sys.exit(
+            "unicodedata is Unicode %s, expected %s; run with a matching Python"
+            % (unicodedata.unidata_version, UNICODE_VERSION)
+        )
+
+    # Python标识符的首字符是XID_Start加上下划线，后续字符是XID_Continue
+    xid_start = ranges(lambda c: c.isidentifier() and c != "_")
+    xid_continue = ranges(lambda c: ("a" + c).isidentifier())
+    dec, comp = decompositions()
+
+    o = [
+        "//! Unicode %s 字符属性表（由 tools/gen_unicode_tables.py 生成，请勿手动修改）\n"
+        % UNICODE_VERSION
+    ]
+    o.append("pub const XID_START: &[(char, char)] = &[")
+    o += ["  (%s, %s)," % (ch(a), ch(b)) for a, b in xid_start]
+    o.append("];\n")
+    o.append("pub const XID_CONTINUE: &[(char, char)] = &[")
+    o += ["  (%s, %s)," % (ch(a), ch(b)) for a, b in xid_continue]
+    o.append("];\n")
+    o.append("pub const CANONICAL_COMBINING_CLASS: &[(char, char, u8)] = &[")
+    o += ["  (%s, %s, %d)," % (ch(a), ch(b), k) for a, b, k in combining_classes()]
+    o.append("];\n")
+    o.append("pub const CANONICAL_DECOMPOSITION: &[(char, char, Option<char>)] = &[")
+    o += [
+        "  (%s, %s, %s),"
+        % (ch(c), ch(p[0]), ("Some(%s)" % ch(p[1])) if len(p) > 1 else "None")
+        for c, p in dec
+    ]
+    o.append("];\n")
+    o.append("pub const CANONICAL_COMPOSITION: &[(char, char, char)] = &[")
+    o += ["  (%s, %s, %s)," % (ch(a), ch(b), ch(c)) for a, b, c in comp]
+    o.append("];")
+
+    with open(OUTPUT, "w", encoding="utf-8") as f:
+        f.write("\n".join(o) + "\n")
+
+
+if __name__ == "__main__":
+    main()